@@ -0,0 +1,455 @@
+//! Card module for rendering sessions as shareable images
+//!
+//! A card shows a header with the project and date, followed by the prompts
+//! of a session in rounded boxes. It is drawn as SVG and rasterized to PNG
+//! in pure Rust, so no browser is needed. DejaVu Sans is embedded in the
+//! binary; characters it lacks, such as CJK, fall back to the fonts
+//! installed on the system.
+
+use crate::exporter::{display_time, escape_html};
+use crate::session::{format_duration, Session};
+use crate::timefmt::TimeFormat;
+use resvg::tiny_skia::{Pixmap, Transform};
+use resvg::usvg::{fontdb, Options, Tree};
+use std::fmt::Write;
+use std::sync::Arc;
+use unicode_linebreak::linebreaks;
+
+/// The embedded font, used for layout and rendering
+const FONT: &[u8] = include_bytes!("../assets/fonts/DejaVuSans.ttf");
+/// Family name of the embedded font
+const FONT_FAMILY: &str = "DejaVu Sans";
+/// Fonts tried by SVG viewers after the embedded one
+const FALLBACK_FAMILIES: &str =
+    "'PingFang SC', 'Hiragino Sans GB', 'Microsoft YaHei', 'Noto Sans CJK SC', sans-serif";
+
+/// Width of a card
+const WIDTH: f32 = 800.0;
+/// Space around the content
+const PADDING: f32 = 40.0;
+/// Space between the border of a prompt box and its text
+const BOX_PADDING: f32 = 14.0;
+/// Font size of the project name
+const TITLE_SIZE: f32 = 24.0;
+/// Font size of prompts
+const TEXT_SIZE: f32 = 15.0;
+/// Font size of dates and offsets
+const META_SIZE: f32 = 13.0;
+/// Line height relative to the font size
+const LINE_HEIGHT: f32 = 1.5;
+/// Prompts longer than this many lines are cut off
+const MAX_PROMPT_LINES: usize = 16;
+/// Pixel density of PNG output
+const PNG_SCALE: f32 = 2.0;
+
+// Colors, matching the light theme of HTML exports
+const BACKGROUND: &str = "#ffffff";
+const FOREGROUND: &str = "#1f2328";
+const MUTED: &str = "#656d76";
+const BORDER: &str = "#d0d7de";
+const CARD: &str = "#f6f8fa";
+const ACCENT: &str = "#cc785c";
+
+/// Renders sessions as SVG and PNG cards
+#[derive(Debug, Clone)]
+pub struct CardRenderer {
+    /// Fonts available to the rasterizer
+    fontdb: Arc<fontdb::Database>,
+    /// Pattern and time zone of the dates shown
+    time: TimeFormat,
+}
+
+impl CardRenderer {
+    /// Create a renderer with the embedded font and the system fonts
+    pub fn new() -> Self {
+        let mut fontdb = fontdb::Database::new();
+        fontdb.load_system_fonts();
+        Self::with_fontdb(fontdb)
+    }
+
+    /// Create a renderer with the embedded font and the given fonts
+    pub fn with_fontdb(mut fontdb: fontdb::Database) -> Self {
+        fontdb.load_font_data(FONT.to_vec());
+        fontdb.set_sans_serif_family(FONT_FAMILY);
+        Self {
+            fontdb: Arc::new(fontdb),
+            time: TimeFormat::default(),
+        }
+    }
+
+    /// Show dates with the pattern and in the zone of a time format
+    pub fn with_time_format(mut self, time: TimeFormat) -> Self {
+        self.time = time.with_relative(false);
+        self
+    }
+
+    /// Draw a session as an SVG document
+    pub fn to_svg(&self, session: &Session) -> String {
+        let face = embedded_face();
+        let content_width = WIDTH - 2.0 * PADDING;
+        let text_width = content_width - 2.0 * BOX_PADDING;
+        let mut body = String::new();
+        let mut y = PADDING;
+
+        // Header: project, then date, duration and prompt count
+        for line in wrap(&face, session.project(), TITLE_SIZE, content_width) {
+            y += TITLE_SIZE * 1.25;
+            text(&mut body, PADDING, y, TITLE_SIZE, FOREGROUND, "bold", &line);
+        }
+        y += META_SIZE * LINE_HEIGHT + 4.0;
+        let summary = format!(
+            "{} · {} · {} prompts",
+            display_time(&self.time, session.start()),
+            format_duration(session.duration()),
+            session.prompt_count()
+        );
+        text(&mut body, PADDING, y, META_SIZE, MUTED, "normal", &summary);
+        y += 16.0;
+        let _ = writeln!(
+            body,
+            r#"<line x1="{PADDING}" y1="{y}" x2="{}" y2="{y}" stroke="{ACCENT}" stroke-width="2"/>"#,
+            WIDTH - PADDING
+        );
+        y += 8.0;
+
+        for (offset, record) in session.timeline() {
+            y += 16.0 + META_SIZE;
+            let meta = format!(
+                "+{} · {}",
+                format_duration(offset),
+                display_time(&self.time, record.timestamp)
+            );
+            text(&mut body, PADDING, y, META_SIZE, ACCENT, "bold", &meta);
+
+            let mut lines = wrap(&face, &record.display, TEXT_SIZE, text_width);
+            if lines.len() > MAX_PROMPT_LINES {
+                let hidden = lines.len() - MAX_PROMPT_LINES + 1;
+                lines.truncate(MAX_PROMPT_LINES - 1);
+                lines.push(format!("… {hidden} more lines"));
+            }
+
+            let line_height = TEXT_SIZE * LINE_HEIGHT;
+            let box_height = lines.len() as f32 * line_height + 2.0 * BOX_PADDING;
+            y += 8.0;
+            let _ = writeln!(
+                body,
+                r#"<rect x="{PADDING}" y="{y}" width="{content_width}" height="{box_height}" rx="8" fill="{CARD}" stroke="{BORDER}"/>"#
+            );
+            let mut line_y = y + BOX_PADDING;
+            for line in &lines {
+                line_y += line_height;
+                // Baseline a little above the bottom of the line box
+                let baseline = line_y - (line_height - TEXT_SIZE) / 2.0 - 3.0;
+                text(
+                    &mut body,
+                    PADDING + BOX_PADDING,
+                    baseline,
+                    TEXT_SIZE,
+                    FOREGROUND,
+                    "normal",
+                    line,
+                );
+            }
+            y += box_height;
+
+            // Pasted contents are too long for a card, only their labels are shown
+            for pasted in &record.pasted {
+                y += 6.0 + META_SIZE;
+                text(
+                    &mut body,
+                    PADDING,
+                    y,
+                    META_SIZE,
+                    MUTED,
+                    "normal",
+                    &pasted.label(),
+                );
+            }
+        }
+
+        y += 24.0 + META_SIZE;
+        let footer = format!("claude-memo · {}", session.session_id);
+        text(&mut body, PADDING, y, META_SIZE, MUTED, "normal", &footer);
+        let height = (y + PADDING).ceil();
+
+        format!(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{WIDTH}" height="{height}" viewBox="0 0 {WIDTH} {height}" font-family="{FONT_FAMILY}, {FALLBACK_FAMILIES}">
+<rect width="{WIDTH}" height="{height}" fill="{BACKGROUND}"/>
+{body}</svg>
+"#
+        )
+    }
+
+    /// Draw a session as a PNG image
+    pub fn to_png(&self, session: &Session) -> Result<Vec<u8>, crate::error::Error> {
+        let svg = self.to_svg(session);
+        let options = Options {
+            font_family: FONT_FAMILY.to_string(),
+            fontdb: Arc::clone(&self.fontdb),
+            ..Options::default()
+        };
+        let tree = Tree::from_str(&svg, &options)
+            .map_err(|e| crate::error::Error::Render(e.to_string()))?;
+
+        let size = tree
+            .size()
+            .to_int_size()
+            .scale_by(PNG_SCALE)
+            .ok_or_else(|| {
+                crate::error::Error::Render("card is too large to rasterize".to_string())
+            })?;
+        let mut pixmap = Pixmap::new(size.width(), size.height()).ok_or_else(|| {
+            crate::error::Error::Render("card is too large to rasterize".to_string())
+        })?;
+        resvg::render(
+            &tree,
+            Transform::from_scale(PNG_SCALE, PNG_SCALE),
+            &mut pixmap.as_mut(),
+        );
+        pixmap
+            .encode_png()
+            .map_err(|e| crate::error::Error::Render(e.to_string()))
+    }
+}
+
+impl Default for CardRenderer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Parse the embedded font for measuring text
+fn embedded_face() -> ttf_parser::Face<'static> {
+    ttf_parser::Face::parse(FONT, 0).expect("embedded font is valid")
+}
+
+/// Append a single line of text
+fn text(svg: &mut String, x: f32, y: f32, size: f32, fill: &str, weight: &str, line: &str) {
+    let _ = writeln!(
+        svg,
+        r#"<text x="{x}" y="{y}" font-size="{size}" font-weight="{weight}" fill="{fill}" xml:space="preserve">{}</text>"#,
+        escape_xml(line)
+    );
+}
+
+/// Escape text for XML, dropping characters XML cannot contain
+fn escape_xml(text: &str) -> String {
+    let valid: String = text
+        .chars()
+        .filter(|c| !c.is_control() && !matches!(c, '\u{FFFE}' | '\u{FFFF}'))
+        .collect();
+    escape_html(&valid)
+}
+
+/// Break text into lines no wider than `max_width`
+///
+/// Lines break where Unicode allows it (UAX #14): between words in Latin
+/// text and between characters in CJK text. Words wider than a line are
+/// split between characters.
+fn wrap(face: &ttf_parser::Face, text: &str, size: f32, max_width: f32) -> Vec<String> {
+    let text = text.replace('\t', "    ");
+    if text.is_empty() {
+        return vec![String::new()];
+    }
+    text.lines()
+        .flat_map(|paragraph| wrap_paragraph(face, paragraph, size, max_width))
+        .collect()
+}
+
+/// Break a paragraph without newlines into lines
+fn wrap_paragraph(
+    face: &ttf_parser::Face,
+    paragraph: &str,
+    size: f32,
+    max_width: f32,
+) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    let mut width = 0.0;
+    let mut start = 0;
+
+    for (end, _) in linebreaks(paragraph) {
+        let segment = &paragraph[start..end];
+        start = end;
+
+        // Trailing spaces may hang past the end of a line
+        let visible = text_width(face, segment.trim_end(), size);
+        if width + visible > max_width && !line.is_empty() {
+            lines.push(line.trim_end().to_string());
+            line.clear();
+            width = 0.0;
+        }
+
+        if visible <= max_width {
+            line.push_str(segment);
+            width += text_width(face, segment, size);
+            continue;
+        }
+        for c in segment.chars() {
+            let advance = char_width(face, c, size);
+            if width + advance > max_width && !line.is_empty() {
+                lines.push(line.trim_end().to_string());
+                line.clear();
+                width = 0.0;
+            }
+            line.push(c);
+            width += advance;
+        }
+    }
+
+    if !line.is_empty() || lines.is_empty() {
+        lines.push(line.trim_end().to_string());
+    }
+    lines
+}
+
+/// Width of text in the embedded font
+fn text_width(face: &ttf_parser::Face, text: &str, size: f32) -> f32 {
+    text.chars().map(|c| char_width(face, c, size)).sum()
+}
+
+/// Width of a character in the embedded font
+///
+/// Characters missing from the font are drawn with a fallback font, and are
+/// estimated at a full em for wide (CJK) characters and at 0.6 em otherwise.
+fn char_width(face: &ttf_parser::Face, c: char, size: f32) -> f32 {
+    if c.is_control() {
+        return 0.0;
+    }
+    match face
+        .glyph_index(c)
+        .and_then(|glyph| face.glyph_hor_advance(glyph))
+    {
+        Some(advance) => f32::from(advance) * size / f32::from(face.units_per_em()),
+        None if is_wide(c) => size,
+        None => size * 0.6,
+    }
+}
+
+/// Whether a character is usually drawn at double width (CJK, emoji)
+fn is_wide(c: char) -> bool {
+    matches!(
+        c as u32,
+        0x1100..=0x115F
+            | 0x2E80..=0x303E
+            | 0x3041..=0x33FF
+            | 0x3400..=0x4DBF
+            | 0x4E00..=0x9FFF
+            | 0xA000..=0xA4CF
+            | 0xAC00..=0xD7A3
+            | 0xF900..=0xFAFF
+            | 0xFE30..=0xFE4F
+            | 0xFF00..=0xFF60
+            | 0xFFE0..=0xFFE6
+            | 0x1F300..=0x1F64F
+            | 0x1F900..=0x1F9FF
+            | 0x20000..=0x3FFFD
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::SessionRecord;
+    use crate::timefmt::Zone;
+
+    fn session(prompts: &[&str]) -> Session {
+        Session::from_records(
+            prompts
+                .iter()
+                .enumerate()
+                .map(|(i, display)| {
+                    SessionRecord::new(
+                        display.to_string(),
+                        1766567616000 + i as i64 * 60_000,
+                        "/Users/elliotxx/app".to_string(),
+                        "d55aaa1c-b149-4aa4-9809-7eab1dba8d4c".to_string(),
+                    )
+                })
+                .collect(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_wrap_latin_at_word_boundaries() {
+        let face = embedded_face();
+        let text = "the quick brown fox jumps over the lazy dog ".repeat(4);
+        let lines = wrap(&face, &text, TEXT_SIZE, 300.0);
+
+        assert!(lines.len() > 1);
+        for line in &lines {
+            assert!(text_width(&face, line, TEXT_SIZE) <= 300.0, "{line}");
+            assert!(!line.starts_with(' ') && !line.ends_with(' '));
+        }
+        // No word is split
+        let words: Vec<&str> = lines.iter().flat_map(|l| l.split(' ')).collect();
+        assert!(words
+            .iter()
+            .all(|w| "the quick brown fox jumps over lazy dog".contains(w)));
+    }
+
+    #[test]
+    fn test_wrap_cjk_between_characters() {
+        let face = embedded_face();
+        let text = "帮我重构搜索模块的排序逻辑，并补充单元测试".repeat(3);
+        let lines = wrap(&face, &text, TEXT_SIZE, 150.0);
+
+        // Ten full-width characters fit in 150px at 15px
+        assert_eq!(lines[0].chars().count(), 10);
+        assert_eq!(lines.concat(), text);
+    }
+
+    #[test]
+    fn test_wrap_keeps_newlines_and_splits_long_words() {
+        let face = embedded_face();
+        let url = format!("https://example.com/{}", "a".repeat(200));
+        let lines = wrap(&face, &format!("first\n\n{url}"), TEXT_SIZE, 300.0);
+
+        assert_eq!(lines[0], "first");
+        assert_eq!(lines[1], "");
+        assert!(lines.len() > 3);
+        assert_eq!(lines[2..].concat(), url);
+        assert_eq!(wrap(&face, "", TEXT_SIZE, 300.0), [""]);
+    }
+
+    #[test]
+    fn test_to_svg() {
+        let svg = CardRenderer::with_fontdb(fontdb::Database::new())
+            .with_time_format(TimeFormat::default().with_zone(Zone::Utc))
+            .to_svg(&session(&["/model", "fix <the> parser & \u{1b}[31mtests"]));
+
+        assert!(svg.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"800\""));
+        assert!(svg.contains(">/Users/elliotxx/app</text>"));
+        assert!(svg.contains(">2025-12-24 09:13 UTC · 1m 0s · 2 prompts</text>"));
+        assert!(svg.contains(">+1m 0s · 2025-12-24 09:14 UTC</text>"));
+        assert!(svg.contains(">fix &lt;the&gt; parser &amp; [31mtests</text>"));
+        // The output is well-formed
+        Tree::from_str(&svg, &Options::default()).unwrap();
+    }
+
+    #[test]
+    fn test_to_svg_truncates_long_prompts() {
+        let pasted = (1..=40)
+            .map(|i| format!("line {i}"))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let svg = CardRenderer::with_fontdb(fontdb::Database::new()).to_svg(&session(&[&pasted]));
+
+        assert!(svg.contains(">line 15</text>"));
+        assert!(!svg.contains(">line 16</text>"));
+        assert!(svg.contains(">… 25 more lines</text>"));
+    }
+
+    #[test]
+    fn test_to_png() {
+        let png = CardRenderer::with_fontdb(fontdb::Database::new())
+            .to_png(&session(&["hello", "再来一次"]))
+            .unwrap();
+
+        assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+        // Width from the IHDR chunk, at twice the SVG size
+        let width = u32::from_be_bytes(png[16..20].try_into().unwrap());
+        assert_eq!(width, 1600);
+    }
+}
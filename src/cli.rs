@@ -1,5 +1,11 @@
 //! CLI module for command-line interface
 
+use crate::config::{OutputFormat, Overrides};
+use crate::exporter::ExportFormat;
+use crate::redact::RedactMode;
+use crate::search::{RankMode, SearchScope};
+use crate::session::PromptRange;
+use crate::timefmt::{TimeFormat, Zone};
 use clap::{Parser, Subcommand};
 use std::path::PathBuf;
 
@@ -10,10 +16,71 @@ use std::path::PathBuf;
 #[command(version = "0.1.0")]
 #[command(about = "Claude Code 会话记录管理工具", long_about = None)]
 pub struct Cli {
+    /// 文本输出中的时间格式 (strftime)，如 "%Y-%m-%d %H:%M"，覆盖配置项 date_format
+    #[arg(long = "date-format", global = true, value_parser = parse_date_format)]
+    pub date_format: Option<String>,
+
+    /// 文本和 JSON 输出中的时区: local (本地), UTC 或 IANA 时区名如 Asia/Shanghai，覆盖配置项 timezone
+    #[arg(long = "timezone", global = true, value_parser = parse_timezone)]
+    pub timezone: Option<String>,
+
+    /// 将一周内的时间显示为相对时间，如 "3h ago"、"yesterday"，覆盖配置项 relative_time
+    #[arg(long = "relative", global = true)]
+    pub relative: bool,
+
     #[command(subcommand)]
     pub command: Commands,
 }
 
+impl Cli {
+    /// Settings given as flags, which take precedence over the config
+    /// file and the environment
+    pub fn overrides(&self) -> Overrides {
+        let (format, limit) = match &self.command {
+            Commands::Parse(args) => (output_format(args.json, args.text), None),
+            Commands::Search(args) => (output_format(args.json, args.text), args.limit),
+            Commands::Show(args) => (output_format(args.json, args.text), None),
+            Commands::Export(args) => (None, args.limit),
+            Commands::Marks(args) => (output_format(args.json, args.text), None),
+            Commands::Tags(args) => (output_format(args.json, args.text), None),
+            Commands::Mark(_)
+            | Commands::Unmark(_)
+            | Commands::Note(_)
+            | Commands::Tag(_)
+            | Commands::Untag(_)
+            | Commands::Config(_) => (None, None),
+        };
+        Overrides {
+            output_format: format,
+            default_limit: limit,
+            date_format: self.date_format.clone(),
+            timezone: self.timezone.clone(),
+            relative_time: self.relative.then_some(true),
+        }
+    }
+}
+
+/// Output format chosen by `--json` or `--text`
+fn output_format(json: bool, text: bool) -> Option<OutputFormat> {
+    if json {
+        Some(OutputFormat::Json)
+    } else if text {
+        Some(OutputFormat::Text)
+    } else {
+        None
+    }
+}
+
+/// Parse a `--date-format` value
+fn parse_date_format(value: &str) -> Result<String, String> {
+    TimeFormat::new(value).map(|format| format.pattern().to_string())
+}
+
+/// Parse a `--timezone` value
+fn parse_timezone(value: &str) -> Result<String, String> {
+    Zone::parse(value).map(|zone| zone.name().to_string())
+}
+
 /// CLI 子命令
 #[derive(Subcommand, Debug)]
 pub enum Commands {
@@ -25,6 +92,14 @@ pub enum Commands {
     #[command(name = "search")]
     Search(SearchArgs),
 
+    /// 查看会话的完整时间线
+    #[command(name = "show")]
+    Show(ShowArgs),
+
+    /// 导出会话、搜索结果或全部收藏（HTML / Markdown / SVG / PNG）用于分享
+    #[command(name = "export")]
+    Export(ExportArgs),
+
     /// 添加收藏
     #[command(name = "mark")]
     Mark(AddMarkArgs),
@@ -36,33 +111,160 @@ pub enum Commands {
     /// 列出所有收藏
     #[command(name = "marks")]
     Marks(ListMarksArgs),
+
+    /// 用 $EDITOR 编辑收藏的笔记（会自动收藏该会话）
+    #[command(name = "note")]
+    Note(NoteArgs),
+
+    /// 为会话添加标签（会自动收藏该会话）
+    #[command(name = "tag")]
+    Tag(TagArgs),
+
+    /// 移除会话的标签
+    #[command(name = "untag")]
+    Untag(TagArgs),
+
+    /// 列出所有标签，或重命名、合并标签
+    #[command(name = "tags")]
+    Tags(TagsArgs),
+
+    /// 查看和修改配置 (~/.claude-memo/config.toml)
+    #[command(name = "config")]
+    Config(ConfigArgs),
 }
 
 /// Parse 命令参数
 #[derive(Parser, Debug)]
 pub struct ParseArgs {
-    /// JSON 格式输出
+    /// JSON 格式输出 (覆盖配置项 output_format)
     #[arg(long = "json")]
     pub json: bool,
 
-    /// 限制显示数量
+    /// 文本格式输出 (覆盖配置项 output_format)
+    #[arg(long = "text", conflicts_with = "json")]
+    pub text: bool,
+
+    /// 限制显示数量 (默认: 全部，不受配置项 default_limit 影响)
     #[arg(long = "limit", short = 'n')]
     pub limit: Option<usize>,
+
+    /// 脱敏 JSON 输出: on (遮盖敏感信息), off, dry-run (仅列出将被遮盖的内容)，
+    /// 需要 --json 或配置项 output_format = "json"
+    #[arg(long = "redact", num_args = 0..=1, require_equals = true, default_missing_value = "on")]
+    pub redact: Option<RedactMode>,
 }
 
 /// Search 命令参数
 #[derive(Parser, Debug)]
 pub struct SearchArgs {
-    /// 搜索关键词
+    /// 搜索关键词，支持 "精确短语"、前缀*、OR、AND、NOT/-排除、括号分组，
+    /// 以及字段过滤: project:<路径> session:<ID> last:7d after:2026-01-01 before:2026-02-01
+    #[arg(allow_hyphen_values = true)]
     pub keyword: String,
 
-    /// JSON 格式输出
+    /// JSON 格式输出 (覆盖配置项 output_format)
     #[arg(long = "json")]
     pub json: bool,
 
-    /// 限制结果数量 (默认: 20)
+    /// 文本格式输出 (覆盖配置项 output_format)
+    #[arg(long = "text", conflicts_with = "json")]
+    pub text: bool,
+
+    /// 限制结果数量 (默认: 配置项 default_limit，即 20)
     #[arg(long = "limit", short = 'n')]
     pub limit: Option<usize>,
+
+    /// 排序方式: relevance (相关度), recent (最新优先), hybrid (相关度随时间衰减)
+    #[arg(long = "rank", default_value_t = RankMode::Recent)]
+    pub rank: RankMode,
+
+    /// hybrid 排序的时间衰减半衰期，如 12h、7d、2w (默认: 7d)
+    #[arg(long = "half-life", value_parser = parse_half_life)]
+    pub half_life: Option<chrono::Duration>,
+
+    /// 搜索范围: all (全部), prompts (仅提问), pasted (仅粘贴内容), answers (仅回答), tools (仅工具调用)
+    #[arg(long = "in", default_value_t = SearchScope::All)]
+    pub scope: SearchScope,
+
+    /// 脱敏 JSON 输出: on (遮盖敏感信息), off, dry-run (仅列出将被遮盖的内容)，
+    /// 需要 --json 或配置项 output_format = "json"
+    #[arg(long = "redact", num_args = 0..=1, require_equals = true, default_missing_value = "on")]
+    pub redact: Option<RedactMode>,
+}
+
+/// 解析半衰期参数
+fn parse_half_life(value: &str) -> Result<chrono::Duration, String> {
+    let duration = crate::query::parse_duration(value)
+        .map_err(|_| format!("invalid half-life '{value}' (expected e.g. 12h, 7d, 2w)"))?;
+    if duration <= chrono::Duration::zero() {
+        return Err("half-life must be greater than zero".to_string());
+    }
+    Ok(duration)
+}
+
+/// Show 命令参数
+#[derive(Parser, Debug)]
+pub struct ShowArgs {
+    /// 会话 ID
+    pub session_id: String,
+
+    /// JSON 格式输出 (覆盖配置项 output_format)
+    #[arg(long = "json")]
+    pub json: bool,
+
+    /// 文本格式输出 (覆盖配置项 output_format)
+    #[arg(long = "text", conflicts_with = "json")]
+    pub text: bool,
+
+    /// 同时显示 Claude 的回复和工具调用 (读取 ~/.claude/projects 中的会话记录)
+    #[arg(long = "transcript")]
+    pub transcript: bool,
+}
+
+/// Export 命令参数
+#[derive(Parser, Debug)]
+pub struct ExportArgs {
+    /// 会话 ID
+    #[arg(required_unless_present_any = ["search", "marks"])]
+    pub session_id: Option<String>,
+
+    /// 导出搜索结果（仅 Markdown），语法同 search 命令
+    #[arg(long = "search", conflicts_with_all = ["session_id", "marks"], allow_hyphen_values = true)]
+    pub search: Option<String>,
+
+    /// 导出全部收藏的会话（仅 Markdown）
+    #[arg(long = "marks", conflicts_with = "session_id")]
+    pub marks: bool,
+
+    /// 限制搜索结果数量 (默认: 配置项 default_limit，即 20)
+    #[arg(long = "limit", short = 'n', requires = "search")]
+    pub limit: Option<usize>,
+
+    /// 导出格式：html, markdown (md), svg, png (卡片图片)
+    /// (默认: 单个会话为 html，--search 和 --marks 为 markdown)
+    #[arg(long = "format")]
+    pub format: Option<ExportFormat>,
+
+    /// 使用模板导出单个会话：~/.claude-memo/templates/ 下的文件名，
+    /// 或内置模板 summary、transcript
+    #[arg(long = "template", conflicts_with_all = ["format", "search", "marks"])]
+    pub template: Option<String>,
+
+    /// 只导出部分提问，从 1 开始编号，如 3、2-5、4-
+    #[arg(long = "prompts", conflicts_with_all = ["search", "marks"])]
+    pub prompts: Option<PromptRange>,
+
+    /// 同时导出 Claude 的回复和工具调用
+    #[arg(long = "transcript", conflicts_with = "search")]
+    pub transcript: bool,
+
+    /// 脱敏: on (遮盖密钥、邮箱、家目录等), off, dry-run (仅列出将被遮盖的内容)
+    #[arg(long = "redact", default_value_t = RedactMode::On, num_args = 0..=1, require_equals = true, default_missing_value = "on")]
+    pub redact: RedactMode,
+
+    /// 输出文件，默认输出到标准输出
+    #[arg(long = "output", short = 'o')]
+    pub output: Option<PathBuf>,
 }
 
 /// 添加收藏参数
@@ -70,6 +272,18 @@ pub struct SearchArgs {
 pub struct AddMarkArgs {
     /// 会话 ID
     pub session_id: String,
+
+    /// 即使历史记录中找不到该会话也收藏
+    #[arg(long = "force", short = 'f')]
+    pub force: bool,
+
+    /// 收藏标题，在列表中代替最后一条提问显示
+    #[arg(long = "title")]
+    pub title: Option<String>,
+
+    /// 收藏笔记
+    #[arg(long = "note")]
+    pub note: Option<String>,
 }
 
 /// 取消收藏参数
@@ -82,9 +296,148 @@ pub struct RemoveMarkArgs {
 /// 列出收藏参数
 #[derive(Parser, Debug)]
 pub struct ListMarksArgs {
-    /// JSON 格式输出
+    /// JSON 格式输出 (覆盖配置项 output_format)
+    #[arg(long = "json")]
+    pub json: bool,
+
+    /// 文本格式输出 (覆盖配置项 output_format)
+    #[arg(long = "text", conflicts_with = "json")]
+    pub text: bool,
+
+    /// 只列出会话已不在历史记录中的收藏
+    #[arg(long = "orphaned")]
+    pub orphaned: bool,
+
+    /// 删除这些失效的收藏（需配合 --orphaned）
+    #[arg(long = "prune", requires = "orphaned")]
+    pub prune: bool,
+
+    /// 只列出带有该标签的收藏
+    #[arg(long = "tag", conflicts_with = "orphaned")]
+    pub tag: Option<String>,
+
+    /// 全文搜索收藏的标题和笔记（语法同 search）
+    #[arg(long = "search", conflicts_with = "orphaned")]
+    pub search: Option<String>,
+}
+
+/// 编辑笔记参数
+#[derive(Parser, Debug)]
+pub struct NoteArgs {
+    /// 会话 ID
+    pub session_id: String,
+
+    /// 直接设置笔记内容，不打开编辑器（空字符串清除笔记）
+    #[arg(long = "message", short = 'm')]
+    pub message: Option<String>,
+}
+
+/// 标签参数
+#[derive(Parser, Debug)]
+pub struct TagArgs {
+    /// 会话 ID
+    pub session_id: String,
+
+    /// 标签（可指定多个）
+    #[arg(required = true)]
+    pub tags: Vec<String>,
+}
+
+/// 列出标签参数
+#[derive(Parser, Debug)]
+pub struct TagsArgs {
+    /// JSON 格式输出 (覆盖配置项 output_format)
     #[arg(long = "json")]
     pub json: bool,
+
+    /// 文本格式输出 (覆盖配置项 output_format)
+    #[arg(long = "text", conflicts_with = "json")]
+    pub text: bool,
+
+    #[command(subcommand)]
+    pub action: Option<TagsAction>,
+}
+
+/// 标签管理操作
+#[derive(Subcommand, Debug)]
+pub enum TagsAction {
+    /// 重命名标签
+    #[command(name = "rename")]
+    Rename {
+        /// 原标签
+        from: String,
+        /// 新标签
+        to: String,
+    },
+
+    /// 将多个标签合并为一个
+    #[command(name = "merge")]
+    Merge {
+        /// 要合并的标签
+        #[arg(required = true)]
+        sources: Vec<String>,
+        /// 合并后的标签
+        #[arg(long = "into")]
+        target: String,
+    },
+}
+
+/// 配置参数
+#[derive(Parser, Debug)]
+pub struct ConfigArgs {
+    #[command(subcommand)]
+    pub action: ConfigAction,
+}
+
+/// 配置管理操作
+#[derive(Subcommand, Debug)]
+pub enum ConfigAction {
+    /// 显示配置项的当前值，嵌套配置项用点号分隔，如 redact.disable
+    #[command(name = "get")]
+    Get {
+        /// 配置项
+        key: String,
+        /// 同时显示值的来源: default, file, env, flag
+        #[arg(long = "show-origin")]
+        show_origin: bool,
+    },
+
+    /// 在配置文件中设置配置项（列表用逗号分隔）
+    #[command(name = "set")]
+    Set {
+        /// 配置项
+        key: String,
+        /// 值
+        #[arg(allow_hyphen_values = true)]
+        value: String,
+    },
+
+    /// 从配置文件中删除配置项，恢复默认值
+    #[command(name = "unset")]
+    Unset {
+        /// 配置项
+        key: String,
+    },
+
+    /// 列出所有配置项的当前值
+    #[command(name = "list")]
+    List {
+        /// 同时显示值的来源: default, file, env, flag
+        #[arg(long = "show-origin")]
+        show_origin: bool,
+    },
+
+    /// 将配置文件重置为默认值
+    #[command(name = "reset")]
+    Reset,
+
+    /// 显示配置文件路径
+    #[command(name = "path")]
+    Path,
+
+    /// 用 $EDITOR 编辑配置文件
+    #[command(name = "edit")]
+    Edit,
 }
 
 /// 获取历史文件路径
@@ -98,3 +451,15 @@ pub fn get_history_path() -> PathBuf {
     let home = dirs::home_dir().unwrap_or(PathBuf::from("."));
     home.join(".claude/history.jsonl")
 }
+
+/// Get the directory holding the session transcripts
+pub fn get_projects_dir() -> PathBuf {
+    // Check CLAUDE_PROJECTS_DIR env var first
+    if let Ok(path) = std::env::var("CLAUDE_PROJECTS_DIR") {
+        return PathBuf::from(path);
+    }
+
+    // Default to ~/.claude/projects
+    let home = dirs::home_dir().unwrap_or(PathBuf::from("."));
+    home.join(".claude/projects")
+}
@@ -3,20 +3,45 @@
 //! # Configuration Options
 //!
 //! - `output_format`: "text" or "json" (default: "text")
-//! - `default_limit`: Default number of results (default: 20)
+//! - `default_limit`: Default number of search results (default: 20)
 //! - `date_format`: Date format string (default: "%Y-%m-%d %H:%M")
+//! - `timezone`: "local", "UTC" or an IANA name such as "Asia/Shanghai"
+//!   (default: "local")
+//! - `relative_time`: Show recent times as "3h ago" (default: false)
+//! - `redact`: Redaction rules, see [`crate::redact`]
+//!
+//! # Precedence
+//!
+//! [`resolve`] builds the effective configuration from, in increasing
+//! order of precedence: the defaults, the config file, the environment
+//! variables in [`ENV_VARS`] and command-line flags. Unknown keys and
+//! invalid values are reported as warnings and skipped, so the next lower
+//! source applies.
+//!
+//! # Editing
+//!
+//! [`set_key`] and [`unset_key`] change the content of the config file by
+//! dotted key, e.g. `redact.disable`, checking values against the schema.
+//! Only the affected key is touched; comments, formatting and the order of
+//! the other keys are kept.
 
+use crate::timefmt::{TimeFormat, Zone, DEFAULT_DATE_FORMAT};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 /// Output format enum
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
 pub enum OutputFormat {
     /// Plain text output
     #[default]
+    #[serde(alias = "Text")]
     Text,
     /// JSON output
+    #[serde(alias = "Json")]
     Json,
 }
 
@@ -30,22 +55,479 @@ pub struct Config {
     /// Date format string
     #[serde(default = "default_date_format")]
     pub date_format: String,
+    /// Time zone of timestamps
+    #[serde(default = "default_timezone")]
+    pub timezone: String,
+    /// Show recent timestamps relative to now
+    #[serde(default)]
+    pub relative_time: bool,
+    /// Redaction settings
+    #[serde(default)]
+    pub redact: RedactConfig,
+}
+
+/// Redaction settings
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub struct RedactConfig {
+    /// Names of built-in rules to turn off
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub disable: Vec<String>,
+    /// Additional rules
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub rules: Vec<RedactRule>,
+}
+
+/// A user-defined redaction rule
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RedactRule {
+    /// Name shown in the mask and in reports
+    pub name: String,
+    /// Regular expression matching the secret
+    pub pattern: String,
+    /// Text replacing the secret, `[REDACTED:<name>]` by default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub replacement: Option<String>,
 }
 
 fn default_date_format() -> String {
-    "%Y-%m-%d %H:%M".to_string()
+    DEFAULT_DATE_FORMAT.to_string()
 }
 
+fn default_timezone() -> String {
+    "local".to_string()
+}
+
+/// Default number of search and parse results
+pub const DEFAULT_LIMIT: usize = 20;
+
 impl Default for Config {
     fn default() -> Self {
         Self {
             output_format: OutputFormat::Text,
-            default_limit: 20,
+            default_limit: DEFAULT_LIMIT,
             date_format: default_date_format(),
+            timezone: default_timezone(),
+            relative_time: false,
+            redact: RedactConfig::default(),
+        }
+    }
+}
+
+impl Config {
+    /// Format of timestamps in text and JSON output
+    ///
+    /// Falls back to the defaults for an invalid `date_format` or
+    /// `timezone`, which [`resolve`] never lets through.
+    pub fn time_format(&self) -> TimeFormat {
+        TimeFormat::new(&self.date_format)
+            .unwrap_or_default()
+            .with_zone(Zone::parse(&self.timezone).unwrap_or(Zone::Local))
+            .with_relative(self.relative_time)
+    }
+}
+
+/// Dotted keys of all settings
+pub const KEYS: &[&str] = &[
+    "output_format",
+    "default_limit",
+    "date_format",
+    "timezone",
+    "relative_time",
+    "redact.disable",
+    "redact.rules",
+];
+
+/// Environment variables overriding settings, as (key, variable)
+pub const ENV_VARS: &[(&str, &str)] = &[
+    ("output_format", "CLAUDE_MEMO_OUTPUT_FORMAT"),
+    ("default_limit", "CLAUDE_MEMO_DEFAULT_LIMIT"),
+    ("date_format", "CLAUDE_MEMO_DATE_FORMAT"),
+    ("timezone", "CLAUDE_MEMO_TIMEZONE"),
+    ("relative_time", "CLAUDE_MEMO_RELATIVE_TIME"),
+];
+
+/// Where the effective value of a setting comes from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Origin {
+    /// Built-in default
+    Default,
+    /// The config file
+    File,
+    /// An environment variable
+    Env,
+    /// A command-line flag
+    Flag,
+}
+
+impl Origin {
+    /// Name of the origin in `--show-origin` output
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Origin::Default => "default",
+            Origin::File => "file",
+            Origin::Env => "env",
+            Origin::Flag => "flag",
+        }
+    }
+}
+
+impl fmt::Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Settings given as command-line flags
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Overrides {
+    /// `--json`
+    pub output_format: Option<OutputFormat>,
+    /// `--limit`
+    pub default_limit: Option<usize>,
+    /// `--date-format`
+    pub date_format: Option<String>,
+    /// `--timezone`
+    pub timezone: Option<String>,
+    /// `--relative`
+    pub relative_time: Option<bool>,
+}
+
+/// The effective configuration and where each setting came from
+#[derive(Debug, Clone, PartialEq)]
+pub struct Resolved {
+    /// Effective configuration
+    pub config: Config,
+    /// Origin of each key in [`KEYS`]
+    pub origins: BTreeMap<&'static str, Origin>,
+    /// Unknown keys and invalid values that were skipped
+    pub warnings: Vec<String>,
+}
+
+/// Resolve the configuration from the config file, the environment and
+/// command-line flags
+pub fn resolve(overrides: &Overrides) -> Resolved {
+    let path = get_config_path();
+    let file = fs::read_to_string(&path).ok();
+    resolve_from(
+        file.as_deref().map(|content| (path.as_path(), content)),
+        |var| std::env::var(var).ok(),
+        overrides,
+    )
+}
+
+/// Resolve the configuration from a config file (path and content), an
+/// environment lookup and command-line flags
+pub fn resolve_from(
+    file: Option<(&Path, &str)>,
+    env: impl Fn(&str) -> Option<String>,
+    overrides: &Overrides,
+) -> Resolved {
+    let mut layers = Layers {
+        table: default_table(),
+        origins: KEYS.iter().map(|key| (*key, Origin::Default)).collect(),
+        warnings: Vec::new(),
+    };
+
+    if let Some((path, content)) = file {
+        let source = path.display().to_string();
+        match content.parse::<toml::Table>() {
+            Ok(table) => {
+                for (key, value) in flatten(&table) {
+                    layers.apply(&source, &key, value, Origin::File);
+                }
+            }
+            Err(e) => layers
+                .warnings
+                .push(format!("{source}: {}; ignoring the file", e.message())),
+        }
+    }
+
+    for (key, var) in ENV_VARS {
+        if let Some(raw) = env(var) {
+            match parse_value(key, &raw) {
+                Ok(value) => layers.apply(var, key, value, Origin::Env),
+                Err(e) => layers
+                    .warnings
+                    .push(format!("{var}: invalid value for {key}: {e}")),
+            }
+        }
+    }
+
+    let mut config: Config = toml::Value::Table(layers.table)
+        .try_into()
+        .unwrap_or_default();
+    let mut origins = layers.origins;
+    if let Some(output_format) = overrides.output_format {
+        config.output_format = output_format;
+        origins.insert("output_format", Origin::Flag);
+    }
+    if let Some(limit) = overrides.default_limit {
+        config.default_limit = limit;
+        origins.insert("default_limit", Origin::Flag);
+    }
+    if let Some(date_format) = &overrides.date_format {
+        config.date_format = date_format.clone();
+        origins.insert("date_format", Origin::Flag);
+    }
+    if let Some(timezone) = &overrides.timezone {
+        config.timezone = timezone.clone();
+        origins.insert("timezone", Origin::Flag);
+    }
+    if let Some(relative_time) = overrides.relative_time {
+        config.relative_time = relative_time;
+        origins.insert("relative_time", Origin::Flag);
+    }
+
+    Resolved {
+        config,
+        origins,
+        warnings: layers.warnings,
+    }
+}
+
+/// The configuration as it is built up, source by source
+struct Layers {
+    table: toml::Table,
+    origins: BTreeMap<&'static str, Origin>,
+    warnings: Vec<String>,
+}
+
+impl Layers {
+    /// Set a key if it is known and its value is valid, else warn
+    fn apply(&mut self, source: &str, key: &str, value: toml::Value, origin: Origin) {
+        let Some(key) = KEYS.iter().find(|k| **k == key) else {
+            self.warnings.push(format!("{source}: unknown key '{key}'"));
+            return;
+        };
+        let mut table = self.table.clone();
+        set_dotted(&mut table, key, value);
+        match validate(&table, key) {
+            Ok(()) => {
+                self.table = table;
+                self.origins.insert(key, origin);
+            }
+            Err(e) => self
+                .warnings
+                .push(format!("{source}: invalid value for {key}: {e}")),
+        }
+    }
+}
+
+/// The default configuration as a TOML table
+fn default_table() -> toml::Table {
+    match toml::Value::try_from(Config::default()) {
+        Ok(toml::Value::Table(table)) => table,
+        _ => toml::Table::new(),
+    }
+}
+
+/// Check that a table is a valid configuration, and the value of `key`
+/// in particular
+fn validate(table: &toml::Table, key: &str) -> Result<(), String> {
+    let config: Config = toml::Value::Table(table.clone())
+        .try_into()
+        .map_err(|e: toml::de::Error| e.message().trim().to_string())?;
+    match key {
+        "default_limit" if config.default_limit == 0 => Err("must be at least 1".to_string()),
+        "date_format" => TimeFormat::new(&config.date_format).map(|_| ()),
+        "timezone" => Zone::parse(&config.timezone).map(|_| ()),
+        "redact.disable" | "redact.rules" => crate::redact::Redactor::new(&config.redact)
+            .map(|_| ())
+            .map_err(|e| e.to_string()),
+        _ => Ok(()),
+    }
+}
+
+/// Leaf values of a table by dotted key; arrays are leaves
+fn flatten(table: &toml::Table) -> Vec<(String, toml::Value)> {
+    let mut values = Vec::new();
+    for (key, value) in table {
+        match value {
+            toml::Value::Table(inner) => values.extend(
+                flatten(inner)
+                    .into_iter()
+                    .map(|(inner_key, value)| (format!("{key}.{inner_key}"), value)),
+            ),
+            _ => values.push((key.clone(), value.clone())),
+        }
+    }
+    values
+}
+
+/// Set the value of a dotted key, creating the tables on the way
+fn set_dotted(table: &mut toml::Table, key: &str, value: toml::Value) {
+    match key.split_once('.') {
+        Some((head, rest)) => {
+            let inner = table
+                .entry(head)
+                .or_insert_with(|| toml::Value::Table(toml::Table::new()));
+            if !inner.is_table() {
+                *inner = toml::Value::Table(toml::Table::new());
+            }
+            if let toml::Value::Table(inner) = inner {
+                set_dotted(inner, rest, value);
+            }
+        }
+        None => {
+            table.insert(key.to_string(), value);
+        }
+    }
+}
+
+/// Get the value of a dotted key
+fn get_dotted<'a>(table: &'a toml::Table, key: &str) -> Option<&'a toml::Value> {
+    match key.split_once('.') {
+        Some((head, rest)) => get_dotted(table.get(head)?.as_table()?, rest),
+        None => table.get(key),
+    }
+}
+
+/// Read a setting written as plain text, e.g. in an environment variable
+///
+/// Integers and booleans are parsed, `redact.disable` is split at commas and
+/// `redact.rules` is an inline TOML array; other settings are strings,
+/// which [`validate`] checks further.
+fn parse_value(key: &str, raw: &str) -> Result<toml::Value, String> {
+    let raw = raw.trim();
+    match key {
+        "default_limit" => raw
+            .parse()
+            .map(toml::Value::Integer)
+            .map_err(|_| format!("expected an integer, got '{raw}'")),
+        "relative_time" => raw
+            .parse()
+            .map(toml::Value::Boolean)
+            .map_err(|_| format!("expected true or false, got '{raw}'")),
+        "redact.disable" => Ok(toml::Value::Array(
+            raw.split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(|item| toml::Value::String(item.to_string()))
+                .collect(),
+        )),
+        "redact.rules" => format!("rules = {raw}")
+            .parse::<toml::Table>()
+            .ok()
+            .and_then(|mut table| table.remove("rules"))
+            .filter(toml::Value::is_array)
+            .ok_or_else(|| {
+                "expected an array of tables, e.g. [{ name = \"ticket\", pattern = 'T-\\d+' }]"
+                    .to_string()
+            }),
+        _ => Ok(toml::Value::String(raw.to_string())),
+    }
+}
+
+/// Set the value of a dotted key in a document, creating the tables on
+/// the way
+///
+/// A value that is replaced keeps its surrounding comments and whitespace.
+fn set_in_document(table: &mut dyn toml_edit::TableLike, key: &str, value: toml_edit::Value) {
+    match key.split_once('.') {
+        Some((head, rest)) => {
+            let inner = table.entry(head).or_insert_with(toml_edit::table);
+            if !inner.is_table_like() {
+                *inner = toml_edit::table();
+            }
+            if let Some(inner) = inner.as_table_like_mut() {
+                set_in_document(inner, rest, value);
+            }
+        }
+        None => match table.get_mut(key).and_then(toml_edit::Item::as_value_mut) {
+            Some(old) => {
+                let decor = old.decor().clone();
+                *old = value;
+                *old.decor_mut() = decor;
+            }
+            None => {
+                table.insert(key, toml_edit::value(value));
+            }
+        },
+    }
+}
+
+/// Remove a dotted key from a document, along with the tables it leaves
+/// empty
+fn remove_from_document(table: &mut dyn toml_edit::TableLike, key: &str) -> bool {
+    match key.split_once('.') {
+        Some((head, rest)) => {
+            let Some(inner) = table
+                .get_mut(head)
+                .and_then(toml_edit::Item::as_table_like_mut)
+            else {
+                return false;
+            };
+            let removed = remove_from_document(inner, rest);
+            if inner.is_empty() {
+                table.remove(head);
+            }
+            removed
         }
+        None => table.remove(key).is_some(),
     }
 }
 
+/// Look up a key in [`KEYS`]
+fn check_key(key: &str) -> Result<&'static str, crate::error::Error> {
+    KEYS.iter()
+        .find(|k| **k == key)
+        .copied()
+        .ok_or_else(|| crate::error::Error::UnknownConfigKey {
+            key: key.to_string(),
+            available: KEYS.iter().map(|k| k.to_string()).collect(),
+        })
+}
+
+/// The value of a setting by dotted key
+pub fn config_value(config: &Config, key: &str) -> Result<toml::Value, crate::error::Error> {
+    let key = check_key(key)?;
+    let table = match toml::Value::try_from(config) {
+        Ok(toml::Value::Table(table)) => table,
+        _ => toml::Table::new(),
+    };
+    // Empty redact lists are left out when serializing
+    Ok(get_dotted(&table, key)
+        .cloned()
+        .unwrap_or_else(|| toml::Value::Array(Vec::new())))
+}
+
+/// Set a setting in the content of a config file
+///
+/// The value is written as plain text, see [`parse_value`], and checked
+/// against the schema before the table is changed.
+pub fn set_key(
+    document: &mut toml_edit::DocumentMut,
+    key: &str,
+    raw: &str,
+) -> Result<(), crate::error::Error> {
+    let key = check_key(key)?;
+    let invalid = |message| crate::error::Error::InvalidConfigValue {
+        key: key.to_string(),
+        message,
+    };
+    let value = parse_value(key, raw).map_err(invalid)?;
+    let mut checked = default_table();
+    set_dotted(&mut checked, key, value.clone());
+    validate(&checked, key).map_err(invalid)?;
+    let value = value
+        .to_string()
+        .parse::<toml_edit::Value>()
+        .map_err(|e| invalid(e.to_string()))?;
+    set_in_document(document.as_table_mut(), key, value);
+    Ok(())
+}
+
+/// Remove a setting from the content of a config file, returning whether
+/// it was there
+pub fn unset_key(
+    document: &mut toml_edit::DocumentMut,
+    key: &str,
+) -> Result<bool, crate::error::Error> {
+    let key = check_key(key)?;
+    Ok(remove_from_document(document.as_table_mut(), key))
+}
+
 /// Get the config file path
 pub fn get_config_path() -> PathBuf {
     let data_dir = get_data_dir();
@@ -66,34 +548,50 @@ fn get_data_dir() -> PathBuf {
     }
 }
 
-/// Load configuration from ~/.claude-memo/config.toml
+/// Load the content of ~/.claude-memo/config.toml as an editable document
 ///
-/// Returns default config if file doesn't exist or is invalid.
-pub fn load_config() -> Result<Config, crate::error::Error> {
+/// Returns an empty document if the file doesn't exist.
+fn load_config_document() -> Result<toml_edit::DocumentMut, crate::error::Error> {
     let config_path = get_config_path();
 
     if !config_path.exists() {
-        return Ok(Config::default());
+        return Ok(toml_edit::DocumentMut::new());
     }
 
     let content = fs::read_to_string(&config_path)?;
-    let config: Config = toml::from_str(&content).map_err(crate::error::Error::TomlParse)?;
+    Ok(content.parse()?)
+}
 
-    Ok(config)
+/// Edit ~/.claude-memo/config.toml as a document
+///
+/// The data directory stays locked from reading the file until the edited
+/// document is written, so concurrent edits are not lost. The document is
+/// only written if `edit` returns true; its result is returned.
+pub fn edit_config_document(
+    edit: impl FnOnce(&mut toml_edit::DocumentMut) -> Result<bool, crate::error::Error>,
+) -> Result<bool, crate::error::Error> {
+    let _lock = crate::datadir::DataDirLock::acquire(&get_data_dir())?;
+    let mut document = load_config_document()?;
+    let changed = edit(&mut document)?;
+    if changed {
+        crate::datadir::write_atomic(&get_config_path(), document.to_string())?;
+    }
+    Ok(changed)
 }
 
 /// Save configuration to ~/.claude-memo/config.toml
 pub fn save_config(config: &Config) -> Result<(), crate::error::Error> {
+    let content = toml::to_string_pretty(config).map_err(crate::error::Error::TomlSerialize)?;
+    save_config_text(&content)
+}
+
+/// Replace ~/.claude-memo/config.toml with the given content
+pub fn save_config_text(content: &str) -> Result<(), crate::error::Error> {
     let config_path = get_config_path();
     let data_dir = get_data_dir();
 
-    // Create directory if it doesn't exist
-    if !data_dir.exists() {
-        fs::create_dir_all(&data_dir)?;
-    }
-
-    let content = toml::to_string_pretty(config).map_err(crate::error::Error::TomlSerialize)?;
-    fs::write(&config_path, content)?;
+    let _lock = crate::datadir::DataDirLock::acquire(&data_dir)?;
+    crate::datadir::write_atomic(&config_path, content)?;
 
     Ok(())
 }
@@ -123,6 +621,9 @@ mod tests {
             output_format: OutputFormat::Json,
             default_limit: 50,
             date_format: "%Y/%m/%d".to_string(),
+            timezone: "UTC".to_string(),
+            relative_time: true,
+            redact: RedactConfig::default(),
         };
 
         let toml_str = toml::to_string_pretty(&config).unwrap();
@@ -131,13 +632,15 @@ mod tests {
         assert_eq!(decoded.output_format, OutputFormat::Json);
         assert_eq!(decoded.default_limit, 50);
         assert_eq!(decoded.date_format, "%Y/%m/%d");
+        assert_eq!(decoded.timezone, "UTC");
+        assert!(decoded.relative_time);
     }
 
     #[test]
     fn test_load_config_nonexistent() {
-        // Test that load_config returns default for non-existent file
+        // Without a config file the defaults apply
         let temp_dir = TempDir::new().unwrap();
-        let config_path = temp_dir.path().join("config.toml");
+        let _config_path = temp_dir.path().join("config.toml");
 
         // This will use the actual file system, so we need to mock get_config_path
         // For simplicity, we test the structure here
@@ -165,4 +668,190 @@ date_format = "%Y/%m/%d"
         assert_eq!(json_config.output_format, OutputFormat::Json);
         assert_eq!(json_config.default_limit, 50);
     }
+
+    #[test]
+    fn test_resolve_precedence() {
+        let file = "output_format = \"json\"\ndefault_limit = 50\n";
+        let env = |var: &str| (var == "CLAUDE_MEMO_DEFAULT_LIMIT").then(|| "30".to_string());
+        let overrides = Overrides {
+            date_format: Some("%d/%m/%Y".to_string()),
+            ..Overrides::default()
+        };
+        let resolved = resolve_from(Some((Path::new("config.toml"), file)), env, &overrides);
+
+        assert_eq!(resolved.config.output_format, OutputFormat::Json);
+        assert_eq!(resolved.config.default_limit, 30);
+        assert_eq!(resolved.config.date_format, "%d/%m/%Y");
+        assert_eq!(resolved.origins["output_format"], Origin::File);
+        assert_eq!(resolved.origins["default_limit"], Origin::Env);
+        assert_eq!(resolved.origins["date_format"], Origin::Flag);
+        assert_eq!(resolved.origins["redact.rules"], Origin::Default);
+        assert!(resolved.warnings.is_empty());
+
+        let overrides = Overrides {
+            default_limit: Some(5),
+            ..Overrides::default()
+        };
+        let resolved = resolve_from(Some((Path::new("config.toml"), file)), env, &overrides);
+        assert_eq!(resolved.config.default_limit, 5);
+    }
+
+    #[test]
+    fn test_resolve_warns_about_unknown_and_invalid_keys() {
+        let file = r#"
+default_limit = "many"
+date_format = "%Q"
+colour = "red"
+
+[redact]
+disable = ["email"]
+rules = [{ name = "ticket", patern = "T-[0-9]+" }]
+"#;
+        let env = |var: &str| (var == "CLAUDE_MEMO_OUTPUT_FORMAT").then(|| "yaml".to_string());
+        let resolved = resolve_from(
+            Some((Path::new("config.toml"), file)),
+            env,
+            &Overrides::default(),
+        );
+
+        // Invalid values fall back to the next lower source
+        assert_eq!(
+            resolved.config,
+            Config {
+                redact: RedactConfig {
+                    disable: vec!["email".to_string()],
+                    rules: Vec::new(),
+                },
+                ..Config::default()
+            }
+        );
+        assert_eq!(resolved.origins["redact.disable"], Origin::File);
+        assert_eq!(resolved.origins["default_limit"], Origin::Default);
+
+        let warnings = resolved.warnings.join("\n");
+        assert!(
+            warnings.contains("config.toml: unknown key 'colour'"),
+            "{warnings}"
+        );
+        assert!(
+            warnings.contains(
+                "config.toml: invalid value for default_limit: invalid type: string \"many\""
+            ),
+            "{warnings}"
+        );
+        assert!(
+            warnings.contains("invalid value for date_format: '%Q' is not a valid strftime format"),
+            "{warnings}"
+        );
+        assert!(
+            warnings.contains("invalid value for redact.rules: unknown field `patern`"),
+            "{warnings}"
+        );
+        assert!(warnings.contains("CLAUDE_MEMO_OUTPUT_FORMAT: invalid value for output_format: unknown variant `yaml`"), "{warnings}");
+        assert_eq!(resolved.warnings.len(), 5);
+    }
+
+    #[test]
+    fn test_resolve_ignores_unparsable_file() {
+        let resolved = resolve_from(
+            Some((Path::new("config.toml"), "default_limit = ")),
+            |_| None,
+            &Overrides::default(),
+        );
+        assert_eq!(resolved.config, Config::default());
+        assert_eq!(resolved.warnings.len(), 1);
+        assert!(resolved.warnings[0].ends_with("; ignoring the file"));
+    }
+
+    #[test]
+    fn test_set_and_unset_keys() {
+        let mut table = toml_edit::DocumentMut::new();
+        set_key(&mut table, "default_limit", "50").unwrap();
+        set_key(&mut table, "output_format", "json").unwrap();
+        set_key(&mut table, "timezone", "utc").unwrap();
+        set_key(&mut table, "relative_time", "true").unwrap();
+        set_key(&mut table, "redact.disable", "email, home-path").unwrap();
+        set_key(
+            &mut table,
+            "redact.rules",
+            r#"[{ name = "ticket", pattern = 'T-\d+' }]"#,
+        )
+        .unwrap();
+
+        let config: Config = toml::from_str(&table.to_string()).unwrap();
+        assert_eq!(config.default_limit, 50);
+        assert_eq!(config.output_format, OutputFormat::Json);
+        assert_eq!(config.timezone, "utc");
+        assert!(config.relative_time);
+        assert_eq!(config.redact.disable, vec!["email", "home-path"]);
+        assert_eq!(config.redact.rules[0].pattern, r"T-\d+");
+
+        let error = |key: &str, raw: &str| {
+            set_key(&mut toml_edit::DocumentMut::new(), key, raw)
+                .unwrap_err()
+                .to_string()
+        };
+        assert_eq!(
+            error("default_limit", "many"),
+            "Invalid value for default_limit: expected an integer, got 'many'"
+        );
+        assert_eq!(
+            error("default_limit", "0"),
+            "Invalid value for default_limit: must be at least 1"
+        );
+        assert!(error("output_format", "yaml").contains("unknown variant `yaml`"));
+        assert!(error("date_format", "%Q").contains("not a valid strftime format"));
+        assert!(error("timezone", "Mars/Base").contains("unknown time zone 'Mars/Base'"));
+        assert_eq!(
+            error("relative_time", "yes"),
+            "Invalid value for relative_time: expected true or false, got 'yes'"
+        );
+        assert!(error("redact.disable", "emails").contains("Invalid redact rule 'emails'"));
+        assert!(error("redact.rules", "ticket").contains("expected an array of tables"));
+        assert!(error("colour", "red").starts_with("Unknown config key 'colour' (available: "));
+
+        assert!(unset_key(&mut table, "redact.disable").unwrap());
+        assert!(unset_key(&mut table, "redact.rules").unwrap());
+        assert!(!unset_key(&mut table, "redact.rules").unwrap());
+        assert!(!table.contains_key("redact"));
+        assert!(unset_key(&mut table, "colour").is_err());
+    }
+
+    #[test]
+    fn test_set_and_unset_keep_comments_and_order() {
+        let content = "# my settings\ndefault_limit = 20 # results\n\n# always json\noutput_format = \"text\"\n\n[redact]\n# not needed here\ndisable = [\"email\"]\n";
+        let mut document: toml_edit::DocumentMut = content.parse().unwrap();
+
+        set_key(&mut document, "default_limit", "50").unwrap();
+        set_key(&mut document, "timezone", "UTC").unwrap();
+        assert_eq!(
+            document.to_string(),
+            "# my settings\ndefault_limit = 50 # results\n\n# always json\noutput_format = \"text\"\ntimezone = \"UTC\"\n\n[redact]\n# not needed here\ndisable = [\"email\"]\n"
+        );
+
+        assert!(unset_key(&mut document, "redact.disable").unwrap());
+        assert!(unset_key(&mut document, "timezone").unwrap());
+        assert_eq!(
+            document.to_string(),
+            "# my settings\ndefault_limit = 50 # results\n\n# always json\noutput_format = \"text\"\n"
+        );
+    }
+
+    #[test]
+    fn test_config_value() {
+        let config = Config::default();
+        assert_eq!(
+            config_value(&config, "default_limit").unwrap(),
+            toml::Value::Integer(20)
+        );
+        assert_eq!(
+            config_value(&config, "output_format").unwrap(),
+            toml::Value::String("text".to_string())
+        );
+        assert_eq!(
+            config_value(&config, "redact.disable").unwrap(),
+            toml::Value::Array(Vec::new())
+        );
+        assert!(config_value(&config, "redact").is_err());
+    }
 }
@@ -0,0 +1,138 @@
+//! Data directory module for safe concurrent access to ~/.claude-memo/
+//!
+//! Several `claude-memo` processes may run at the same time, e.g. a search
+//! in one terminal while a session is marked in another. Processes that
+//! change the data directory hold an advisory lock on it, and files are
+//! replaced by writing a temporary file next to them and renaming it over
+//! the original, so readers never see a partial write.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Name of the lock file inside the data directory
+const LOCK_FILE: &str = ".lock";
+
+/// Get the data directory path (~/.claude-memo)
+///
+/// Only resolves the path; nothing is opened or created.
+///
+/// Supports CLAUDE_MEMO_DATA_DIR environment variable for testing.
+/// When CLAUDE_MEMO_DATA_DIR is set, it is used as-is (user provides full path).
+/// Otherwise defaults to ~/.claude-memo
+pub fn data_dir() -> Result<PathBuf, crate::error::Error> {
+    if let Ok(data_dir) = std::env::var("CLAUDE_MEMO_DATA_DIR") {
+        // User provides the full data directory path
+        Ok(PathBuf::from(data_dir))
+    } else {
+        // Use default ~/.claude-memo
+        let home = dirs::home_dir().ok_or(crate::error::Error::HomeDirNotFound)?;
+        Ok(home.join(".claude-memo"))
+    }
+}
+
+/// Exclusive advisory lock on a data directory, released when dropped
+#[derive(Debug)]
+pub struct DataDirLock {
+    /// Open lock file; closing it releases the lock
+    _file: File,
+}
+
+impl DataDirLock {
+    /// Wait until no other process holds the lock, then take it
+    ///
+    /// The lock is advisory: it only coordinates `claude-memo` processes.
+    pub fn acquire(data_dir: &Path) -> Result<Self, crate::error::Error> {
+        fs::create_dir_all(data_dir)?;
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(data_dir.join(LOCK_FILE))?;
+        file.lock()?;
+        Ok(Self { _file: file })
+    }
+}
+
+/// Replace the contents of a file atomically
+///
+/// The data is written to a temporary file in the same directory, flushed
+/// to disk and renamed over `path`. Missing parent directories are created.
+pub fn write_atomic(path: &Path, contents: impl AsRef<[u8]>) -> Result<(), crate::error::Error> {
+    let dir = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    fs::create_dir_all(dir)?;
+
+    let file_name = path.file_name().unwrap_or(path.as_os_str());
+    let temp_path = dir.join(format!(
+        ".{}.{}.tmp",
+        file_name.to_string_lossy(),
+        std::process::id()
+    ));
+
+    let result = File::create(&temp_path)
+        .and_then(|mut file| {
+            file.write_all(contents.as_ref())?;
+            file.sync_all()
+        })
+        .and_then(|()| fs::rename(&temp_path, path));
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    Ok(result?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+    use std::time::Duration;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_write_atomic_replaces_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("nested/config.toml");
+
+        write_atomic(&path, "first").unwrap();
+        write_atomic(&path, "second").unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "second");
+        // No temporary file is left behind
+        let entries = fs::read_dir(path.parent().unwrap()).unwrap().count();
+        assert_eq!(entries, 1);
+    }
+
+    #[test]
+    fn test_write_atomic_keeps_original_on_failure() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("target");
+        // Renaming a file over a non-empty directory fails
+        fs::create_dir_all(path.join("child")).unwrap();
+
+        assert!(write_atomic(&path, "data").is_err());
+        assert!(path.join("child").is_dir());
+        assert_eq!(fs::read_dir(temp_dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_lock_is_exclusive() {
+        let temp_dir = TempDir::new().unwrap();
+        let lock = DataDirLock::acquire(temp_dir.path()).unwrap();
+
+        let (sender, receiver) = mpsc::channel();
+        let data_dir = temp_dir.path().to_path_buf();
+        let waiter = std::thread::spawn(move || {
+            let _lock = DataDirLock::acquire(&data_dir).unwrap();
+            sender.send(()).unwrap();
+        });
+
+        // The second lock is only granted once the first is released
+        assert!(receiver.recv_timeout(Duration::from_millis(200)).is_err());
+        drop(lock);
+        receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+        waiter.join().unwrap();
+    }
+}
@@ -16,6 +16,10 @@ pub enum Error {
     #[error("TOML parse error: {0}")]
     TomlParse(#[from] toml::de::Error),
 
+    /// TOML document parsing error, when editing a file in place
+    #[error("TOML parse error: {0}")]
+    TomlEdit(#[from] toml_edit::TomlError),
+
     /// TOML serialization error
     #[error("TOML serialization error: {0}")]
     TomlSerialize(toml::ser::Error),
@@ -24,6 +28,12 @@ pub enum Error {
     #[error("Database error: {0}")]
     Database(#[from] rusqlite::Error),
 
+    /// Database written by a newer version of claude-memo
+    #[error(
+        "Unsupported database schema version {found} (this version supports up to {supported})"
+    )]
+    UnsupportedSchemaVersion { found: i32, supported: i32 },
+
     /// File not found error (exit code 3)
     #[error("File not found: {0}")]
     NotFound(String),
@@ -36,6 +46,88 @@ pub enum Error {
     #[error("Session not found in favorites: {0}")]
     SessionNotFound(String),
 
+    /// Session not found in the history index
+    #[error("Session not found in history: {0}")]
+    UnknownSession(String),
+
+    /// Session ID prefix matching more than one session
+    #[error("Ambiguous session ID '{prefix}', candidates:\n  {}", .candidates.join("\n  "))]
+    AmbiguousSessionId {
+        /// The prefix as given
+        prefix: String,
+        /// Matching session IDs
+        candidates: Vec<String>,
+    },
+
+    /// Tag name that cannot be used
+    #[error("Invalid tag: {0}")]
+    InvalidTag(String),
+
+    /// Tag not used by any session
+    #[error("Tag not found: {0}")]
+    TagNotFound(String),
+
+    /// Tag name already in use
+    #[error("Tag already exists: {0}")]
+    TagExists(String),
+
+    /// Malformed search query
+    #[error("Invalid query: {0}")]
+    InvalidQuery(String),
+
+    /// Export template that is neither a user template nor built in
+    #[error("Template not found: {name} (available: {})", .available.join(", "))]
+    TemplateNotFound {
+        /// The name as given
+        name: String,
+        /// Names of the available templates
+        available: Vec<String>,
+    },
+
+    /// Export template that fails to compile or render
+    #[error("Template error: {0:#}")]
+    Template(#[from] minijinja::Error),
+
+    /// Prompt range past the end of a session
+    #[error("Prompt range {range} is out of bounds (the session has {prompt_count} prompts)")]
+    PromptRangeOutOfBounds {
+        /// The range as given
+        range: String,
+        /// Number of prompts in the session
+        prompt_count: usize,
+    },
+
+    /// Image that cannot be rendered
+    #[error("Render error: {0}")]
+    Render(String),
+
+    /// Redaction rule that cannot be used
+    #[error("Invalid redact rule '{name}': {message}")]
+    InvalidRedactRule {
+        /// Name of the rule
+        name: String,
+        /// What is wrong with it
+        message: String,
+    },
+
+    /// Setting that is not part of the configuration
+    #[error("Unknown config key '{key}' (available: {})", .available.join(", "))]
+    UnknownConfigKey {
+        /// The key as given
+        key: String,
+        /// Keys of all settings
+        available: Vec<String>,
+    },
+
+    /// Value that does not fit a setting
+    #[error("Invalid value for {key}: {message}")]
+    InvalidConfigValue {
+        /// Dotted key of the setting
+        key: String,
+        /// What is wrong with the value
+        message: String,
+    },
+
     /// Invalid timestamp
     #[error("Invalid timestamp: {0}")]
     InvalidTimestamp(i64),
@@ -1,21 +1,65 @@
 //! Indexer module for building FTS5 search indexes
+//!
+//! Prompts from history.jsonl go into `records`. The answers and tool
+//! activity of the session transcripts go into `messages`, each linked to
+//! the prompt it followed.
 
-use crate::parser::SessionRecord;
+use crate::datadir::DataDirLock;
+use crate::parser::{parse_line, PastedContent, SessionRecord};
+use crate::transcript::{parse_transcript_file, Message, Transcript, TranscriptStore};
 use crate::Result;
-use rusqlite::{params, Connection};
-use std::path::PathBuf;
+use rusqlite::{params, params_from_iter, Connection, OptionalExtension, TransactionBehavior};
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+/// Version of the index schema, stored in `PRAGMA user_version`
+const SCHEMA_VERSION: i32 = 6;
+
+/// Aggregated information about one session in the index
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SessionSummary {
+    /// Unique session identifier (UUID)
+    pub session_id: String,
+    /// Project path from the session's most recent record
+    pub project: String,
+    /// Timestamp of the first record (milliseconds)
+    pub first_timestamp: i64,
+    /// Timestamp of the last record (milliseconds)
+    pub last_timestamp: i64,
+    /// Number of prompts recorded in the session
+    pub prompt_count: usize,
+}
+
+impl SessionSummary {
+    /// Read a summary from a row of
+    /// `session_id, project, first_timestamp, last_timestamp, prompt_count`
+    pub(crate) fn from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<Self> {
+        Ok(Self {
+            session_id: row.get(0)?,
+            project: row.get(1)?,
+            first_timestamp: row.get(2)?,
+            last_timestamp: row.get(3)?,
+            prompt_count: row.get::<_, i64>(4)? as usize,
+        })
+    }
+}
 
 /// FTS5 Indexer for session records
 #[derive(Debug, Clone)]
 pub struct Indexer {
     /// Path to the SQLite database
     db_path: PathBuf,
+    /// Directory whose lock serializes index updates
+    lock_dir: PathBuf,
 }
 
 impl Indexer {
     /// Create a new Indexer
     pub fn new() -> Result<Self> {
-        let data_dir = crate::storage::Storage::new()?.data_dir().clone();
+        let data_dir = crate::datadir::data_dir()?;
         let db_path = data_dir.join("index/sessions.db");
 
         // Create index directory if it doesn't exist
@@ -25,17 +69,21 @@ impl Indexer {
             }
         }
 
-        Ok(Self { db_path })
+        Ok(Self {
+            db_path,
+            lock_dir: data_dir,
+        })
     }
 
     /// Create an Indexer with a custom database path (for testing)
     #[cfg(test)]
     pub fn with_db_path(db_path: PathBuf) -> Self {
-        Self { db_path }
+        let lock_dir = db_path.parent().map(PathBuf::from).unwrap_or_default();
+        Self { db_path, lock_dir }
     }
 
-    /// Build or rebuild the FTS5 index from session records
-    pub fn build_index(&self, records: &[SessionRecord]) -> Result<usize> {
+    /// Open the index database, creating the schema if needed
+    fn open(&self) -> Result<Connection> {
         // Ensure parent directory exists before opening database
         if let Some(parent) = self.db_path.parent() {
             if !parent.exists() {
@@ -43,91 +91,820 @@ impl Indexer {
             }
         }
 
-        let conn = Connection::open(&self.db_path)?;
+        let mut conn = Connection::open(&self.db_path)?;
+        crate::tokenizer::register(&conn)?;
+        conn.busy_timeout(std::time::Duration::from_secs(5))?;
 
-        // Enable WAL mode for better performance
+        // Enable WAL mode for better performance; readers keep seeing the
+        // last committed index while it is being updated
         conn.pragma_update(None, "journal_mode", "WAL")?;
 
-        // Create tables and FTS5 virtual table
-        conn.execute_batch(
-            r#"
-            CREATE TABLE IF NOT EXISTS sessions (
-                id INTEGER PRIMARY KEY AUTOINCREMENT,
-                display TEXT NOT NULL,
-                timestamp INTEGER NOT NULL,
-                project TEXT NOT NULL,
-                session_id TEXT NOT NULL UNIQUE
-            );
+        if user_version(&conn)? != SCHEMA_VERSION {
+            create_schema(&mut conn)?;
+        }
 
-            CREATE VIRTUAL TABLE IF NOT EXISTS sessions_fts USING fts5(
-                display,
-                project,
-                session_id,
-                content='sessions',
-                content_rowid='rowid'
-            );
+        Ok(conn)
+    }
 
-            CREATE TRIGGER IF NOT EXISTS sessions_ai AFTER INSERT ON sessions BEGIN
-                INSERT INTO sessions_fts(rowid, display, project, session_id)
-                VALUES (new.rowid, new.display, new.project, new.session_id);
-            END;
-
-            CREATE TRIGGER IF NOT EXISTS sessions_ad AFTER DELETE ON sessions BEGIN
-                INSERT INTO sessions_fts(sessions_fts, rowid, display, project, session_id)
-                VALUES ('delete', old.rowid, old.display, old.project, old.session_id);
-            END;
-
-            CREATE TRIGGER IF NOT EXISTS sessions_au AFTER UPDATE ON sessions BEGIN
-                INSERT INTO sessions_fts(sessions_fts, rowid, display, project, session_id)
-                VALUES ('delete', old.rowid, old.display, old.project, old.session_id);
-                INSERT INTO sessions_fts(rowid, display, project, session_id)
-                VALUES (new.rowid, new.display, new.project, new.session_id);
-            END;
-            "#,
-        )?;
+    /// Build or rebuild the FTS5 index from session records
+    ///
+    /// The index is no longer tied to a history file afterwards, so the
+    /// checkpoint is cleared and the next `update_index` does a full rebuild.
+    pub fn build_index(&self, records: &[SessionRecord]) -> Result<usize> {
+        let _lock = DataDirLock::acquire(&self.lock_dir)?;
+        let mut conn = self.open()?;
+        let tx = conn.transaction()?;
 
-        // Clear existing data and rebuild
-        conn.execute("DELETE FROM sessions", [])?;
-        conn.execute("DELETE FROM sessions_fts", [])?;
+        clear_index(&tx)?;
+        let count = insert_records(&tx, records)?;
+        relink_messages(&tx)?;
 
-        // Insert records
-        let mut insert_stmt = conn.prepare(
-            "INSERT OR REPLACE INTO sessions (display, timestamp, project, session_id) VALUES (?1, ?2, ?3, ?4)",
-        )?;
+        optimize(&tx)?;
+        tx.commit()?;
+
+        Ok(count)
+    }
 
+    /// Bring the index up to date with a history file
+    ///
+    /// Only lines appended since the stored checkpoint are parsed and
+    /// inserted. The index is rebuilt from scratch when there is no
+    /// checkpoint, or when the file was replaced, truncated or rewritten.
+    ///
+    /// Concurrent updates are serialized by the data directory lock, so the
+    /// same lines are never indexed twice. Each update is one transaction:
+    /// readers see the previous index until it commits.
+    pub fn update_index(&self, history_path: &Path) -> Result<IndexUpdate> {
+        let _lock = DataDirLock::acquire(&self.lock_dir)?;
+
+        let file = File::open(history_path).map_err(|_| {
+            crate::error::Error::NotFound(history_path.to_string_lossy().to_string())
+        })?;
+        let metadata = file.metadata()?;
+        let file_id = file_identity(&metadata);
+        let file_size = metadata.len();
+
+        let mut conn = self.open()?;
+        let checkpoint = load_checkpoint(&conn)?;
+        let mut reader = BufReader::new(file);
+
+        let resume = match &checkpoint {
+            Some(cp) => cp.file_id == file_id && cp.matches_file(&mut reader, file_size)?,
+            None => false,
+        };
+
+        if resume {
+            let cp = checkpoint.expect("checked above");
+            if cp.offset == file_size {
+                return Ok(IndexUpdate::UpToDate);
+            }
+
+            let chunk = read_lines_from(&mut reader, cp.offset)?;
+            let tx = conn.transaction()?;
+            let count = insert_records(&tx, &chunk.records)?;
+            relink_session_messages(&tx, &chunk.records)?;
+            save_checkpoint(&tx, &chunk.checkpoint(file_id, file_size).unwrap_or(cp))?;
+            tx.commit()?;
+
+            return Ok(IndexUpdate::Appended(count));
+        }
+
+        let chunk = read_lines_from(&mut reader, 0)?;
+        let tx = conn.transaction()?;
+        clear_index(&tx)?;
+        let count = insert_records(&tx, &chunk.records)?;
+        relink_messages(&tx)?;
+        // Saved even when no line was read, so that an empty history is not
+        // rebuilt again on every run
+        let cp = chunk
+            .checkpoint(file_id.clone(), file_size)
+            .unwrap_or_else(|| Checkpoint {
+                file_id,
+                file_size,
+                offset: chunk.end,
+                last_line_start: chunk.end,
+                last_line_hash: hash_line(b""),
+            });
+        save_checkpoint(&tx, &cp)?;
+        optimize(&tx)?;
+        tx.commit()?;
+
+        Ok(IndexUpdate::Rebuilt(count))
+    }
+
+    /// Bring the indexed transcript messages up to date
+    ///
+    /// Transcripts whose size or modification time changed since the last
+    /// run are indexed again, those that were removed are dropped. Messages
+    /// are linked to the prompts already in the index, so the history
+    /// should be indexed first. Returns the number of transcripts indexed.
+    pub fn update_transcripts(&self, store: &TranscriptStore) -> Result<usize> {
+        let _lock = DataDirLock::acquire(&self.lock_dir)?;
+        let mut conn = self.open()?;
+        let indexed = indexed_transcripts(&conn)?;
+        let tx = conn.transaction()?;
+
+        let mut listed = HashSet::new();
         let mut count = 0;
-        for record in records {
-            insert_stmt.execute(params![
-                record.display,
-                record.timestamp,
-                record.project,
-                record.session_id
-            ])?;
+        for (session_id, path) in store.list()? {
+            let path_key = path.to_string_lossy().to_string();
+            // A transcript removed since the listing is dropped below
+            let metadata = match std::fs::metadata(&path) {
+                Ok(metadata) => metadata,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e.into()),
+            };
+            let stamp = file_stamp(&metadata);
+            listed.insert(path_key.clone());
+            if indexed.get(&path_key) == Some(&stamp) {
+                continue;
+            }
+
+            // Unreadable transcripts are skipped like unparsable lines
+            let Ok(transcript) = parse_transcript_file(&path) else {
+                continue;
+            };
+            // Messages are keyed by transcript, as a session may have
+            // transcripts in more than one project directory
+            tx.execute(
+                "DELETE FROM messages WHERE transcript = ?1",
+                params![path_key],
+            )?;
+            insert_messages(&tx, &path_key, &transcript)?;
+            tx.execute(
+                "INSERT OR REPLACE INTO transcripts (path, session_id, size, modified) VALUES (?1, ?2, ?3, ?4)",
+                params![path_key, session_id, stamp.0, stamp.1],
+            )?;
             count += 1;
         }
 
-        // Optimize FTS5 index
-        conn.execute(
-            "INSERT INTO sessions_fts(sessions_fts) VALUES('optimize')",
-            [],
-        )?;
+        for path in indexed.keys().filter(|p| !listed.contains(*p)) {
+            tx.execute("DELETE FROM messages WHERE transcript = ?1", params![path])?;
+            tx.execute("DELETE FROM transcripts WHERE path = ?1", params![path])?;
+        }
 
+        tx.commit()?;
         Ok(count)
     }
 
+    /// Get the number of indexed transcript messages
+    pub fn message_count(&self) -> Result<usize> {
+        let conn = self.open()?;
+        let count: i64 = conn.query_row("SELECT COUNT(*) FROM messages", [], |row| row.get(0))?;
+        Ok(count as usize)
+    }
+
+    /// Get the stored history checkpoint, if any
+    pub fn checkpoint(&self) -> Result<Option<Checkpoint>> {
+        let conn = self.open()?;
+        load_checkpoint(&conn)
+    }
+
     /// Get the number of indexed records
     pub fn get_count(&self) -> Result<usize> {
-        let conn = Connection::open(&self.db_path)?;
+        let conn = self.open()?;
+        let count: i64 = conn.query_row("SELECT COUNT(*) FROM records", [], |row| row.get(0))?;
+        Ok(count as usize)
+    }
+
+    /// Get the number of distinct indexed sessions
+    pub fn session_count(&self) -> Result<usize> {
+        let conn = self.open()?;
         let count: i64 = conn.query_row("SELECT COUNT(*) FROM sessions", [], |row| row.get(0))?;
         Ok(count as usize)
     }
 
+    /// Get the aggregated summary of one session
+    pub fn get_session(&self, session_id: &str) -> Result<Option<SessionSummary>> {
+        let conn = self.open()?;
+        let summary = conn
+            .query_row(
+                "SELECT session_id, project, first_timestamp, last_timestamp, prompt_count FROM sessions WHERE session_id = ?1",
+                params![session_id],
+                SessionSummary::from_row,
+            )
+            .optional()?;
+        Ok(summary)
+    }
+
+    /// Get the IDs of all indexed sessions
+    pub fn session_ids(&self) -> Result<Vec<String>> {
+        let conn = self.open()?;
+        let mut stmt = conn.prepare("SELECT session_id FROM sessions")?;
+        let rows = stmt.query_map([], |row| row.get(0))?;
+
+        let mut ids = Vec::new();
+        for row in rows {
+            ids.push(row?);
+        }
+        Ok(ids)
+    }
+
+    /// Get all records of one session, oldest first
+    pub fn session_records(&self, session_id: &str) -> Result<Vec<SessionRecord>> {
+        let conn = self.open()?;
+        let mut stmt = conn.prepare(
+            "SELECT display, timestamp, project, session_id, pasted_json FROM records WHERE session_id = ?1 ORDER BY timestamp, id",
+        )?;
+        let rows = stmt.query_map(params![session_id], |row| {
+            Ok(
+                SessionRecord::new(row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)
+                    .with_pasted(decode_pasted(row.get(4)?)),
+            )
+        })?;
+
+        let mut records = Vec::new();
+        for row in rows {
+            records.push(row?);
+        }
+        Ok(records)
+    }
+
+    /// Get the most recent record of each of the given sessions
+    ///
+    /// Sessions that are not indexed are left out.
+    pub fn latest_records(&self, session_ids: &[String]) -> Result<HashMap<String, SessionRecord>> {
+        let mut latest = HashMap::new();
+        if session_ids.is_empty() {
+            return Ok(latest);
+        }
+
+        let conn = self.open()?;
+        let placeholders = vec!["?"; session_ids.len()].join(", ");
+        let mut stmt = conn.prepare(&format!(
+            "SELECT r.display, r.timestamp, r.project, r.session_id, r.pasted_json
+             FROM sessions s
+             JOIN records r ON r.session_id = s.session_id AND r.timestamp = s.last_timestamp
+             WHERE s.session_id IN ({placeholders})
+             ORDER BY r.id"
+        ))?;
+        let rows = stmt.query_map(params_from_iter(session_ids), |row| {
+            Ok(
+                SessionRecord::new(row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)
+                    .with_pasted(decode_pasted(row.get(4)?)),
+            )
+        })?;
+
+        for row in rows {
+            let record = row?;
+            latest.entry(record.session_id.clone()).or_insert(record);
+        }
+        Ok(latest)
+    }
+
     /// Check if index exists
     pub fn index_exists(&self) -> bool {
         self.db_path.exists()
     }
 }
 
+/// Position in history.jsonl up to which the index is known to be complete
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Checkpoint {
+    /// Identity of the indexed file (device and inode on Unix)
+    pub file_id: String,
+    /// File size when the checkpoint was taken
+    pub file_size: u64,
+    /// Byte offset just past the last indexed line
+    pub offset: u64,
+    /// Byte offset where the last indexed line starts
+    pub last_line_start: u64,
+    /// FNV-1a hash of the last indexed line, used to detect rewritten files
+    pub last_line_hash: String,
+}
+
+impl Checkpoint {
+    /// Check that the file still contains the last indexed line at the same position
+    fn matches_file<R: Read + Seek>(&self, reader: &mut R, file_size: u64) -> Result<bool> {
+        if file_size < self.offset || self.last_line_start > self.offset {
+            return Ok(false);
+        }
+
+        let mut line = vec![0u8; (self.offset - self.last_line_start) as usize];
+        reader.seek(SeekFrom::Start(self.last_line_start))?;
+        reader.read_exact(&mut line)?;
+        Ok(hash_line(&line) == self.last_line_hash)
+    }
+}
+
+/// Result of an incremental index update
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IndexUpdate {
+    /// Nothing was appended since the last run
+    UpToDate,
+    /// Records appended since the last checkpoint were indexed
+    Appended(usize),
+    /// The index was rebuilt from the whole file
+    Rebuilt(usize),
+}
+
+/// Records read from a history file starting at some offset
+struct Chunk {
+    records: Vec<SessionRecord>,
+    /// Offset just past the last consumed line
+    end: u64,
+    /// Start offset and hash of the last consumed line
+    last_line: Option<(u64, String)>,
+}
+
+impl Chunk {
+    /// Build the checkpoint to store after indexing this chunk
+    fn checkpoint(&self, file_id: String, file_size: u64) -> Option<Checkpoint> {
+        let (last_line_start, last_line_hash) = self.last_line.clone()?;
+        Some(Checkpoint {
+            file_id,
+            file_size,
+            offset: self.end,
+            last_line_start,
+            last_line_hash,
+        })
+    }
+}
+
+/// Read and parse complete lines starting at `offset`
+///
+/// A trailing line without a newline is only consumed when it parses, so a
+/// line that is still being written is picked up on the next run.
+fn read_lines_from<R: BufRead + Seek>(reader: &mut R, offset: u64) -> Result<Chunk> {
+    reader.seek(SeekFrom::Start(offset))?;
+
+    let mut records = Vec::new();
+    let mut pos = offset;
+    let mut last_line = None;
+    let mut buf = Vec::new();
+
+    loop {
+        buf.clear();
+        let n = reader.read_until(b'\n', &mut buf)?;
+        if n == 0 {
+            break;
+        }
+
+        let complete = buf.ends_with(b"\n");
+        let parsed = parse_line(&String::from_utf8_lossy(&buf));
+        if !complete && parsed.is_err() {
+            break;
+        }
+
+        // Skip invalid lines instead of failing (graceful handling)
+        if let Ok(Some(record)) = parsed {
+            records.push(record);
+        }
+        last_line = Some((pos, hash_line(&buf)));
+        pos += n as u64;
+    }
+
+    Ok(Chunk {
+        records,
+        end: pos,
+        last_line,
+    })
+}
+
+/// Read the schema version of the index database
+fn user_version(conn: &Connection) -> Result<i32> {
+    Ok(conn.pragma_query_value(None, "user_version", |row| row.get(0))?)
+}
+
+/// Create the index schema, dropping an index of another schema version
+///
+/// The index is a cache of history.jsonl: an index written by another
+/// schema version is dropped and rebuilt rather than migrated. Everything
+/// happens in one transaction, so concurrent readers never see the tables
+/// missing.
+fn create_schema(conn: &mut Connection) -> Result<()> {
+    let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+    // Another process may have created the schema while this one waited
+    if user_version(&tx)? == SCHEMA_VERSION {
+        return Ok(());
+    }
+
+    tx.execute_batch(
+        r#"
+        DROP TABLE IF EXISTS sessions_fts;
+        DROP TABLE IF EXISTS records_fts;
+        DROP TABLE IF EXISTS records_cjk;
+        DROP TABLE IF EXISTS sessions;
+        DROP TABLE IF EXISTS records;
+        DROP TABLE IF EXISTS index_checkpoint;
+        DROP TABLE IF EXISTS messages_fts;
+        DROP TABLE IF EXISTS messages_cjk;
+        DROP TABLE IF EXISTS messages;
+        DROP TABLE IF EXISTS transcripts;
+        "#,
+    )?;
+
+    // One row per history line in `records`, aggregated per session in
+    // `sessions`. Records are only ever appended or cleared as a whole,
+    // so the FTS5 tables only need an insert trigger. `records_cjk`
+    // indexes the same columns with the CJK bigram tokenizer. `pasted`
+    // holds the text of the pasted contents for searching, `pasted_json`
+    // the pasted contents themselves.
+    //
+    // `messages` holds one row per answer, tool call or tool result of a
+    // transcript, `transcript` being the path of the file. `role` is
+    // `answer`, `tool_use` or `tool_result`, `files` the file paths the
+    // tool worked on, one per line, and `record_id` the prompt the message
+    // followed. Messages are replaced per transcript, so their FTS5 tables
+    // also need a delete trigger. `transcripts` records the size and
+    // modification time of each indexed transcript.
+    tx.execute_batch(
+        r#"
+        CREATE TABLE IF NOT EXISTS records (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            display TEXT NOT NULL,
+            timestamp INTEGER NOT NULL,
+            project TEXT NOT NULL,
+            session_id TEXT NOT NULL,
+            pasted TEXT NOT NULL DEFAULT '',
+            pasted_json TEXT
+        );
+
+        CREATE INDEX IF NOT EXISTS records_session_idx ON records(session_id, timestamp);
+        CREATE INDEX IF NOT EXISTS records_timestamp_idx ON records(timestamp);
+
+        CREATE TABLE IF NOT EXISTS sessions (
+            session_id TEXT PRIMARY KEY,
+            project TEXT NOT NULL,
+            first_timestamp INTEGER NOT NULL,
+            last_timestamp INTEGER NOT NULL,
+            prompt_count INTEGER NOT NULL
+        );
+
+        CREATE VIRTUAL TABLE IF NOT EXISTS records_fts USING fts5(
+            display,
+            project,
+            session_id,
+            pasted,
+            content='records',
+            content_rowid='id'
+        );
+
+        CREATE VIRTUAL TABLE IF NOT EXISTS records_cjk USING fts5(
+            display,
+            project,
+            session_id,
+            pasted,
+            content='records',
+            content_rowid='id',
+            tokenize='cjk_bigram'
+        );
+
+        CREATE TRIGGER IF NOT EXISTS records_ai AFTER INSERT ON records BEGIN
+            INSERT INTO records_fts(rowid, display, project, session_id, pasted)
+            VALUES (new.id, new.display, new.project, new.session_id, new.pasted);
+            INSERT INTO records_cjk(rowid, display, project, session_id, pasted)
+            VALUES (new.id, new.display, new.project, new.session_id, new.pasted);
+
+            INSERT INTO sessions (session_id, project, first_timestamp, last_timestamp, prompt_count)
+            VALUES (new.session_id, new.project, new.timestamp, new.timestamp, 1)
+            ON CONFLICT(session_id) DO UPDATE SET
+                project = CASE
+                    WHEN excluded.last_timestamp >= sessions.last_timestamp THEN excluded.project
+                    ELSE sessions.project
+                END,
+                first_timestamp = min(sessions.first_timestamp, excluded.first_timestamp),
+                last_timestamp = max(sessions.last_timestamp, excluded.last_timestamp),
+                prompt_count = sessions.prompt_count + 1;
+        END;
+
+        CREATE TABLE IF NOT EXISTS index_checkpoint (
+            id INTEGER PRIMARY KEY CHECK (id = 1),
+            file_id TEXT NOT NULL,
+            file_size INTEGER NOT NULL,
+            offset INTEGER NOT NULL,
+            last_line_start INTEGER NOT NULL,
+            last_line_hash TEXT NOT NULL
+        );
+
+        CREATE TABLE IF NOT EXISTS messages (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            transcript TEXT NOT NULL,
+            session_id TEXT NOT NULL,
+            record_id INTEGER,
+            timestamp INTEGER,
+            role TEXT NOT NULL,
+            tool TEXT NOT NULL DEFAULT '',
+            text TEXT NOT NULL,
+            files TEXT NOT NULL DEFAULT ''
+        );
+
+        CREATE INDEX IF NOT EXISTS messages_transcript_idx ON messages(transcript);
+        CREATE INDEX IF NOT EXISTS messages_session_idx ON messages(session_id);
+        CREATE INDEX IF NOT EXISTS messages_record_idx ON messages(record_id);
+
+        CREATE VIRTUAL TABLE IF NOT EXISTS messages_fts USING fts5(
+            text,
+            tool,
+            role UNINDEXED,
+            content='messages',
+            content_rowid='id'
+        );
+
+        CREATE VIRTUAL TABLE IF NOT EXISTS messages_cjk USING fts5(
+            text,
+            tool,
+            role UNINDEXED,
+            content='messages',
+            content_rowid='id',
+            tokenize='cjk_bigram'
+        );
+
+        CREATE TRIGGER IF NOT EXISTS messages_ai AFTER INSERT ON messages BEGIN
+            INSERT INTO messages_fts(rowid, text, tool, role)
+            VALUES (new.id, new.text, new.tool, new.role);
+            INSERT INTO messages_cjk(rowid, text, tool, role)
+            VALUES (new.id, new.text, new.tool, new.role);
+        END;
+
+        CREATE TRIGGER IF NOT EXISTS messages_ad AFTER DELETE ON messages BEGIN
+            INSERT INTO messages_fts(messages_fts, rowid, text, tool, role)
+            VALUES ('delete', old.id, old.text, old.tool, old.role);
+            INSERT INTO messages_cjk(messages_cjk, rowid, text, tool, role)
+            VALUES ('delete', old.id, old.text, old.tool, old.role);
+        END;
+
+        CREATE TABLE IF NOT EXISTS transcripts (
+            path TEXT PRIMARY KEY,
+            session_id TEXT NOT NULL,
+            size INTEGER NOT NULL,
+            modified INTEGER NOT NULL
+        );
+        "#,
+    )?;
+    tx.pragma_update(None, "user_version", SCHEMA_VERSION)?;
+    tx.commit()?;
+    Ok(())
+}
+
+/// Remove all indexed records and the checkpoint
+///
+/// Transcript messages are kept; [`relink_messages`] links them to the
+/// rebuilt records.
+fn clear_index(conn: &Connection) -> Result<()> {
+    conn.execute("DELETE FROM records", [])?;
+    conn.execute("DELETE FROM sessions", [])?;
+    conn.execute(
+        "INSERT INTO records_fts(records_fts) VALUES('delete-all')",
+        [],
+    )?;
+    conn.execute(
+        "INSERT INTO records_cjk(records_cjk) VALUES('delete-all')",
+        [],
+    )?;
+    conn.execute("DELETE FROM index_checkpoint", [])?;
+    Ok(())
+}
+
+/// Statement linking transcript messages to the prompt they followed, as
+/// [`insert_messages`] does
+const RELINK_MESSAGES: &str = r#"
+    UPDATE messages SET record_id = COALESCE(
+        (SELECT id FROM records r WHERE r.session_id = messages.session_id AND r.timestamp <= messages.timestamp ORDER BY r.timestamp DESC, r.id DESC LIMIT 1),
+        (SELECT id FROM records r WHERE r.session_id = messages.session_id ORDER BY r.timestamp, r.id LIMIT 1)
+    )
+"#;
+
+/// Link the transcript messages to the prompts again, after the records
+/// were rebuilt
+fn relink_messages(conn: &Connection) -> Result<()> {
+    conn.execute(RELINK_MESSAGES, [])?;
+    Ok(())
+}
+
+/// Link the transcript messages of some sessions to their prompts again,
+/// after prompts of these sessions were appended
+fn relink_session_messages(conn: &Connection, records: &[SessionRecord]) -> Result<()> {
+    let sessions: HashSet<&str> = records.iter().map(|r| r.session_id.as_str()).collect();
+    let mut stmt = conn.prepare(&format!("{RELINK_MESSAGES} WHERE session_id = ?1"))?;
+    for session_id in sessions {
+        stmt.execute(params![session_id])?;
+    }
+    Ok(())
+}
+
+/// Merge the FTS5 index segments after a rebuild
+fn optimize(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "INSERT INTO records_fts(records_fts) VALUES('optimize')",
+        [],
+    )?;
+    conn.execute(
+        "INSERT INTO records_cjk(records_cjk) VALUES('optimize')",
+        [],
+    )?;
+    Ok(())
+}
+
+/// Insert records into the index
+fn insert_records(conn: &Connection, records: &[SessionRecord]) -> Result<usize> {
+    let mut insert_stmt = conn.prepare_cached(
+        "INSERT INTO records (display, timestamp, project, session_id, pasted, pasted_json) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+    )?;
+
+    let mut count = 0;
+    for record in records {
+        let pasted_json = if record.pasted.is_empty() {
+            None
+        } else {
+            Some(serde_json::to_string(&record.pasted)?)
+        };
+        insert_stmt.execute(params![
+            record.display,
+            record.timestamp,
+            record.project,
+            record.session_id,
+            record.pasted_text(),
+            pasted_json
+        ])?;
+        count += 1;
+    }
+
+    Ok(count)
+}
+
+/// Insert the answers and tool activity of a transcript
+///
+/// Each message is linked to the last prompt of the session sent at or
+/// before it, or to the first prompt when it predates them all.
+fn insert_messages(conn: &Connection, path: &str, transcript: &Transcript) -> Result<usize> {
+    let mut insert_stmt = conn.prepare_cached(
+        r#"
+        INSERT INTO messages (session_id, record_id, timestamp, role, tool, text, files, transcript)
+        VALUES (?1, COALESCE(
+            (SELECT id FROM records WHERE session_id = ?1 AND timestamp <= ?2 ORDER BY timestamp DESC, id DESC LIMIT 1),
+            (SELECT id FROM records WHERE session_id = ?1 ORDER BY timestamp, id LIMIT 1)
+        ), ?2, ?3, ?4, ?5, ?6, ?7)
+        "#,
+    )?;
+
+    let mut count = 0;
+    for message in &transcript.messages {
+        let (role, tool, text, files) = match message {
+            Message::AssistantText { text, .. } => ("answer", "", text.clone(), Vec::new()),
+            Message::ToolUse { name, input, .. } => (
+                "tool_use",
+                name.as_str(),
+                input_text(input),
+                input_files(input),
+            ),
+            Message::ToolResult {
+                tool_use_id,
+                content,
+                ..
+            } => (
+                "tool_result",
+                transcript.tool_name(tool_use_id).unwrap_or_default(),
+                content.clone(),
+                tool_use_input(transcript, tool_use_id)
+                    .map(input_files)
+                    .unwrap_or_default(),
+            ),
+            Message::User { .. } | Message::Summary { .. } => continue,
+        };
+        insert_stmt.execute(params![
+            transcript.session_id,
+            message.timestamp(),
+            role,
+            tool,
+            text,
+            files.join("\n"),
+            path
+        ])?;
+        count += 1;
+    }
+
+    Ok(count)
+}
+
+/// Input of the tool call with the given ID
+fn tool_use_input<'a>(transcript: &'a Transcript, tool_use_id: &str) -> Option<&'a Value> {
+    transcript.messages.iter().find_map(|m| match m {
+        Message::ToolUse { id, input, .. } if id == tool_use_id => Some(input),
+        _ => None,
+    })
+}
+
+/// All string values of a tool input, one per line, for searching
+fn input_text(input: &Value) -> String {
+    fn collect<'a>(value: &'a Value, strings: &mut Vec<&'a str>) {
+        match value {
+            Value::String(s) => strings.push(s),
+            Value::Array(values) => values.iter().for_each(|v| collect(v, strings)),
+            Value::Object(fields) => fields.values().for_each(|v| collect(v, strings)),
+            _ => {}
+        }
+    }
+    let mut strings = Vec::new();
+    collect(input, &mut strings);
+    strings.join("\n")
+}
+
+/// File paths a tool input refers to
+fn input_files(input: &Value) -> Vec<String> {
+    FILE_KEYS
+        .iter()
+        .filter_map(|key| input.get(key).and_then(Value::as_str))
+        .map(str::to_string)
+        .collect()
+}
+
+/// Keys of tool inputs that hold a file path, as used by `file:` filters
+const FILE_KEYS: &[&str] = &["file_path", "notebook_path", "path"];
+
+/// Size and modification time (milliseconds) of a file
+fn file_stamp(metadata: &std::fs::Metadata) -> (i64, i64) {
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or_default();
+    (metadata.len() as i64, modified)
+}
+
+/// Size and modification time of each indexed transcript, by path
+fn indexed_transcripts(conn: &Connection) -> Result<HashMap<String, (i64, i64)>> {
+    let mut stmt = conn.prepare("SELECT path, size, modified FROM transcripts")?;
+    let rows = stmt.query_map([], |row| Ok((row.get(0)?, (row.get(1)?, row.get(2)?))))?;
+
+    let mut transcripts = HashMap::new();
+    for row in rows {
+        let (path, stamp) = row?;
+        transcripts.insert(path, stamp);
+    }
+    Ok(transcripts)
+}
+
+/// Read the pasted contents stored in `records.pasted_json`
+pub(crate) fn decode_pasted(json: Option<String>) -> Vec<PastedContent> {
+    json.and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default()
+}
+
+/// Load the stored checkpoint
+fn load_checkpoint(conn: &Connection) -> Result<Option<Checkpoint>> {
+    let checkpoint = conn
+        .query_row(
+            "SELECT file_id, file_size, offset, last_line_start, last_line_hash FROM index_checkpoint WHERE id = 1",
+            [],
+            |row| {
+                Ok(Checkpoint {
+                    file_id: row.get(0)?,
+                    file_size: row.get::<_, i64>(1)? as u64,
+                    offset: row.get::<_, i64>(2)? as u64,
+                    last_line_start: row.get::<_, i64>(3)? as u64,
+                    last_line_hash: row.get(4)?,
+                })
+            },
+        )
+        .optional()?;
+    Ok(checkpoint)
+}
+
+/// Store the checkpoint, replacing the previous one
+fn save_checkpoint(conn: &Connection, checkpoint: &Checkpoint) -> Result<()> {
+    conn.execute(
+        "INSERT OR REPLACE INTO index_checkpoint (id, file_id, file_size, offset, last_line_start, last_line_hash) VALUES (1, ?1, ?2, ?3, ?4, ?5)",
+        params![
+            checkpoint.file_id,
+            checkpoint.file_size as i64,
+            checkpoint.offset as i64,
+            checkpoint.last_line_start as i64,
+            checkpoint.last_line_hash
+        ],
+    )?;
+    Ok(())
+}
+
+/// Identify a file across renames so a rotated history is detected
+#[cfg(unix)]
+fn file_identity(metadata: &std::fs::Metadata) -> String {
+    use std::os::unix::fs::MetadataExt;
+    format!("{}:{}", metadata.dev(), metadata.ino())
+}
+
+/// Identify a file across renames so a rotated history is detected
+#[cfg(not(unix))]
+fn file_identity(metadata: &std::fs::Metadata) -> String {
+    metadata
+        .created()
+        .ok()
+        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+        .map(|d| d.as_nanos().to_string())
+        .unwrap_or_default()
+}
+
+/// FNV-1a hash of a line, stable across Rust versions
+fn hash_line(line: &[u8]) -> String {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in line {
+        hash ^= u64::from(*byte);
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    format!("{hash:016x}")
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -138,9 +915,7 @@ mod tests {
         let temp_dir = TempDir::new().unwrap();
         let db_path = temp_dir.path().join("test.db");
 
-        let indexer = Indexer {
-            db_path: db_path.clone(),
-        };
+        let indexer = Indexer::with_db_path(db_path.clone());
 
         let records = vec![
             SessionRecord::new(
@@ -188,4 +963,463 @@ mod tests {
         assert!(db_path.parent().unwrap().exists());
         assert!(db_path.exists());
     }
+
+    #[test]
+    fn test_build_index_keeps_every_prompt_of_a_session() {
+        let temp_dir = TempDir::new().unwrap();
+        let indexer = Indexer::with_db_path(temp_dir.path().join("test.db"));
+
+        let records = vec![
+            SessionRecord::new(
+                "/first prompt".to_string(),
+                1766567616338,
+                "/Users/elliotxx/old".to_string(),
+                "abc123".to_string(),
+            ),
+            SessionRecord::new(
+                "/second prompt".to_string(),
+                1766567618000,
+                "/Users/elliotxx/new".to_string(),
+                "abc123".to_string(),
+            ),
+            SessionRecord::new(
+                "/other session".to_string(),
+                1766567617000,
+                "/Users/elliotxx".to_string(),
+                "def456".to_string(),
+            ),
+        ];
+
+        assert_eq!(indexer.build_index(&records).unwrap(), 3);
+        assert_eq!(indexer.get_count().unwrap(), 3);
+        assert_eq!(indexer.session_count().unwrap(), 2);
+
+        let session = indexer.get_session("abc123").unwrap().unwrap();
+        assert_eq!(session.prompt_count, 2);
+        assert_eq!(session.first_timestamp, 1766567616338);
+        assert_eq!(session.last_timestamp, 1766567618000);
+        assert_eq!(session.project, "/Users/elliotxx/new");
+
+        assert!(indexer.get_session("missing").unwrap().is_none());
+
+        let displays: Vec<String> = indexer
+            .session_records("abc123")
+            .unwrap()
+            .into_iter()
+            .map(|r| r.display)
+            .collect();
+        assert_eq!(displays, vec!["/first prompt", "/second prompt"]);
+        assert!(indexer.session_records("missing").unwrap().is_empty());
+
+        let latest = indexer
+            .latest_records(&["abc123".to_string(), "missing".to_string()])
+            .unwrap();
+        assert_eq!(latest.len(), 1);
+        assert_eq!(latest["abc123"].display, "/second prompt");
+
+        let mut ids = indexer.session_ids().unwrap();
+        ids.sort();
+        assert_eq!(ids, vec!["abc123", "def456"]);
+    }
+
+    #[test]
+    fn test_update_transcripts_follows_the_files() {
+        let temp_dir = TempDir::new().unwrap();
+        let indexer = Indexer::with_db_path(temp_dir.path().join("test.db"));
+        indexer
+            .build_index(&[SessionRecord::new(
+                "run the tests".to_string(),
+                1766567616000,
+                "/app".to_string(),
+                "s1".to_string(),
+            )])
+            .unwrap();
+
+        let project_dir = temp_dir.path().join("projects/-app");
+        std::fs::create_dir_all(&project_dir).unwrap();
+        let transcript = project_dir.join("s1.jsonl");
+        let answer = r#"{"type":"assistant","timestamp":"2025-12-24T09:13:40.000Z","message":{"content":"All green."}}"#;
+        std::fs::write(&transcript, format!("{answer}\n")).unwrap();
+        let store = TranscriptStore::new(temp_dir.path().join("projects"));
+
+        assert_eq!(indexer.update_transcripts(&store).unwrap(), 1);
+        assert_eq!(indexer.message_count().unwrap(), 1);
+        // Unchanged transcripts are not indexed again
+        assert_eq!(indexer.update_transcripts(&store).unwrap(), 0);
+
+        std::fs::write(&transcript, format!("{answer}\n{answer}\n")).unwrap();
+        assert_eq!(indexer.update_transcripts(&store).unwrap(), 1);
+        assert_eq!(indexer.message_count().unwrap(), 2);
+
+        // Another project directory holding a transcript of the same session
+        let other_dir = temp_dir.path().join("projects/-app-worktree");
+        std::fs::create_dir_all(&other_dir).unwrap();
+        std::fs::write(other_dir.join("s1.jsonl"), format!("{answer}\n")).unwrap();
+        assert_eq!(indexer.update_transcripts(&store).unwrap(), 1);
+        assert_eq!(indexer.message_count().unwrap(), 3);
+
+        std::fs::remove_file(&transcript).unwrap();
+        assert_eq!(indexer.update_transcripts(&store).unwrap(), 0);
+        assert_eq!(indexer.message_count().unwrap(), 1);
+
+        std::fs::remove_file(other_dir.join("s1.jsonl")).unwrap();
+        assert_eq!(indexer.update_transcripts(&store).unwrap(), 0);
+        assert_eq!(indexer.message_count().unwrap(), 0);
+    }
+
+    #[test]
+    fn test_session_records_keep_pasted_contents() {
+        let temp_dir = TempDir::new().unwrap();
+        let indexer = Indexer::with_db_path(temp_dir.path().join("test.db"));
+
+        let pasted = vec![
+            PastedContent {
+                id: 1,
+                kind: crate::parser::PastedKind::Text,
+                content: "thread 'main' panicked".to_string(),
+                media_type: None,
+            },
+            PastedContent {
+                id: 2,
+                kind: crate::parser::PastedKind::Image,
+                content: String::new(),
+                media_type: Some("image/png".to_string()),
+            },
+        ];
+        let records = vec![SessionRecord::new(
+            "why? [Pasted text #1]".to_string(),
+            1766567616338,
+            "/Users/elliotxx".to_string(),
+            "abc123".to_string(),
+        )
+        .with_pasted(pasted.clone())];
+        indexer.build_index(&records).unwrap();
+
+        let stored = indexer.session_records("abc123").unwrap();
+        assert_eq!(stored[0].pasted, pasted);
+    }
+
+    #[test]
+    fn test_open_rebuilds_outdated_schema() {
+        let temp_dir = TempDir::new().unwrap();
+        let db_path = temp_dir.path().join("test.db");
+
+        // Index written by the previous one-row-per-session schema
+        let conn = Connection::open(&db_path).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE sessions (id INTEGER PRIMARY KEY, display TEXT, timestamp INTEGER, project TEXT, session_id TEXT UNIQUE);",
+        )
+        .unwrap();
+        drop(conn);
+
+        let indexer = Indexer::with_db_path(db_path);
+        let records = vec![SessionRecord::new(
+            "/test".to_string(),
+            1766567616338,
+            "/Users/elliotxx".to_string(),
+            "test-id".to_string(),
+        )];
+        assert_eq!(indexer.build_index(&records).unwrap(), 1);
+        assert_eq!(indexer.session_count().unwrap(), 1);
+    }
+
+    fn history_line(display: &str, timestamp: i64, session_id: &str) -> String {
+        format!(
+            "{{\"display\":\"{display}\",\"pastedContents\":{{}},\"timestamp\":{timestamp},\"project\":\"/Users/elliotxx\",\"sessionId\":\"{session_id}\"}}\n"
+        )
+    }
+
+    fn append(path: &std::path::Path, content: &str) {
+        use std::io::Write;
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .unwrap();
+        file.write_all(content.as_bytes()).unwrap();
+    }
+
+    #[test]
+    fn test_update_index_appends_only_new_lines() {
+        let temp_dir = TempDir::new().unwrap();
+        let history = temp_dir.path().join("history.jsonl");
+        let indexer = Indexer::with_db_path(temp_dir.path().join("index/test.db"));
+
+        append(&history, &history_line("/first", 1766567616338, "id-001"));
+        append(&history, &history_line("/second", 1766567617000, "id-002"));
+        assert_eq!(
+            indexer.update_index(&history).unwrap(),
+            IndexUpdate::Rebuilt(2)
+        );
+
+        // Nothing new
+        assert_eq!(
+            indexer.update_index(&history).unwrap(),
+            IndexUpdate::UpToDate
+        );
+
+        append(&history, &history_line("/third", 1766567618000, "id-003"));
+        assert_eq!(
+            indexer.update_index(&history).unwrap(),
+            IndexUpdate::Appended(1)
+        );
+        assert_eq!(indexer.get_count().unwrap(), 3);
+
+        let checkpoint = indexer.checkpoint().unwrap().unwrap();
+        assert_eq!(
+            checkpoint.offset,
+            std::fs::metadata(&history).unwrap().len()
+        );
+    }
+
+    #[test]
+    fn test_concurrent_updates_index_each_line_once() {
+        let temp_dir = TempDir::new().unwrap();
+        let history = temp_dir.path().join("history.jsonl");
+        for i in 0..50 {
+            append(
+                &history,
+                &history_line(&format!("/prompt {i}"), 1766567616000 + i, "id-001"),
+            );
+        }
+
+        let db_path = temp_dir.path().join("index/test.db");
+        let updates: Vec<IndexUpdate> = std::thread::scope(|scope| {
+            let handles: Vec<_> = (0..4)
+                .map(|_| {
+                    let indexer = Indexer::with_db_path(db_path.clone());
+                    let history = &history;
+                    scope.spawn(move || indexer.update_index(history).unwrap())
+                })
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+
+        let rebuilds = updates
+            .iter()
+            .filter(|u| matches!(u, IndexUpdate::Rebuilt(_)))
+            .count();
+        assert_eq!(rebuilds, 1);
+        assert_eq!(Indexer::with_db_path(db_path).get_count().unwrap(), 50);
+    }
+
+    #[test]
+    fn test_readers_never_see_empty_index_during_rebuild() {
+        let temp_dir = TempDir::new().unwrap();
+        let db_path = temp_dir.path().join("index/test.db");
+        let records: Vec<SessionRecord> = (0..200)
+            .map(|i| {
+                SessionRecord::new(
+                    format!("/prompt {i}"),
+                    1766567616000 + i,
+                    "/Users/elliotxx".to_string(),
+                    format!("id-{}", i % 10),
+                )
+            })
+            .collect();
+        let indexer = Indexer::with_db_path(db_path.clone());
+        indexer.build_index(&records).unwrap();
+
+        std::thread::scope(|scope| {
+            let writer = scope.spawn(|| {
+                for _ in 0..5 {
+                    indexer.build_index(&records).unwrap();
+                }
+            });
+            let reader = Indexer::with_db_path(db_path.clone());
+            while !writer.is_finished() {
+                assert_eq!(reader.get_count().unwrap(), 200);
+                assert_eq!(reader.session_count().unwrap(), 10);
+            }
+        });
+    }
+
+    #[test]
+    fn test_update_index_waits_for_partial_line() {
+        let temp_dir = TempDir::new().unwrap();
+        let history = temp_dir.path().join("history.jsonl");
+        let indexer = Indexer::with_db_path(temp_dir.path().join("test.db"));
+
+        append(&history, &history_line("/first", 1766567616338, "id-001"));
+        append(&history, "{\"display\":\"/sec");
+        assert_eq!(
+            indexer.update_index(&history).unwrap(),
+            IndexUpdate::Rebuilt(1)
+        );
+
+        // Finish writing the line
+        append(
+            &history,
+            "ond\",\"timestamp\":1766567617000,\"project\":\"/p\",\"sessionId\":\"id-002\"}\n",
+        );
+        assert_eq!(
+            indexer.update_index(&history).unwrap(),
+            IndexUpdate::Appended(1)
+        );
+        assert_eq!(indexer.get_count().unwrap(), 2);
+    }
+
+    #[test]
+    fn test_update_index_rebuilds_after_truncation() {
+        let temp_dir = TempDir::new().unwrap();
+        let history = temp_dir.path().join("history.jsonl");
+        let indexer = Indexer::with_db_path(temp_dir.path().join("test.db"));
+
+        append(&history, &history_line("/first", 1766567616338, "id-001"));
+        append(&history, &history_line("/second", 1766567617000, "id-002"));
+        indexer.update_index(&history).unwrap();
+
+        std::fs::write(&history, history_line("/fresh", 1766567619000, "id-009")).unwrap();
+        assert_eq!(
+            indexer.update_index(&history).unwrap(),
+            IndexUpdate::Rebuilt(1)
+        );
+        assert_eq!(indexer.get_count().unwrap(), 1);
+    }
+
+    #[test]
+    fn test_empty_history_is_not_rebuilt_again() {
+        let temp_dir = TempDir::new().unwrap();
+        let history = temp_dir.path().join("history.jsonl");
+        let indexer = Indexer::with_db_path(temp_dir.path().join("test.db"));
+
+        std::fs::write(&history, "").unwrap();
+        assert_eq!(
+            indexer.update_index(&history).unwrap(),
+            IndexUpdate::Rebuilt(0)
+        );
+        assert_eq!(
+            indexer.update_index(&history).unwrap(),
+            IndexUpdate::UpToDate
+        );
+
+        append(&history, &history_line("/first", 1766567616338, "id-001"));
+        assert_eq!(
+            indexer.update_index(&history).unwrap(),
+            IndexUpdate::Appended(1)
+        );
+    }
+
+    #[test]
+    fn test_history_rebuild_keeps_transcript_messages() {
+        let temp_dir = TempDir::new().unwrap();
+        let history = temp_dir.path().join("history.jsonl");
+        let indexer = Indexer::with_db_path(temp_dir.path().join("test.db"));
+        append(&history, &history_line("/first", 1766567616338, "s1"));
+        indexer.update_index(&history).unwrap();
+
+        let project_dir = temp_dir.path().join("projects/-app");
+        std::fs::create_dir_all(&project_dir).unwrap();
+        let answer = r#"{"type":"assistant","timestamp":"2025-12-24T09:13:40.000Z","message":{"content":"All green."}}"#;
+        std::fs::write(project_dir.join("s1.jsonl"), format!("{answer}\n")).unwrap();
+        let store = TranscriptStore::new(temp_dir.path().join("projects"));
+        assert_eq!(indexer.update_transcripts(&store).unwrap(), 1);
+
+        std::fs::write(&history, history_line("/fresh", 1766567615000, "s1")).unwrap();
+        assert_eq!(
+            indexer.update_index(&history).unwrap(),
+            IndexUpdate::Rebuilt(1)
+        );
+        // Neither dropped nor read again, and linked to the new prompt
+        assert_eq!(indexer.message_count().unwrap(), 1);
+        assert_eq!(indexer.update_transcripts(&store).unwrap(), 0);
+        let conn = indexer.open().unwrap();
+        let linked: String = conn
+            .query_row(
+                "SELECT r.display FROM messages m JOIN records r ON r.id = m.record_id",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(linked, "/fresh");
+    }
+
+    #[test]
+    fn test_appended_prompts_are_linked_to_indexed_messages() {
+        let temp_dir = TempDir::new().unwrap();
+        let history = temp_dir.path().join("history.jsonl");
+        let indexer = Indexer::with_db_path(temp_dir.path().join("test.db"));
+        append(&history, &history_line("/other", 1766567610000, "s0"));
+        indexer.update_index(&history).unwrap();
+
+        // The transcript is indexed before its prompt reaches the history
+        let project_dir = temp_dir.path().join("projects/-app");
+        std::fs::create_dir_all(&project_dir).unwrap();
+        let answer = r#"{"type":"assistant","timestamp":"2025-12-24T09:13:40.000Z","message":{"content":"All green."}}"#;
+        std::fs::write(project_dir.join("s1.jsonl"), format!("{answer}\n")).unwrap();
+        let store = TranscriptStore::new(temp_dir.path().join("projects"));
+        assert_eq!(indexer.update_transcripts(&store).unwrap(), 1);
+
+        append(&history, &history_line("/first", 1766567616338, "s1"));
+        assert_eq!(
+            indexer.update_index(&history).unwrap(),
+            IndexUpdate::Appended(1)
+        );
+        let conn = indexer.open().unwrap();
+        let linked: String = conn
+            .query_row(
+                "SELECT r.display FROM messages m JOIN records r ON r.id = m.record_id",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(linked, "/first");
+    }
+
+    #[test]
+    fn test_update_index_rebuilds_after_rewrite() {
+        // Same size, different content: the last-line hash no longer matches
+        let temp_dir = TempDir::new().unwrap();
+        let history = temp_dir.path().join("history.jsonl");
+        let indexer = Indexer::with_db_path(temp_dir.path().join("test.db"));
+
+        append(&history, &history_line("/aaaa", 1766567616338, "id-001"));
+        indexer.update_index(&history).unwrap();
+
+        let mut file = std::fs::OpenOptions::new()
+            .write(true)
+            .open(&history)
+            .unwrap();
+        use std::io::Write;
+        file.write_all(history_line("/bbbb", 1766567616338, "id-001").as_bytes())
+            .unwrap();
+        drop(file);
+        append(&history, &history_line("/cccc", 1766567617000, "id-002"));
+
+        assert_eq!(
+            indexer.update_index(&history).unwrap(),
+            IndexUpdate::Rebuilt(2)
+        );
+    }
+
+    #[test]
+    fn test_update_index_rebuilds_after_rotation() {
+        let temp_dir = TempDir::new().unwrap();
+        let history = temp_dir.path().join("history.jsonl");
+        let indexer = Indexer::with_db_path(temp_dir.path().join("test.db"));
+
+        append(&history, &history_line("/first", 1766567616338, "id-001"));
+        indexer.update_index(&history).unwrap();
+
+        // Replace the file with a new one that has the old content as prefix
+        let rotated = temp_dir.path().join("history.jsonl.new");
+        std::fs::copy(&history, &rotated).unwrap();
+        append(&rotated, &history_line("/second", 1766567617000, "id-002"));
+        std::fs::rename(&rotated, &history).unwrap();
+
+        assert_eq!(
+            indexer.update_index(&history).unwrap(),
+            IndexUpdate::Rebuilt(2)
+        );
+    }
+
+    #[test]
+    fn test_update_index_missing_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let indexer = Indexer::with_db_path(temp_dir.path().join("test.db"));
+
+        let result = indexer.update_index(&temp_dir.path().join("missing.jsonl"));
+        assert!(result.is_err());
+        assert!(result.unwrap_err().to_string().contains("File not found"));
+    }
 }
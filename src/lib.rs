@@ -3,22 +3,42 @@
 //! # Modules
 //!
 //! - `config`: User configuration management
+//! - `datadir`: Locking and atomic writes in ~/.claude-memo/
 //! - `parser`: Parse history.jsonl files
+//! - `transcript`: Parse full session transcripts
 //! - `indexer`: Build search indexes
+//! - `tokenizer`: CJK-aware FTS5 tokenizer
 //! - `storage`: Manage ~/.claude-memo/ data
+//! - `query`: Search query parsing
+//! - `resolver`: Resolve abbreviated session IDs
 //! - `search`: Full-text search functionality
-//! - `exporter`: HTML export and screenshot
+//! - `session`: Session timelines
+//! - `timefmt`: Timestamps in text and JSON output
+//! - `exporter`: Session export (HTML, Markdown)
+//! - `card`: SVG/PNG session cards
+//! - `template`: User-defined export templates
+//! - `redact`: Secret redaction
 //! - `cli`: Command-line interface
 //! - `error`: Error types
 
+pub mod card;
 pub mod cli;
 pub mod config;
+pub mod datadir;
 pub mod error;
 pub mod exporter;
 pub mod indexer;
 pub mod parser;
+pub mod query;
+pub mod redact;
+pub mod resolver;
 pub mod search;
+pub mod session;
 pub mod storage;
+pub mod template;
+pub mod timefmt;
+pub mod tokenizer;
+pub mod transcript;
 
 /// Result type alias using anyhow::Error
 pub type Result<T> = std::result::Result<T, anyhow::Error>;
@@ -5,36 +5,109 @@
 //! ```
 //! claude-memo parse          # 解析并显示历史记录
 //! claude-memo search "关键词" # 全文搜索
+//! claude-memo show <session-id>  # 查看会话时间线
+//! claude-memo show <session-id> --transcript # 同时查看 Claude 的回复
+//! claude-memo export <session-id> -o session.html # 导出会话
+//! claude-memo export --marks --format markdown     # 导出全部收藏
+//! claude-memo export <session-id> --template summary # 使用模板导出
+//! claude-memo export <session-id> --format png -o card.png # 导出卡片图片
+//! claude-memo export <session-id> --redact=dry-run # 列出导出时将被遮盖的敏感信息
 //! claude-memo mark <session-id>  # 添加收藏
 //! claude-memo unmark <session-id> # 取消收藏
 //! claude-memo marks         # 列出所有收藏
+//! claude-memo marks --orphaned --prune # 删除失效的收藏
+//! claude-memo note <session-id>  # 编辑收藏笔记
+//! claude-memo tag <session-id> rust # 添加标签
+//! claude-memo tags          # 列出所有标签
+//! claude-memo config list --show-origin # 查看配置及其来源
+//! claude-memo config set default_limit 50 # 修改配置
+//! claude-memo marks --relative --timezone Asia/Shanghai # 相对时间、指定时区
 //! ```
 
 use clap::Parser;
-use claude_memo::cli::{get_history_path, Cli, Commands};
-use claude_memo::parser::parse_history_file;
+use claude_memo::cli::{
+    get_history_path, get_projects_dir, Cli, Commands, ConfigAction, ExportArgs, TagsAction,
+};
+use claude_memo::config::{self, Config, OutputFormat, Overrides, Resolved};
+use claude_memo::exporter::{ExportFormat, Exporter, MarkInfo};
+use claude_memo::indexer::Indexer;
+use claude_memo::parser::{parse_history_file, SessionRecord};
+use claude_memo::redact::{RedactMode, Redactor, ReportEntry};
+use claude_memo::resolver::SessionResolver;
+use claude_memo::search::{MatchRange, RankMode, SearchResult, SearchScope, SnippetSource};
+use claude_memo::session::Session;
 use claude_memo::storage::Storage;
+use claude_memo::template::Templates;
+use claude_memo::timefmt::{FormatTime, TimeFormat};
+use claude_memo::transcript::{Message, TranscriptStore};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::io::{IsTerminal, Write};
 use std::process;
 
 fn main() {
     let cli = Cli::parse();
 
+    // Settings from flags, the environment, the config file and defaults
+    let resolved = config::resolve(&cli.overrides());
+    for warning in &resolved.warnings {
+        eprintln!("Warning: {warning}");
+    }
+    let config = &resolved.config;
+
+    // Redaction applies to JSON output, which may also come from the config
+    let redact = match &cli.command {
+        Commands::Parse(args) => args.redact,
+        Commands::Search(args) => args.redact,
+        _ => None,
+    };
+    if redact.is_some() && config.output_format != OutputFormat::Json {
+        eprintln!(
+            "Error: --redact requires JSON output, use --json or set output_format = \"json\""
+        );
+        process::exit(2);
+    }
+
     // Handle commands
     match &cli.command {
         Commands::Parse(args) => {
-            if let Err(e) = handle_parse(args.json, args.limit) {
+            if let Err(e) = handle_parse(config, args.limit, args.redact) {
                 eprintln!("Error: {e}");
                 process::exit(1);
             }
         }
         Commands::Search(args) => {
-            if let Err(e) = handle_search(&args.keyword, args.limit, args.json) {
+            if let Err(e) = handle_search(
+                &args.keyword,
+                config,
+                args.rank,
+                args.half_life,
+                args.scope,
+                args.redact,
+            ) {
+                eprintln!("Error: {e}");
+                process::exit(1);
+            }
+        }
+        Commands::Show(args) => {
+            if let Err(e) = handle_show(&args.session_id, config, args.transcript) {
+                eprintln!("Error: {e}");
+                process::exit(1);
+            }
+        }
+        Commands::Export(args) => {
+            if let Err(e) = handle_export(args, config) {
                 eprintln!("Error: {e}");
                 process::exit(1);
             }
         }
         Commands::Mark(args) => {
-            if let Err(e) = handle_mark_add(&args.session_id) {
+            if let Err(e) = handle_mark_add(
+                &args.session_id,
+                args.force,
+                args.title.as_deref(),
+                args.note.as_deref(),
+            ) {
                 eprintln!("Error: {e}");
                 process::exit(1);
             }
@@ -46,7 +119,47 @@ fn main() {
             }
         }
         Commands::Marks(args) => {
-            if let Err(e) = handle_mark_list(args.json) {
+            let result = if args.orphaned {
+                handle_mark_orphaned(config, args.prune)
+            } else {
+                handle_mark_list(config, args.tag.as_deref(), args.search.as_deref())
+            };
+            if let Err(e) = result {
+                eprintln!("Error: {e}");
+                process::exit(1);
+            }
+        }
+        Commands::Note(args) => {
+            if let Err(e) = handle_note(&args.session_id, args.message.as_deref()) {
+                eprintln!("Error: {e}");
+                process::exit(1);
+            }
+        }
+        Commands::Tag(args) => {
+            if let Err(e) = handle_tag(&args.session_id, &args.tags) {
+                eprintln!("Error: {e}");
+                process::exit(1);
+            }
+        }
+        Commands::Untag(args) => {
+            if let Err(e) = handle_untag(&args.session_id, &args.tags) {
+                eprintln!("Error: {e}");
+                process::exit(1);
+            }
+        }
+        Commands::Tags(args) => {
+            let result = match &args.action {
+                None => handle_tags_list(config),
+                Some(TagsAction::Rename { from, to }) => handle_tags_rename(from, to),
+                Some(TagsAction::Merge { sources, target }) => handle_tags_merge(sources, target),
+            };
+            if let Err(e) = result {
+                eprintln!("Error: {e}");
+                process::exit(1);
+            }
+        }
+        Commands::Config(args) => {
+            if let Err(e) = handle_config(&args.action, &resolved) {
                 eprintln!("Error: {e}");
                 process::exit(1);
             }
@@ -55,7 +168,11 @@ fn main() {
 }
 
 /// 处理 parse 命令
-fn handle_parse(json: bool, limit: Option<usize>) -> Result<(), Box<dyn std::error::Error>> {
+fn handle_parse(
+    config: &Config,
+    limit: Option<usize>,
+    redact: Option<RedactMode>,
+) -> Result<(), Box<dyn std::error::Error>> {
     let path = get_history_path();
 
     if !path.exists() {
@@ -70,7 +187,29 @@ fn handle_parse(json: bool, limit: Option<usize>) -> Result<(), Box<dyn std::err
         None => records.into_iter().rev().collect(),
     };
 
-    if json {
+    let redact = redact.unwrap_or(RedactMode::Off);
+    if redact == RedactMode::DryRun {
+        let redactor = load_redactor(config)?;
+        let time = config.time_format();
+        let texts = records
+            .iter()
+            .flat_map(|r| record_texts(record_location(r, &time), r));
+        print_redaction_report(&redactor.report(texts));
+        return Ok(());
+    }
+
+    let time = config.time_format();
+    if config.output_format == OutputFormat::Json {
+        let records = match redact {
+            RedactMode::On => {
+                let redactor = load_redactor(config)?;
+                records
+                    .into_iter()
+                    .map(|r| redactor.redact_record(&r))
+                    .collect()
+            }
+            _ => records,
+        };
         // JSON output
         let output: Vec<serde_json::Value> = records
             .iter()
@@ -78,8 +217,10 @@ fn handle_parse(json: bool, limit: Option<usize>) -> Result<(), Box<dyn std::err
                 serde_json::json!({
                     "display": r.display,
                     "timestamp": r.timestamp,
+                    "time": time.iso(r.timestamp),
                     "project": r.project,
-                    "session_id": r.session_id
+                    "session_id": r.session_id,
+                    "pasted": r.pasted
                 })
             })
             .collect();
@@ -87,7 +228,7 @@ fn handle_parse(json: bool, limit: Option<usize>) -> Result<(), Box<dyn std::err
     } else {
         // Text output
         for record in records {
-            println!("{record}");
+            println!("{}", record.with_time_format(&time));
         }
     }
 
@@ -97,62 +238,494 @@ fn handle_parse(json: bool, limit: Option<usize>) -> Result<(), Box<dyn std::err
 /// 处理 search 命令
 fn handle_search(
     keyword: &str,
-    limit: Option<usize>,
-    json: bool,
+    config: &Config,
+    rank: RankMode,
+    half_life: Option<chrono::Duration>,
+    scope: SearchScope,
+    redact: Option<RedactMode>,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    use claude_memo::indexer::Indexer;
     use claude_memo::search::Search;
 
-    let path = get_history_path();
-
-    if !path.exists() {
-        eprintln!("Error: File not found: {}", path.display());
-        process::exit(3);
+    let indexer = open_index()?;
+    if scope.includes_messages() {
+        index_transcripts(&indexer)?;
     }
 
-    // Parse history file and build index
-    let records = parse_history_file(&path)?;
-
-    // Build or update the FTS5 index
-    let indexer = Indexer::new()?;
-    indexer.build_index(&records)?;
-
     // Search using FTS5
-    let search = Search::new()?;
-    let results = search.search(keyword, limit)?;
+    let mut search = Search::new()?
+        .with_rank_mode(rank)
+        .with_scope(scope)
+        .with_zone(*config.time_format().zone());
+    if let Some(half_life) = half_life {
+        search = search.with_half_life(half_life);
+    }
+    let results = search.search(keyword, Some(config.default_limit))?;
 
     if results.is_empty() {
         println!("No results found for: {keyword}");
         return Ok(());
     }
 
-    if json {
+    let redact = redact.unwrap_or(RedactMode::Off);
+    if redact == RedactMode::DryRun {
+        let redactor = load_redactor(config)?;
+        print_redaction_report(
+            &redactor.report(search_result_texts(&results, &config.time_format())),
+        );
+        return Ok(());
+    }
+
+    let time = config.time_format();
+    if config.output_format == OutputFormat::Json {
+        let results = match redact {
+            RedactMode::On => {
+                let redactor = load_redactor(config)?;
+                results
+                    .iter()
+                    .map(|r| redactor.redact_search_result(r))
+                    .collect()
+            }
+            _ => results,
+        };
         let output: Vec<serde_json::Value> = results
             .iter()
             .map(|r| {
                 serde_json::json!({
                     "display": r.record.display,
                     "timestamp": r.record.timestamp,
+                    "time": time.iso(r.record.timestamp),
                     "project": r.record.project,
                     "session_id": r.record.session_id,
-                    "score": r.score
+                    "score": r.score,
+                    "pasted": r.record.pasted,
+                    "snippet": {
+                        "text": r.snippet.text,
+                        "matches": match_ranges(&r.snippet.matches),
+                        "source": r.snippet_source.as_str(),
+                        "tool": r.tool
+                    },
+                    "matches": match_ranges(&r.matches),
+                    "session": {
+                        "project": r.session.project,
+                        "first_timestamp": r.session.first_timestamp,
+                        "last_timestamp": r.session.last_timestamp,
+                        "first_time": time.iso(r.session.first_timestamp),
+                        "last_time": time.iso(r.session.last_timestamp),
+                        "prompt_count": r.session.prompt_count
+                    }
                 })
             })
             .collect();
         println!("{}", serde_json::to_string_pretty(&output)?);
     } else {
+        // Highlight matches only when a person is looking at the output
+        let color = std::io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none();
+        let resolver = SessionResolver::new(indexer.session_ids()?);
         for result in results {
-            println!("{}", result.record);
+            let mut snippet = if color {
+                result.snippet.to_ansi()
+            } else {
+                result.snippet.text
+            };
+            match (result.snippet_source, &result.tool) {
+                (SnippetSource::Prompt, _) => {}
+                (SnippetSource::Tool, Some(tool)) => snippet = format!("[{tool}] {snippet}"),
+                (source, _) => snippet = format!("[{}] {snippet}", source.as_str()),
+            }
+            let record = SessionRecord {
+                display: snippet,
+                session_id: resolver.abbreviate(&result.record.session_id).to_string(),
+                ..result.record
+            };
+            println!("{}", record.with_time_format(&time));
+        }
+    }
+
+    Ok(())
+}
+
+/// Convert match ranges to JSON objects
+fn match_ranges(matches: &[MatchRange]) -> Vec<serde_json::Value> {
+    matches
+        .iter()
+        .map(|m| serde_json::json!({ "start": m.start, "end": m.end }))
+        .collect()
+}
+
+/// 处理 show 命令
+fn handle_show(
+    session_id: &str,
+    config: &Config,
+    transcript: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut session = load_session(session_id)?;
+    if transcript {
+        session = attach_transcript(session)?;
+    }
+
+    let time = config.time_format();
+    if config.output_format == OutputFormat::Json {
+        let prompts: Vec<serde_json::Value> = session
+            .timeline()
+            .enumerate()
+            .map(|(i, (offset, r))| {
+                let mut prompt = serde_json::json!({
+                    "offset_ms": offset,
+                    "timestamp": r.timestamp,
+                    "time": time.iso(r.timestamp),
+                    "display": r.display,
+                    "project": r.project,
+                    "pasted": r.pasted
+                });
+                if session.transcript.is_some() {
+                    prompt["replies"] = serde_json::json!(session.replies(i));
+                }
+                prompt
+            })
+            .collect();
+        let mut output = serde_json::json!({
+            "session_id": session.session_id,
+            "project": session.project(),
+            "start": session.start(),
+            "end": session.end(),
+            "start_time": time.iso(session.start()),
+            "end_time": time.iso(session.end()),
+            "duration_ms": session.duration(),
+            "prompt_count": session.prompt_count(),
+            "prompts": prompts
+        });
+        if let Some(transcript) = &session.transcript {
+            output["summaries"] = serde_json::json!(transcript.summaries().collect::<Vec<_>>());
         }
+        println!("{}", serde_json::to_string_pretty(&output)?);
+    } else {
+        print!("{}", session.with_time_format(&time));
     }
 
     Ok(())
 }
 
+/// 处理 export 命令
+fn handle_export(args: &ExportArgs, config: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    use claude_memo::search::Search;
+
+    let storage = Storage::new()?;
+    let marks = mark_infos(&storage)?;
+    let mut exporter = Exporter::new()
+        .with_marks(marks.clone())
+        .with_time_format(config.time_format());
+    if args.redact == RedactMode::On {
+        exporter = exporter.with_redactor(load_redactor(config)?);
+    }
+    let dry_run = args.redact == RedactMode::DryRun;
+    // Several sessions are only exported as Markdown
+    let format = match (args.format, &args.session_id) {
+        (Some(format), _) => format,
+        (None, Some(_)) => ExportFormat::Html,
+        (None, None) => ExportFormat::Markdown,
+    };
+
+    let (document, exported) = if let Some(session_id) = &args.session_id {
+        let mut session = load_session(session_id)?;
+        if args.transcript {
+            session = attach_transcript(session)?;
+        }
+        if let Some(range) = args.prompts {
+            session = session.select(range)?;
+        }
+        if dry_run {
+            let texts = session_texts("", &session, marks.get(&session.session_id));
+            print_redaction_report(&load_redactor(config)?.report(texts));
+            return Ok(());
+        }
+        let document = match &args.template {
+            Some(name) => {
+                let templates = Templates::new(storage.data_dir().join("templates"));
+                exporter
+                    .render_template(&templates, name, &session)?
+                    .into_bytes()
+            }
+            None => exporter.export(&session, format)?,
+        };
+        (document, session.session_id)
+    } else {
+        if format != ExportFormat::Markdown {
+            return Err(format!(
+                "{format} export supports a single session, use --format markdown"
+            )
+            .into());
+        }
+
+        if let Some(query) = &args.search {
+            index_transcripts(&open_index()?)?;
+            let results = Search::new()?
+                .with_zone(*config.time_format().zone())
+                .search(query, Some(config.default_limit))?;
+            if dry_run {
+                print_redaction_report(
+                    &load_redactor(config)?
+                        .report(search_result_texts(&results, &config.time_format())),
+                );
+                return Ok(());
+            }
+            let document = exporter
+                .search_results_to_markdown(query, &results)
+                .into_bytes();
+            (document, format!("{} search results", results.len()))
+        } else {
+            let indexer = open_index()?;
+            let mut sessions = Vec::new();
+            let mut orphaned = 0;
+            for favorite in storage.list_favorites()? {
+                match Session::from_records(indexer.session_records(&favorite.session_id)?) {
+                    Some(session) if args.transcript => sessions.push(attach_transcript(session)?),
+                    Some(session) => sessions.push(session),
+                    None => orphaned += 1,
+                }
+            }
+            if orphaned > 0 {
+                eprintln!("Skipped {orphaned} marks not found in history");
+            }
+            if dry_run {
+                let texts = sessions.iter().flat_map(|session| {
+                    session_texts(&session.session_id, session, marks.get(&session.session_id))
+                });
+                print_redaction_report(&load_redactor(config)?.report(texts));
+                return Ok(());
+            }
+            let document = exporter
+                .sessions_to_markdown("Marked sessions", &sessions)
+                .into_bytes();
+            (document, format!("{} marked sessions", sessions.len()))
+        }
+    };
+
+    match &args.output {
+        Some(path) => {
+            claude_memo::datadir::write_atomic(path, document)?;
+            println!("✅ Exported {exported} to {}", path.display());
+        }
+        None => {
+            if format.is_binary() && std::io::stdout().is_terminal() {
+                return Err(
+                    format!("refusing to write {format} to a terminal, use -o <file>").into(),
+                );
+            }
+            std::io::stdout().write_all(&document)?;
+        }
+    }
+    Ok(())
+}
+
+/// Attach the transcript of a session, warning when there is none
+fn attach_transcript(session: Session) -> Result<Session, Box<dyn std::error::Error>> {
+    let store = TranscriptStore::new(get_projects_dir());
+    match store.load(&session.session_id)? {
+        Some(transcript) => Ok(session.with_transcript(transcript)),
+        None => {
+            eprintln!("No transcript found for session {}", session.session_id);
+            Ok(session)
+        }
+    }
+}
+
+/// Build the redactor from the built-in rules and the configured ones
+fn load_redactor(config: &Config) -> Result<Redactor, Box<dyn std::error::Error>> {
+    Ok(Redactor::new(&config.redact)?)
+}
+
+/// Label a history record in redaction reports
+fn record_location(record: &SessionRecord, time: &TimeFormat) -> String {
+    format!(
+        "{} {}",
+        record.session_id,
+        time.format_with_zone(record.timestamp)
+    )
+}
+
+/// The prompt, pasted contents and project of a record, labelled for
+/// redaction reports
+fn record_texts(location: String, record: &SessionRecord) -> Vec<(String, &str)> {
+    let mut texts = vec![
+        (location.clone(), record.display.as_str()),
+        (format!("{location} project"), record.project.as_str()),
+    ];
+    for pasted in &record.pasted {
+        texts.push((
+            format!("{location} pasted #{}", pasted.id),
+            pasted.content.as_str(),
+        ));
+    }
+    texts
+}
+
+/// The records of search results and the snippets taken from transcript
+/// messages, labelled for redaction reports
+fn search_result_texts<'a>(
+    results: &'a [SearchResult],
+    time: &TimeFormat,
+) -> Vec<(String, &'a str)> {
+    results
+        .iter()
+        .flat_map(|r| {
+            let location = record_location(&r.record, time);
+            let mut texts = record_texts(location.clone(), &r.record);
+            if matches!(
+                r.snippet_source,
+                SnippetSource::Answer | SnippetSource::Tool
+            ) {
+                texts.push((
+                    format!("{location} {}", r.snippet_source.as_str()),
+                    r.snippet.text.as_str(),
+                ));
+            }
+            texts
+        })
+        .collect()
+}
+
+/// The texts of a session that are exported, labelled for redaction reports
+///
+/// `prefix` is put before the labels to tell sessions apart.
+fn session_texts<'a>(
+    prefix: &str,
+    session: &'a Session,
+    mark: Option<&'a MarkInfo>,
+) -> Vec<(String, Cow<'a, str>)> {
+    let label = |name: String| {
+        if prefix.is_empty() {
+            name
+        } else {
+            format!("{prefix} {name}")
+        }
+    };
+    let mut texts = vec![(label("project".to_string()), session.project().into())];
+    for (i, record) in session.records.iter().enumerate() {
+        texts.push((
+            label(format!("prompt {}", i + 1)),
+            record.display.as_str().into(),
+        ));
+        if record.project != session.project() {
+            texts.push((
+                label(format!("prompt {} project", i + 1)),
+                record.project.as_str().into(),
+            ));
+        }
+        for pasted in &record.pasted {
+            texts.push((
+                label(format!("prompt {} pasted #{}", i + 1, pasted.id)),
+                pasted.content.as_str().into(),
+            ));
+        }
+        for reply in session.replies(i) {
+            let (kind, text) = match reply {
+                Message::AssistantText { text, .. } => ("answer", text.as_str().into()),
+                Message::ToolUse { name, input, .. } => (name.as_str(), input.to_string().into()),
+                Message::ToolResult { content, .. } => ("tool result", content.as_str().into()),
+                Message::User { .. } | Message::Summary { .. } => continue,
+            };
+            texts.push((label(format!("prompt {} {kind}", i + 1)), text));
+        }
+    }
+    if let Some(transcript) = &session.transcript {
+        for summary in transcript.summaries() {
+            texts.push((label("summary".to_string()), summary.into()));
+        }
+    }
+    if let Some(title) = mark.and_then(|m| m.title.as_deref()) {
+        texts.push((label("title".to_string()), title.into()));
+    }
+    if let Some(note) = mark.and_then(|m| m.note.as_deref()) {
+        texts.push((label("note".to_string()), note.into()));
+    }
+    texts
+}
+
+/// Print what a dry run would mask, one secret per line
+fn print_redaction_report(entries: &[ReportEntry]) {
+    if entries.is_empty() {
+        println!("No secrets found");
+        return;
+    }
+    let width = entries
+        .iter()
+        .map(|e| e.location.chars().count())
+        .max()
+        .unwrap_or(0);
+    for entry in entries {
+        println!(
+            "{:width$}  {:<16}  {}",
+            entry.location, entry.rule, entry.preview
+        );
+    }
+    let noun = if entries.len() == 1 {
+        "secret"
+    } else {
+        "secrets"
+    };
+    println!("\n{} {noun} would be redacted", entries.len());
+}
+
+/// Collect tags, title and note of every marked session
+fn mark_infos(storage: &Storage) -> Result<HashMap<String, MarkInfo>, Box<dyn std::error::Error>> {
+    let mut marks = HashMap::new();
+    for favorite in storage.list_favorites()? {
+        let annotation = storage.annotation(&favorite.session_id)?;
+        let mark = MarkInfo {
+            favorited_at: favorite.favorited_at,
+            tags: storage.session_tags(&favorite.session_id)?,
+            title: annotation.title,
+            note: annotation.note,
+        };
+        marks.insert(favorite.session_id, mark);
+    }
+    Ok(marks)
+}
+
+/// Load a session from the index by full ID or unique prefix
+fn load_session(session_id: &str) -> Result<Session, Box<dyn std::error::Error>> {
+    use claude_memo::error::Error;
+
+    let indexer = open_index()?;
+    let resolver = SessionResolver::new(indexer.session_ids()?);
+    let session_id = resolver
+        .resolve(session_id)?
+        .ok_or_else(|| Error::UnknownSession(session_id.to_string()))?;
+
+    Ok(Session::from_records(indexer.session_records(session_id)?)
+        .ok_or_else(|| Error::UnknownSession(session_id.to_string()))?)
+}
+
 /// 处理 mark add 命令
-fn handle_mark_add(session_id: &str) -> Result<(), Box<dyn std::error::Error>> {
+fn handle_mark_add(
+    session_id: &str,
+    force: bool,
+    title: Option<&str>,
+    note: Option<&str>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    use claude_memo::error::Error;
+
     let mut storage = Storage::new()?;
+
+    // Store the full ID when a prefix of an indexed session was given
+    let resolver = SessionResolver::new(indexed_session_ids()?);
+    let session_id = match resolver.resolve(session_id)? {
+        Some(full_id) => full_id,
+        // Empty IDs are rejected by the storage itself
+        None if force || session_id.is_empty() => session_id,
+        None => {
+            let error = Error::UnknownSession(session_id.to_string());
+            return Err(format!("{error} (use --force to mark it anyway)").into());
+        }
+    };
+
     storage.add_favorite(session_id)?;
+    if title.is_some() {
+        storage.set_title(session_id, title)?;
+    }
+    if note.is_some() {
+        storage.set_note(session_id, note)?;
+    }
     println!("✅ Added {session_id} to marks");
     Ok(())
 }
@@ -160,45 +733,443 @@ fn handle_mark_add(session_id: &str) -> Result<(), Box<dyn std::error::Error>> {
 /// 处理 mark remove 命令
 fn handle_mark_remove(session_id: &str) -> Result<(), Box<dyn std::error::Error>> {
     let mut storage = Storage::new()?;
+
+    // Marked sessions may have left the history, so resolve against the marks
+    let resolver = SessionResolver::new(marked_session_ids(&storage)?);
+    let session_id = resolver.resolve(session_id)?.unwrap_or(session_id);
+
     storage.remove_favorite(session_id)?;
     println!("✅ Removed {session_id} from marks");
     Ok(())
 }
 
 /// 处理 marks list 命令
-fn handle_mark_list(json: bool) -> Result<(), Box<dyn std::error::Error>> {
+fn handle_mark_list(
+    config: &Config,
+    tag: Option<&str>,
+    search: Option<&str>,
+) -> Result<(), Box<dyn std::error::Error>> {
     use claude_memo::storage::FavoriteWithDetails;
 
     let storage = Storage::new()?;
-    let history_path = get_history_path();
 
-    // Get favorites enriched with session details from history
-    let favorites: Vec<FavoriteWithDetails> = storage.list_favorites_with_details(&history_path)?;
+    // Get favorites enriched with session details from the index
+    let index = updated_index()?;
+    let marked = marked_session_ids(&storage)?;
+    let latest = match &index {
+        Some(indexer) => indexer.latest_records(&marked)?,
+        None => HashMap::new(),
+    };
+    let mut favorites: Vec<FavoriteWithDetails> = storage.list_favorites_with_details(&latest)?;
+    if let Some(tag) = tag {
+        favorites.retain(|f| f.tags.iter().any(|t| t == tag));
+    }
+    if let Some(query) = search {
+        // Best matching notes first
+        let matches = storage.search_notes(query)?;
+        favorites.retain(|f| matches.contains(&f.session_id));
+        favorites.sort_by_key(|f| matches.iter().position(|id| *id == f.session_id));
+    }
 
     if favorites.is_empty() {
-        println!("No marks yet.");
+        match (tag, search) {
+            (_, Some(query)) => println!("No marks found for: {query}"),
+            (Some(tag), None) => println!("No marks tagged {tag}."),
+            (None, None) => println!("No marks yet."),
+        }
         return Ok(());
     }
 
-    if json {
+    let time = config.time_format();
+    if config.output_format == OutputFormat::Json {
         let output: Vec<serde_json::Value> = favorites
             .iter()
             .map(|f| {
                 serde_json::json!({
                     "session_id": f.session_id,
                     "favorited_at": f.favorited_at,
+                    "favorited_time": time.iso(f.favorited_at),
                     "display": f.display,
                     "project": f.project,
-                    "timestamp": f.session_timestamp
+                    "timestamp": f.session_timestamp,
+                    "time": time.iso(f.session_timestamp),
+                    "tags": f.tags,
+                    "title": f.title,
+                    "note": f.note
                 })
             })
             .collect();
         println!("{}", serde_json::to_string_pretty(&output)?);
     } else {
+        // Marks missing from the history keep their full ID, the others are
+        // abbreviated so that the prefix is unique among both
+        let indexed = match &index {
+            Some(indexer) => indexer.session_ids()?,
+            None => Vec::new(),
+        };
+        let resolver = SessionResolver::new(indexed.iter().cloned().chain(marked));
         for favorite in favorites {
-            println!("{favorite}");
+            let session_id = if indexed.contains(&favorite.session_id) {
+                resolver.abbreviate(&favorite.session_id).to_string()
+            } else {
+                favorite.session_id.clone()
+            };
+            let favorite = FavoriteWithDetails {
+                session_id,
+                ..favorite
+            };
+            println!("{}", favorite.with_time_format(&time));
+        }
+    }
+
+    Ok(())
+}
+
+/// 处理 marks --orphaned 命令
+fn handle_mark_orphaned(config: &Config, prune: bool) -> Result<(), Box<dyn std::error::Error>> {
+    use claude_memo::storage::FavoriteSession;
+    use std::collections::HashSet;
+
+    let mut storage = Storage::new()?;
+
+    // A missing history file would make every mark look orphaned
+    let indexed: HashSet<String> = open_index()?.session_ids()?.into_iter().collect();
+    let orphaned: Vec<FavoriteSession> = storage
+        .list_favorites()?
+        .into_iter()
+        .filter(|f| !indexed.contains(&f.session_id))
+        .collect();
+
+    if prune && !orphaned.is_empty() {
+        let ids: Vec<String> = orphaned.iter().map(|f| f.session_id.clone()).collect();
+        storage.remove_favorites(&ids)?;
+    }
+
+    let time = config.time_format();
+    if config.output_format == OutputFormat::Json {
+        let output: Vec<serde_json::Value> = orphaned
+            .iter()
+            .map(|f| {
+                serde_json::json!({
+                    "session_id": f.session_id,
+                    "favorited_at": f.favorited_at,
+                    "favorited_time": time.iso(f.favorited_at)
+                })
+            })
+            .collect();
+        println!("{}", serde_json::to_string_pretty(&output)?);
+    } else if orphaned.is_empty() {
+        println!("No orphaned marks.");
+    } else {
+        for favorite in &orphaned {
+            println!("{}", favorite.with_time_format(&time));
+        }
+        if prune {
+            println!("✅ Pruned {} orphaned marks", orphaned.len());
         }
     }
 
     Ok(())
 }
+
+/// 处理 note 命令
+fn handle_note(session_id: &str, message: Option<&str>) -> Result<(), Box<dyn std::error::Error>> {
+    use claude_memo::error::Error;
+
+    let mut storage = Storage::new()?;
+
+    // Like tags, a note marks the session
+    let resolver = SessionResolver::new(
+        indexed_session_ids()?
+            .into_iter()
+            .chain(marked_session_ids(&storage)?),
+    );
+    let session_id = resolver
+        .resolve(session_id)?
+        .ok_or_else(|| Error::UnknownSession(session_id.to_string()))?;
+
+    let note = match message {
+        Some(message) => message.to_string(),
+        None => {
+            let current = storage.annotation(session_id)?.note.unwrap_or_default();
+            let path = storage
+                .data_dir()
+                .join(format!("NOTE_EDITMSG.{}", process::id()));
+            edit_text(&path, &current, "note")?
+        }
+    };
+
+    if !storage.is_favorited(session_id)? {
+        storage.add_favorite(session_id)?;
+    }
+    storage.set_note(session_id, Some(&note))?;
+    if note.trim().is_empty() {
+        println!("✅ Cleared the note of {session_id}");
+    } else {
+        println!("✅ Saved the note of {session_id}");
+    }
+    Ok(())
+}
+
+/// Let the user edit text in `$VISUAL` or `$EDITOR` (default `vi`)
+///
+/// The editor command is run through the shell, like git does, so it may
+/// contain arguments.
+fn edit_text(
+    path: &std::path::Path,
+    initial: &str,
+    subject: &str,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .ok()
+        .filter(|editor| !editor.trim().is_empty())
+        .unwrap_or_else(|| "vi".to_string());
+
+    claude_memo::datadir::write_atomic(path, initial)?;
+    let status = process::Command::new("sh")
+        .arg("-c")
+        .arg(format!("{editor} \"$1\""))
+        .arg(&editor)
+        .arg(path)
+        .status();
+    let text = std::fs::read_to_string(path);
+    let _ = std::fs::remove_file(path);
+
+    let status = status.map_err(|e| format!("failed to run editor '{editor}': {e}"))?;
+    if !status.success() {
+        return Err(format!("editor '{editor}' exited with {status}, {subject} unchanged").into());
+    }
+    Ok(text?)
+}
+
+/// 处理 tag 命令
+fn handle_tag(session_id: &str, tags: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    use claude_memo::error::Error;
+
+    let mut storage = Storage::new()?;
+
+    // Tagging marks the session, so accept indexed sessions as well as marks
+    let resolver = SessionResolver::new(
+        indexed_session_ids()?
+            .into_iter()
+            .chain(marked_session_ids(&storage)?),
+    );
+    let session_id = resolver
+        .resolve(session_id)?
+        .ok_or_else(|| Error::UnknownSession(session_id.to_string()))?;
+
+    let added = storage.tag_session(session_id, tags)?;
+    if added.is_empty() {
+        println!("{session_id} already has these tags");
+    } else {
+        println!("✅ Tagged {session_id} with {}", added.join(", "));
+    }
+    Ok(())
+}
+
+/// 处理 untag 命令
+fn handle_untag(session_id: &str, tags: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    use claude_memo::error::Error;
+
+    let mut storage = Storage::new()?;
+
+    let resolver = SessionResolver::new(marked_session_ids(&storage)?);
+    let session_id = resolver
+        .resolve(session_id)?
+        .ok_or_else(|| Error::SessionNotFound(session_id.to_string()))?;
+
+    let removed = storage.untag_session(session_id, tags)?;
+    if removed.is_empty() {
+        println!("{session_id} has none of these tags");
+    } else {
+        println!("✅ Removed {} from {session_id}", removed.join(", "));
+    }
+    Ok(())
+}
+
+/// 处理 tags 命令
+fn handle_tags_list(config: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    let storage = Storage::new()?;
+    let counts = storage.tag_counts()?;
+
+    if config.output_format == OutputFormat::Json {
+        let output: Vec<serde_json::Value> = counts
+            .iter()
+            .map(|(tag, count)| serde_json::json!({ "tag": tag, "count": count }))
+            .collect();
+        println!("{}", serde_json::to_string_pretty(&output)?);
+    } else if counts.is_empty() {
+        println!("No tags yet.");
+    } else {
+        for (tag, count) in counts {
+            println!("{tag} ({count})");
+        }
+    }
+
+    Ok(())
+}
+
+/// 处理 config 命令
+fn handle_config(
+    action: &ConfigAction,
+    resolved: &Resolved,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match action {
+        ConfigAction::Get { key, show_origin } => {
+            let value = config::config_value(&resolved.config, key)?;
+            // Strings are printed bare so that scripts can use them as is
+            let text = match &value {
+                toml::Value::String(s) => s.clone(),
+                value => value.to_string(),
+            };
+            if *show_origin {
+                println!("{}\t{text}", resolved.origins[key.as_str()]);
+            } else {
+                println!("{text}");
+            }
+        }
+        ConfigAction::List { show_origin } => {
+            for key in config::KEYS {
+                let value = config::config_value(&resolved.config, key)?;
+                if *show_origin {
+                    println!("{}\t{key} = {value}", resolved.origins[key]);
+                } else {
+                    println!("{key} = {value}");
+                }
+            }
+        }
+        ConfigAction::Set { key, value } => {
+            config::edit_config_document(|document| {
+                config::set_key(document, key, value)?;
+                Ok(true)
+            })?;
+            println!("✅ Set {key}");
+            warn_if_overridden(resolved, key);
+        }
+        ConfigAction::Unset { key } => {
+            if config::edit_config_document(|document| config::unset_key(document, key))? {
+                println!("✅ Unset {key}");
+                warn_if_overridden(resolved, key);
+            } else {
+                println!("{key} is not set in the config file");
+            }
+        }
+        ConfigAction::Reset => {
+            config::reset_config()?;
+            println!(
+                "✅ Reset {} to the defaults",
+                config::get_config_path().display()
+            );
+        }
+        ConfigAction::Path => println!("{}", config::get_config_path().display()),
+        ConfigAction::Edit => {
+            let path = config::get_config_path();
+            let current = match std::fs::read_to_string(&path) {
+                Ok(content) => content,
+                Err(_) => toml::to_string_pretty(&Config::default())?,
+            };
+            let edit_path = path.with_file_name(format!("CONFIG_EDITMSG.{}.toml", process::id()));
+            let content = edit_text(&edit_path, &current, "config")?;
+            // A file that does not parse would be ignored as a whole
+            if let Err(e) = content.parse::<toml::Table>() {
+                return Err(format!("{}, config unchanged", e.message().trim()).into());
+            }
+            config::save_config_text(&content)?;
+            let check =
+                config::resolve_from(Some((&path, &content)), |_| None, &Overrides::default());
+            for warning in &check.warnings {
+                eprintln!("Warning: {warning}");
+            }
+            println!("✅ Saved {}", path.display());
+        }
+    }
+    Ok(())
+}
+
+/// Tell that a setting just changed in the file is still overridden
+fn warn_if_overridden(resolved: &Resolved, key: &str) {
+    let Some(var) = config::ENV_VARS
+        .iter()
+        .find(|(k, _)| *k == key)
+        .map(|(_, var)| var)
+    else {
+        return;
+    };
+    if std::env::var_os(var).is_some() {
+        eprintln!("Note: {key} is overridden by the environment variable {var}");
+    } else if resolved.origins.get(key) == Some(&config::Origin::Flag) {
+        eprintln!("Note: {key} is overridden by a command-line flag");
+    }
+}
+
+/// 处理 tags rename 命令
+fn handle_tags_rename(from: &str, to: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let mut storage = Storage::new()?;
+    let updated = storage.rename_tag(from, to)?;
+    println!("✅ Renamed {from} to {to} on {updated} sessions");
+    Ok(())
+}
+
+/// 处理 tags merge 命令
+fn handle_tags_merge(sources: &[String], target: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let mut storage = Storage::new()?;
+    let updated = storage.merge_tags(sources, target)?;
+    println!(
+        "✅ Merged {} into {target} on {updated} sessions",
+        sources.join(", ")
+    );
+    Ok(())
+}
+
+/// Bring the index up to date with the history file
+///
+/// Exits with code 3 when the history file does not exist.
+fn open_index() -> Result<Indexer, Box<dyn std::error::Error>> {
+    let path = get_history_path();
+
+    if !path.exists() {
+        eprintln!("Error: File not found: {}", path.display());
+        process::exit(3);
+    }
+
+    // Index only what was appended to the history file since the last run
+    let indexer = Indexer::new()?;
+    indexer.update_index(&path)?;
+    Ok(indexer)
+}
+
+/// Bring the indexed transcript messages up to date
+fn index_transcripts(indexer: &Indexer) -> Result<(), Box<dyn std::error::Error>> {
+    indexer.update_transcripts(&TranscriptStore::new(get_projects_dir()))?;
+    Ok(())
+}
+
+/// The index brought up to date, or none when there is no history file
+fn updated_index() -> Result<Option<Indexer>, Box<dyn std::error::Error>> {
+    let path = get_history_path();
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let indexer = Indexer::new()?;
+    indexer.update_index(&path)?;
+    Ok(Some(indexer))
+}
+
+/// IDs of all sessions in the history, or none when there is no history file
+fn indexed_session_ids() -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    match updated_index()? {
+        Some(indexer) => Ok(indexer.session_ids()?),
+        None => Ok(Vec::new()),
+    }
+}
+
+/// IDs of all marked sessions
+fn marked_session_ids(storage: &Storage) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    Ok(storage
+        .list_favorites()?
+        .into_iter()
+        .map(|f| f.session_id)
+        .collect())
+}
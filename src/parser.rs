@@ -1,7 +1,7 @@
 //! Parser module for processing history.jsonl files
 
-use chrono::{DateTime, TimeZone, Utc};
-use serde::Deserialize;
+use crate::timefmt::{FormatTime, TimeFormat};
+use serde::{Deserialize, Serialize};
 use std::io::BufRead;
 
 /// Represents a single Claude Code session record
@@ -15,6 +15,8 @@ pub struct SessionRecord {
     pub project: String,
     /// Unique session identifier (UUID)
     pub session_id: String,
+    /// Contents pasted into the prompt, ordered by ID
+    pub pasted: Vec<PastedContent>,
 }
 
 impl SessionRecord {
@@ -26,9 +28,26 @@ impl SessionRecord {
             timestamp,
             project,
             session_id,
+            pasted: Vec::new(),
         }
     }
 
+    /// Set the pasted contents of the record
+    pub fn with_pasted(mut self, pasted: Vec<PastedContent>) -> Self {
+        self.pasted = pasted;
+        self
+    }
+
+    /// Text of all pasted text contents, separated by blank lines
+    pub fn pasted_text(&self) -> String {
+        self.pasted
+            .iter()
+            .filter(|p| p.kind == PastedKind::Text)
+            .map(|p| p.content.as_str())
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    }
+
     /// Validate the session record
     /// Returns error if validation fails
     pub fn validate(&self) -> Result<(), crate::error::Error> {
@@ -48,16 +67,12 @@ impl SessionRecord {
     }
 }
 
-impl std::fmt::Display for SessionRecord {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let datetime: DateTime<Utc> = Utc
-            .timestamp_millis_opt(self.timestamp)
-            .single()
-            .unwrap_or(Utc::now());
+impl FormatTime for SessionRecord {
+    fn fmt_time(&self, f: &mut std::fmt::Formatter<'_>, format: &TimeFormat) -> std::fmt::Result {
         write!(
             f,
             "{} {} > {}  [{}]",
-            datetime.format("%Y-%m-%d %H:%M"),
+            format.format(self.timestamp),
             self.project,
             self.display,
             self.session_id
@@ -65,23 +80,151 @@ impl std::fmt::Display for SessionRecord {
     }
 }
 
+impl std::fmt::Display for SessionRecord {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.fmt_time(f, &TimeFormat::default())
+    }
+}
+
+/// Kind of a pasted content
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+pub enum PastedKind {
+    /// Pasted text, e.g. a log or a code snippet
+    Text,
+    /// Pasted image
+    Image,
+    /// A kind this version does not know
+    Other(String),
+}
+
+impl PastedKind {
+    /// Name of the kind as written in history.jsonl
+    pub fn as_str(&self) -> &str {
+        match self {
+            PastedKind::Text => "text",
+            PastedKind::Image => "image",
+            PastedKind::Other(kind) => kind,
+        }
+    }
+}
+
+impl From<String> for PastedKind {
+    fn from(kind: String) -> Self {
+        match kind.as_str() {
+            "text" => PastedKind::Text,
+            "image" => PastedKind::Image,
+            _ => PastedKind::Other(kind),
+        }
+    }
+}
+
+impl From<PastedKind> for String {
+    fn from(kind: PastedKind) -> Self {
+        kind.as_str().to_string()
+    }
+}
+
+/// Content pasted into a prompt
+///
+/// The prompt refers to it with a placeholder such as
+/// `[Pasted text #1 +42 lines]`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PastedContent {
+    /// Number of the paste within the prompt
+    pub id: u64,
+    /// Kind of the content
+    pub kind: PastedKind,
+    /// Pasted text; empty for images, which are not kept
+    pub content: String,
+    /// Media type of an image, e.g. `image/png`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub media_type: Option<String>,
+}
+
+impl PastedContent {
+    /// Short description, e.g. `Pasted text #1 (42 lines)`
+    pub fn label(&self) -> String {
+        match &self.kind {
+            PastedKind::Text => format!(
+                "Pasted text #{} ({} lines)",
+                self.id,
+                self.content.lines().count()
+            ),
+            PastedKind::Image => match &self.media_type {
+                Some(media_type) => format!("Pasted image #{} ({media_type})", self.id),
+                None => format!("Pasted image #{}", self.id),
+            },
+            PastedKind::Other(kind) => format!("Pasted {kind} #{}", self.id),
+        }
+    }
+}
+
 /// Raw JSON structure from history.jsonl
 #[derive(Debug, Deserialize)]
 struct RawSessionRecord {
     display: String,
-    #[serde(default)]
-    _pasted_contents: serde_json::Value,
+    #[serde(default, rename = "pastedContents")]
+    pasted_contents: serde_json::Value,
     timestamp: i64,
     project: String,
     #[serde(alias = "sessionId")]
     session_id: String,
 }
 
+/// Raw JSON structure of one entry of `pastedContents`
+#[derive(Debug, Deserialize)]
+struct RawPastedContent {
+    #[serde(default)]
+    id: Option<u64>,
+    #[serde(rename = "type", default = "default_pasted_kind")]
+    kind: String,
+    #[serde(default)]
+    content: Option<String>,
+    #[serde(rename = "mediaType", default)]
+    media_type: Option<String>,
+}
+
+fn default_pasted_kind() -> String {
+    "text".to_string()
+}
+
+/// Read the `pastedContents` object, keyed by paste number
+///
+/// Entries that cannot be read are skipped rather than failing the record.
+fn parse_pasted_contents(value: serde_json::Value) -> Vec<PastedContent> {
+    let serde_json::Value::Object(entries) = value else {
+        return Vec::new();
+    };
+
+    let mut pasted: Vec<PastedContent> = entries
+        .into_iter()
+        .filter_map(|(key, entry)| {
+            let raw: RawPastedContent = serde_json::from_value(entry).ok()?;
+            let id = raw.id.or_else(|| key.parse().ok())?;
+            let kind = PastedKind::from(raw.kind);
+            let content = match kind {
+                PastedKind::Text => raw.content.unwrap_or_default(),
+                _ => String::new(),
+            };
+            Some(PastedContent {
+                id,
+                kind,
+                content,
+                media_type: raw.media_type,
+            })
+        })
+        .collect();
+    pasted.sort_by_key(|p| p.id);
+    pasted
+}
+
 impl TryFrom<RawSessionRecord> for SessionRecord {
     type Error = crate::error::Error;
 
     fn try_from(raw: RawSessionRecord) -> Result<Self, Self::Error> {
-        let record = SessionRecord::new(raw.display, raw.timestamp, raw.project, raw.session_id);
+        let record = SessionRecord::new(raw.display, raw.timestamp, raw.project, raw.session_id)
+            .with_pasted(parse_pasted_contents(raw.pasted_contents));
         record.validate()?;
         Ok(record)
     }
@@ -137,6 +280,36 @@ mod tests {
         assert_eq!(record.session_id, "d55aaa1c-b149-4aa4-9809-7eab1dba8d4c");
     }
 
+    #[test]
+    fn test_parse_pasted_contents() {
+        let json = r#"{"display":"why? [Pasted text #2 +2 lines] [Image #1]","pastedContents":{"2":{"id":2,"type":"text","content":"error: boom\nat main.rs:3"},"1":{"id":1,"type":"image","content":"iVBORw0KGgo=","mediaType":"image/png"},"3":"broken"},"timestamp":1766567616338,"project":"/Users/elliotxx","sessionId":"abc123"}"#;
+        let record = parse_line(json).unwrap().unwrap();
+        assert_eq!(
+            record.pasted,
+            vec![
+                PastedContent {
+                    id: 1,
+                    kind: PastedKind::Image,
+                    content: String::new(),
+                    media_type: Some("image/png".to_string()),
+                },
+                PastedContent {
+                    id: 2,
+                    kind: PastedKind::Text,
+                    content: "error: boom\nat main.rs:3".to_string(),
+                    media_type: None,
+                },
+            ]
+        );
+        assert_eq!(record.pasted_text(), "error: boom\nat main.rs:3");
+        assert_eq!(record.pasted[0].label(), "Pasted image #1 (image/png)");
+        assert_eq!(record.pasted[1].label(), "Pasted text #2 (2 lines)");
+
+        // Unexpected shapes are ignored rather than failing the record
+        let json = r#"{"display":"/model","pastedContents":[],"timestamp":1766567616338,"project":"/Users/elliotxx","sessionId":"abc123"}"#;
+        assert!(parse_line(json).unwrap().unwrap().pasted.is_empty());
+    }
+
     #[test]
     fn test_parse_empty_line() {
         let result = parse_line("");
@@ -0,0 +1,833 @@
+//! Query module for parsing search query strings
+//!
+//! # Syntax
+//!
+//! - `keyword`: free text matched against the full-text index
+//! - `"exact phrase"`: consecutive words in that order
+//! - `term*`: prefix match
+//! - `a OR b`, `a AND b` (same as `a b`), `NOT a` / `-a`, `( ... )`
+//! - `project:<path>`: project path contains the value
+//! - `session:<id>`: session ID starts with the value
+//! - `tag:<name>`: session is marked with the tag
+//! - `after:<date>` / `before:<date>`: absolute time range; dates and times
+//!   without an offset are read in the configured time zone
+//! - `last:<n><unit>`: relative time range, unit is one of `s`, `m`, `h`, `d`, `w`
+//! - `tool:<name>`: tool calls and results of the tool, e.g. `tool:Bash`
+//! - `file:<path>`: tool calls and results on a file whose path contains the value
+//!
+//! Operators must be uppercase; lowercase `and`/`or`/`not` are plain words.
+//! Field filters apply to the whole query, so they cannot be negated, used
+//! inside parentheses or combined with `OR`. Values containing spaces can be
+//! quoted: `project:"/path/with space"`.
+
+use crate::timefmt::Zone;
+use chrono::{DateTime, Duration, NaiveDate, NaiveDateTime};
+
+/// Parsed search query
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Query {
+    /// Free-text expression, if any
+    pub text: Option<Expr>,
+    /// Field filters, all of which must match
+    pub filters: Vec<Filter>,
+}
+
+/// Free-text expression of a search query
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expr {
+    /// A single word, `prefix` is set for `term*`
+    Term { text: String, prefix: bool },
+    /// A quoted phrase, `prefix` is set for `"some phrase"*`
+    Phrase { text: String, prefix: bool },
+    /// All operands must match
+    And(Vec<Expr>),
+    /// At least one operand must match
+    Or(Vec<Expr>),
+    /// The operand must not match
+    Not(Box<Expr>),
+}
+
+/// Field filter in a search query
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Filter {
+    /// `project:<path>` - project path contains the value
+    Project(String),
+    /// `session:<id>` - session ID starts with the value
+    Session(String),
+    /// `tag:<name>` - session is marked with the tag
+    Tag(String),
+    /// `after:<date>` - records at or after a point in time (milliseconds)
+    After(i64),
+    /// `before:<date>` - records before a point in time (milliseconds)
+    Before(i64),
+    /// `last:<duration>` - records within a duration of the current time
+    Last(Duration),
+    /// `tool:<name>` - transcript messages of the tool (case-insensitive)
+    Tool(String),
+    /// `file:<path>` - transcript messages on a file whose path contains the value
+    File(String),
+}
+
+impl Filter {
+    /// Check whether the filter only applies to transcript messages
+    pub fn is_message_filter(&self) -> bool {
+        matches!(self, Filter::Tool(_) | Filter::File(_))
+    }
+}
+
+/// FTS5 MATCH expressions compiled from the free-text part of a query
+///
+/// FTS5 has no unary NOT, so a purely negative query such as `-model` is
+/// returned as an `exclude` expression for the caller to apply as
+/// `rowid NOT IN (... MATCH exclude)`.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct MatchExpr {
+    /// Records must match this expression
+    pub include: Option<String>,
+    /// Records must not match this expression
+    pub exclude: Option<String>,
+}
+
+impl MatchExpr {
+    /// Check whether there is nothing to match on
+    pub fn is_empty(&self) -> bool {
+        self.include.is_none() && self.exclude.is_none()
+    }
+}
+
+impl Query {
+    /// Parse a query string, reading dates in the local time zone
+    pub fn parse(input: &str) -> Result<Self, crate::error::Error> {
+        Self::parse_in(input, &Zone::Local)
+    }
+
+    /// Parse a query string, reading dates without an offset in `zone`
+    pub fn parse_in(input: &str, zone: &Zone) -> Result<Self, crate::error::Error> {
+        let mut filters = Vec::new();
+        let tokens = extract_filters(lex(input, zone)?, &mut filters)?;
+
+        let text = if tokens.is_empty() {
+            None
+        } else {
+            let mut parser = Parser { tokens, pos: 0 };
+            let expr = parser.parse_or()?;
+            if parser.pos < parser.tokens.len() {
+                return Err(invalid("unmatched ')'"));
+            }
+            Some(expr)
+        };
+
+        Ok(Query { text, filters })
+    }
+
+    /// Check whether the query has neither free text nor filters
+    pub fn is_empty(&self) -> bool {
+        self.text.is_none() && self.filters.is_empty()
+    }
+
+    /// Translate the free-text part into FTS5 MATCH syntax
+    ///
+    /// Terms and phrases are always emitted as quoted FTS5 strings, so no
+    /// user input is ever interpreted as FTS5 syntax. Terms without any
+    /// letter or digit are dropped because FTS5 rejects empty phrases.
+    pub fn to_fts5(&self) -> MatchExpr {
+        match self.text.as_ref().and_then(compile) {
+            Some(Compiled::Include(q)) => MatchExpr {
+                include: Some(q),
+                exclude: None,
+            },
+            Some(Compiled::Exclude(q)) => MatchExpr {
+                include: None,
+                exclude: Some(q),
+            },
+            None => MatchExpr::default(),
+        }
+    }
+
+    /// FTS5 expression of the terms negated at the top level, e.g. `model`
+    /// in `cargo -model`
+    ///
+    /// A search result is a prompt with its transcript messages, so a match
+    /// in a message is dropped when its prompt matches this expression.
+    pub fn excluded_fts5(&self) -> Option<String> {
+        match self.text.as_ref()? {
+            Expr::And(items) => {
+                let (_, exclude) = split_compiled(items);
+                (!exclude.is_empty()).then(|| join(exclude, "OR"))
+            }
+            expr => match compile(expr)? {
+                Compiled::Exclude(q) => Some(q),
+                Compiled::Include(_) => None,
+            },
+        }
+    }
+}
+
+fn invalid(message: &str) -> crate::error::Error {
+    crate::error::Error::InvalidQuery(message.to_string())
+}
+
+/// Lexical token of a query string
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    LParen,
+    RParen,
+    And,
+    Or,
+    Not,
+    /// `-` directly in front of a term, phrase or group
+    Minus,
+    Word {
+        text: String,
+        prefix: bool,
+    },
+    Phrase {
+        text: String,
+        prefix: bool,
+    },
+    Filter(Filter),
+}
+
+/// Split a query string into tokens
+fn lex(input: &str, zone: &Zone) -> Result<Vec<Token>, crate::error::Error> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        match chars[i] {
+            c if c.is_whitespace() => i += 1,
+            '(' => {
+                tokens.push(Token::LParen);
+                i += 1;
+            }
+            ')' => {
+                tokens.push(Token::RParen);
+                i += 1;
+            }
+            '-' if chars
+                .get(i + 1)
+                .is_some_and(|&n| n.is_alphanumeric() || n == '"' || n == '(') =>
+            {
+                tokens.push(Token::Minus);
+                i += 1;
+            }
+            '"' => {
+                let (text, next) = read_quoted(&chars, i + 1)?;
+                i = next;
+                let prefix = chars.get(i) == Some(&'*');
+                if prefix {
+                    i += 1;
+                }
+                tokens.push(Token::Phrase { text, prefix });
+            }
+            _ => {
+                // Quotes inside a word only serve to quote filter values
+                let mut text = String::new();
+                let mut quoted_from = None;
+                while i < chars.len() && !chars[i].is_whitespace() && !matches!(chars[i], '(' | ')')
+                {
+                    if chars[i] == '"' {
+                        quoted_from.get_or_insert(text.len());
+                        let (quoted, next) = read_quoted(&chars, i + 1)?;
+                        text.push_str(&quoted);
+                        i = next;
+                    } else {
+                        text.push(chars[i]);
+                        i += 1;
+                    }
+                }
+                tokens.push(word_token(text, quoted_from, zone)?);
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+/// Read a quoted string starting after the opening quote
+///
+/// Returns the content and the index just past the closing quote.
+fn read_quoted(chars: &[char], start: usize) -> Result<(String, usize), crate::error::Error> {
+    let end = chars[start..]
+        .iter()
+        .position(|&c| c == '"')
+        .ok_or_else(|| invalid("unterminated quote"))?;
+    Ok((chars[start..start + end].iter().collect(), start + end + 1))
+}
+
+/// Classify a bare word as operator, filter or term
+fn word_token(
+    mut text: String,
+    quoted_from: Option<usize>,
+    zone: &Zone,
+) -> Result<Token, crate::error::Error> {
+    if quoted_from.is_none() {
+        match text.as_str() {
+            "AND" => return Ok(Token::And),
+            "OR" => return Ok(Token::Or),
+            "NOT" => return Ok(Token::Not),
+            _ => {}
+        }
+    }
+
+    // `"a:b"` is free text, only an unquoted field name starts a filter
+    if let Some((field, value)) = text.split_once(':') {
+        if quoted_from.is_none_or(|q| q > field.len()) {
+            if let Some(filter) = parse_filter(field, value, zone)? {
+                return Ok(Token::Filter(filter));
+            }
+        }
+    }
+
+    let prefix = quoted_from.is_none() && text.ends_with('*');
+    if prefix {
+        text.pop();
+    }
+    Ok(Token::Word { text, prefix })
+}
+
+/// Move top-level filters out of the token stream
+fn extract_filters(
+    tokens: Vec<Token>,
+    filters: &mut Vec<Filter>,
+) -> Result<Vec<Token>, crate::error::Error> {
+    let mut rest: Vec<Token> = Vec::with_capacity(tokens.len());
+    let mut depth = 0usize;
+    let mut skip_and = false;
+    let mut iter = tokens.into_iter().peekable();
+
+    while let Some(token) = iter.next() {
+        match token {
+            Token::Filter(filter) => {
+                if depth > 0 {
+                    return Err(invalid("field filters cannot be used inside parentheses"));
+                }
+                match rest.last() {
+                    Some(Token::Minus | Token::Not) => {
+                        return Err(invalid("field filters cannot be negated"))
+                    }
+                    Some(Token::Or) => {
+                        return Err(invalid("field filters cannot be combined with OR"))
+                    }
+                    Some(Token::And) => {
+                        rest.pop();
+                    }
+                    _ => skip_and = true,
+                }
+                if iter.peek() == Some(&Token::Or) {
+                    return Err(invalid("field filters cannot be combined with OR"));
+                }
+                filters.push(filter);
+                continue;
+            }
+            Token::And if skip_and => {}
+            Token::LParen => {
+                depth += 1;
+                rest.push(token);
+            }
+            Token::RParen => {
+                depth = depth.saturating_sub(1);
+                rest.push(token);
+            }
+            _ => rest.push(token),
+        }
+        skip_and = false;
+    }
+
+    Ok(rest)
+}
+
+/// Recursive-descent parser: OR binds loosest, then AND, then NOT
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn parse_or(&mut self) -> Result<Expr, crate::error::Error> {
+        let mut items = vec![self.parse_and()?];
+        while self.peek() == Some(&Token::Or) {
+            self.pos += 1;
+            items.push(self.parse_and()?);
+        }
+        Ok(if items.len() == 1 {
+            items.remove(0)
+        } else {
+            Expr::Or(items)
+        })
+    }
+
+    fn parse_and(&mut self) -> Result<Expr, crate::error::Error> {
+        let mut items = vec![self.parse_unary()?];
+        loop {
+            match self.peek() {
+                None | Some(Token::Or | Token::RParen) => break,
+                Some(Token::And) => {
+                    self.pos += 1;
+                    items.push(self.parse_unary()?);
+                }
+                Some(_) => items.push(self.parse_unary()?),
+            }
+        }
+        Ok(if items.len() == 1 {
+            items.remove(0)
+        } else {
+            Expr::And(items)
+        })
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, crate::error::Error> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+
+        match token {
+            Some(Token::Not | Token::Minus) => Ok(Expr::Not(Box::new(self.parse_unary()?))),
+            Some(Token::LParen) => {
+                if self.peek() == Some(&Token::RParen) {
+                    return Err(invalid("empty parentheses"));
+                }
+                let expr = self.parse_or()?;
+                if self.peek() != Some(&Token::RParen) {
+                    return Err(invalid("missing ')'"));
+                }
+                self.pos += 1;
+                Ok(expr)
+            }
+            Some(Token::Word { text, prefix }) => Ok(Expr::Term { text, prefix }),
+            Some(Token::Phrase { text, prefix }) => Ok(Expr::Phrase { text, prefix }),
+            Some(Token::RParen) => Err(invalid("unmatched ')'")),
+            Some(Token::And) => Err(invalid("AND must be placed between two terms")),
+            Some(Token::Or) => Err(invalid("OR must be placed between two terms")),
+            Some(Token::Filter(_)) => Err(invalid("unexpected field filter")),
+            None => Err(invalid("query ends with an operator")),
+        }
+    }
+}
+
+/// Free-text expression compiled to FTS5, either as a positive match or as
+/// a match that must be excluded
+enum Compiled {
+    Include(String),
+    Exclude(String),
+}
+
+/// Compile an expression, returning `None` if nothing matchable is left
+fn compile(expr: &Expr) -> Option<Compiled> {
+    match expr {
+        Expr::Term { text, prefix } | Expr::Phrase { text, prefix } => {
+            fts5_string(text, *prefix).map(Compiled::Include)
+        }
+        Expr::Not(inner) => match compile(inner)? {
+            Compiled::Include(q) => Some(Compiled::Exclude(q)),
+            Compiled::Exclude(q) => Some(Compiled::Include(q)),
+        },
+        Expr::And(items) => {
+            let (include, exclude) = split_compiled(items);
+            match (include.is_empty(), exclude.is_empty()) {
+                (true, true) => None,
+                // NOT a AND NOT b == NOT (a OR b)
+                (true, false) => Some(Compiled::Exclude(join(exclude, "OR"))),
+                (false, _) => {
+                    let mut q = join(include, "AND");
+                    for e in exclude {
+                        q = format!("({q} NOT {e})");
+                    }
+                    Some(Compiled::Include(q))
+                }
+            }
+        }
+        Expr::Or(items) => {
+            let (include, exclude) = split_compiled(items);
+            match (include.is_empty(), exclude.is_empty()) {
+                (true, true) => None,
+                (false, true) => Some(Compiled::Include(join(include, "OR"))),
+                // a OR NOT b == NOT (b AND NOT a)
+                (true, false) => Some(Compiled::Exclude(join(exclude, "AND"))),
+                (false, false) => Some(Compiled::Exclude(format!(
+                    "({} NOT {})",
+                    join(exclude, "AND"),
+                    join(include, "OR")
+                ))),
+            }
+        }
+    }
+}
+
+fn split_compiled(items: &[Expr]) -> (Vec<String>, Vec<String>) {
+    let mut include = Vec::new();
+    let mut exclude = Vec::new();
+    for item in items.iter().filter_map(compile) {
+        match item {
+            Compiled::Include(q) => include.push(q),
+            Compiled::Exclude(q) => exclude.push(q),
+        }
+    }
+    (include, exclude)
+}
+
+fn join(mut parts: Vec<String>, operator: &str) -> String {
+    if parts.len() == 1 {
+        return parts.remove(0);
+    }
+    format!("({})", parts.join(&format!(" {operator} ")))
+}
+
+/// Quote text as an FTS5 string, dropping text without letters or digits
+fn fts5_string(text: &str, prefix: bool) -> Option<String> {
+    if !text.chars().any(char::is_alphanumeric) {
+        return None;
+    }
+    let star = if prefix { "*" } else { "" };
+    Some(format!("\"{}\"{star}", text.replace('"', "\"\"")))
+}
+
+/// Parse `field:value`, returning `None` if the field is not a known filter
+fn parse_filter(
+    field: &str,
+    value: &str,
+    zone: &Zone,
+) -> Result<Option<Filter>, crate::error::Error> {
+    let filter = match field {
+        "project" => Filter::Project(require_value(field, value)?.to_string()),
+        "session" => Filter::Session(require_value(field, value)?.to_string()),
+        "tag" => Filter::Tag(require_value(field, value)?.to_string()),
+        "after" => Filter::After(parse_time(field, require_value(field, value)?, zone)?),
+        "before" => Filter::Before(parse_time(field, require_value(field, value)?, zone)?),
+        "last" => Filter::Last(parse_duration(require_value(field, value)?)?),
+        "tool" => Filter::Tool(require_value(field, value)?.to_string()),
+        "file" => Filter::File(require_value(field, value)?.to_string()),
+        _ => return Ok(None),
+    };
+    Ok(Some(filter))
+}
+
+fn require_value<'a>(field: &str, value: &'a str) -> Result<&'a str, crate::error::Error> {
+    if value.is_empty() {
+        return Err(crate::error::Error::InvalidQuery(format!(
+            "missing value for {field}:"
+        )));
+    }
+    Ok(value)
+}
+
+/// Parse an absolute time: `2026-01-29`, `2026-01-29T10:30[:00]` or RFC 3339
+///
+/// Dates and times without an offset are read in `zone`.
+fn parse_time(field: &str, value: &str, zone: &Zone) -> Result<i64, crate::error::Error> {
+    if let Ok(datetime) = DateTime::parse_from_rfc3339(value) {
+        return Ok(datetime.timestamp_millis());
+    }
+    for format in ["%Y-%m-%dT%H:%M:%S", "%Y-%m-%dT%H:%M", "%Y-%m-%d %H:%M"] {
+        if let Ok(datetime) = NaiveDateTime::parse_from_str(value, format) {
+            return Ok(zone.resolve(&datetime).timestamp_millis());
+        }
+    }
+    if let Ok(date) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        let start = date.and_hms_opt(0, 0, 0).expect("midnight is valid");
+        return Ok(zone.resolve(&start).timestamp_millis());
+    }
+
+    Err(crate::error::Error::InvalidQuery(format!(
+        "invalid date for {field}: '{value}' (expected YYYY-MM-DD or YYYY-MM-DDTHH:MM)"
+    )))
+}
+
+/// Parse a relative duration such as `30m`, `1h`, `7d` or `2w`
+pub fn parse_duration(value: &str) -> Result<Duration, crate::error::Error> {
+    let invalid = || {
+        crate::error::Error::InvalidQuery(format!(
+            "invalid duration: '{value}' (expected e.g. 30m, 1h, 7d, 2w)"
+        ))
+    };
+
+    let split = value
+        .find(|c: char| !c.is_ascii_digit())
+        .ok_or_else(invalid)?;
+    let (amount, unit) = value.split_at(split);
+    let amount: i64 = amount.parse().map_err(|_| invalid())?;
+
+    let duration = match unit {
+        "s" => Duration::try_seconds(amount),
+        "m" => Duration::try_minutes(amount),
+        "h" => Duration::try_hours(amount),
+        "d" => Duration::try_days(amount),
+        "w" => Duration::try_weeks(amount),
+        _ => None,
+    };
+    duration.ok_or_else(invalid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn term(text: &str) -> Expr {
+        Expr::Term {
+            text: text.to_string(),
+            prefix: false,
+        }
+    }
+
+    fn fts5(input: &str) -> MatchExpr {
+        Query::parse(input).unwrap().to_fts5()
+    }
+
+    fn include(q: &str) -> MatchExpr {
+        MatchExpr {
+            include: Some(q.to_string()),
+            exclude: None,
+        }
+    }
+
+    #[test]
+    fn test_excluded_fts5() {
+        let excluded = |input: &str| Query::parse(input).unwrap().excluded_fts5();
+        assert_eq!(excluded("cargo -model"), Some(r#""model""#.to_string()));
+        assert_eq!(
+            excluded("cargo -model -test"),
+            Some(r#"("model" OR "test")"#.to_string())
+        );
+        assert_eq!(excluded("-model"), Some(r#""model""#.to_string()));
+        assert_eq!(excluded("cargo test"), None);
+        assert_eq!(excluded("cargo OR test"), None);
+    }
+
+    #[test]
+    fn test_parse_free_text() {
+        let query = Query::parse("cargo  test").unwrap();
+        assert_eq!(
+            query.text,
+            Some(Expr::And(vec![term("cargo"), term("test")]))
+        );
+        assert!(query.filters.is_empty());
+    }
+
+    #[test]
+    fn test_parse_field_filters() {
+        let query = Query::parse(
+            "project:/Users/elliotxx/workspace session:abc123 tag:refactor last:7d keyword",
+        )
+        .unwrap();
+        assert_eq!(query.text, Some(term("keyword")));
+        assert_eq!(
+            query.filters,
+            vec![
+                Filter::Project("/Users/elliotxx/workspace".to_string()),
+                Filter::Session("abc123".to_string()),
+                Filter::Tag("refactor".to_string()),
+                Filter::Last(Duration::days(7)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_message_filters() {
+        let query = Query::parse("tool:Bash file:src/main.rs cargo").unwrap();
+        assert_eq!(query.text, Some(term("cargo")));
+        assert_eq!(
+            query.filters,
+            vec![
+                Filter::Tool("Bash".to_string()),
+                Filter::File("src/main.rs".to_string()),
+            ]
+        );
+        assert!(query.filters.iter().all(Filter::is_message_filter));
+        assert!(Query::parse("tool:").is_err());
+    }
+
+    #[test]
+    fn test_parse_quoted_value() {
+        let query = Query::parse(r#"project:"/Users/me/my project" fix"#).unwrap();
+        assert_eq!(
+            query.filters,
+            vec![Filter::Project("/Users/me/my project".to_string())]
+        );
+        assert_eq!(query.text, Some(term("fix")));
+    }
+
+    #[test]
+    fn test_parse_absolute_dates() {
+        let query =
+            Query::parse_in("after:2026-01-01 before:2026-01-29T10:30", &Zone::Utc).unwrap();
+        assert_eq!(
+            query.filters,
+            vec![Filter::After(1767225600000), Filter::Before(1769682600000),]
+        );
+    }
+
+    #[test]
+    fn test_parse_dates_in_zone() {
+        let shanghai = Zone::parse("Asia/Shanghai").unwrap();
+        let query = Query::parse_in(
+            "after:2026-01-02 before:2026-01-29T10:30 after:2026-01-02T00:00:00Z",
+            &shanghai,
+        )
+        .unwrap();
+        assert_eq!(
+            query.filters,
+            vec![
+                // 2026-01-01T16:00Z and 2026-01-29T02:30Z
+                Filter::After(1767283200000),
+                Filter::Before(1769653800000),
+                // An explicit offset wins over the zone
+                Filter::After(1767312000000),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_durations() {
+        for (input, expected) in [
+            ("last:30s", Duration::seconds(30)),
+            ("last:15m", Duration::minutes(15)),
+            ("last:1h", Duration::hours(1)),
+            ("last:2w", Duration::weeks(2)),
+        ] {
+            let query = Query::parse(input).unwrap();
+            assert_eq!(query.filters, vec![Filter::Last(expected)]);
+        }
+    }
+
+    #[test]
+    fn test_unknown_field_is_free_text() {
+        let query = Query::parse("http://example.com").unwrap();
+        assert_eq!(query.text, Some(term("http://example.com")));
+        assert!(query.filters.is_empty());
+    }
+
+    #[test]
+    fn test_quoted_colon_is_free_text() {
+        let query = Query::parse(r#""project:foo""#).unwrap();
+        assert_eq!(
+            query.text,
+            Some(Expr::Phrase {
+                text: "project:foo".to_string(),
+                prefix: false
+            })
+        );
+        assert!(query.filters.is_empty());
+    }
+
+    #[test]
+    fn test_invalid_filters() {
+        for input in [
+            "last:7",
+            "last:7y",
+            "last:d",
+            "after:yesterday",
+            "project:",
+            r#"project:"unterminated"#,
+        ] {
+            let result = Query::parse(input);
+            assert!(result.is_err(), "expected error for {input}");
+            assert!(result.unwrap_err().to_string().contains("Invalid query"));
+        }
+    }
+
+    #[test]
+    fn test_empty_query() {
+        assert!(Query::parse("   ").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_fts5_terms_and_phrases() {
+        assert_eq!(fts5("cargo"), include(r#""cargo""#));
+        assert_eq!(fts5("carg*"), include(r#""carg"*"#));
+        assert_eq!(fts5(r#""cargo test""#), include(r#""cargo test""#));
+        assert_eq!(fts5(r#""cargo te"*"#), include(r#""cargo te"*"#));
+        assert_eq!(fts5("cargo test"), include(r#"("cargo" AND "test")"#));
+    }
+
+    #[test]
+    fn test_fts5_boolean_operators() {
+        assert_eq!(fts5("a OR b"), include(r#"("a" OR "b")"#));
+        assert_eq!(fts5("a AND b"), include(r#"("a" AND "b")"#));
+        assert_eq!(fts5("a b OR c"), include(r#"(("a" AND "b") OR "c")"#));
+        assert_eq!(fts5("a (b OR c)"), include(r#"("a" AND ("b" OR "c"))"#));
+        // Lowercase operators are plain words
+        assert_eq!(fts5("a or b"), include(r#"("a" AND "or" AND "b")"#));
+    }
+
+    #[test]
+    fn test_fts5_exclusion() {
+        assert_eq!(fts5("cargo -model"), include(r#"("cargo" NOT "model")"#));
+        assert_eq!(fts5("cargo NOT model"), include(r#"("cargo" NOT "model")"#));
+        assert_eq!(
+            fts5("-model"),
+            MatchExpr {
+                include: None,
+                exclude: Some(r#""model""#.to_string()),
+            }
+        );
+        assert_eq!(
+            fts5("-a -b"),
+            MatchExpr {
+                include: None,
+                exclude: Some(r#"("a" OR "b")"#.to_string()),
+            }
+        );
+        assert_eq!(fts5("NOT NOT a"), include(r#""a""#));
+    }
+
+    #[test]
+    fn test_fts5_escapes_user_input() {
+        // FTS5 syntax inside terms stays literal
+        assert_eq!(fts5("a:b"), include(r#""a:b""#));
+        assert_eq!(fts5("^foo+bar"), include(r#""^foo+bar""#));
+        assert_eq!(fts5("--option"), include(r#""--option""#));
+        assert_eq!(fts5("NEAR (a b)"), include(r#"("NEAR" AND ("a" AND "b"))"#));
+    }
+
+    #[test]
+    fn test_fts5_drops_punctuation_only_terms() {
+        assert_eq!(fts5("- * --"), MatchExpr::default());
+        assert_eq!(fts5("cargo -- test"), include(r#"("cargo" AND "test")"#));
+    }
+
+    #[test]
+    fn test_filters_with_boolean_text() {
+        let query = Query::parse("project:app a AND b last:1h").unwrap();
+        assert_eq!(query.text, Some(Expr::And(vec![term("a"), term("b")])));
+        assert_eq!(query.filters.len(), 2);
+
+        let query = Query::parse("a AND project:app").unwrap();
+        assert_eq!(query.text, Some(term("a")));
+    }
+
+    #[test]
+    fn test_malformed_queries() {
+        for (input, message) in [
+            ("(a", "missing ')'"),
+            ("a)", "unmatched ')'"),
+            ("()", "empty parentheses"),
+            ("a OR", "query ends with an operator"),
+            ("OR a", "OR must be placed between two terms"),
+            ("a AND AND b", "AND must be placed between two terms"),
+            ("NOT", "query ends with an operator"),
+            (r#""cargo test"#, "unterminated quote"),
+            ("-project:app", "field filters cannot be negated"),
+            (
+                "(a project:app)",
+                "field filters cannot be used inside parentheses",
+            ),
+            (
+                "a OR project:app",
+                "field filters cannot be combined with OR",
+            ),
+            (
+                "project:app OR a",
+                "field filters cannot be combined with OR",
+            ),
+        ] {
+            let result = Query::parse(input);
+            assert!(result.is_err(), "expected error for {input}");
+            let err = result.unwrap_err().to_string();
+            assert!(err.contains(message), "{input}: {err}");
+        }
+    }
+}
@@ -0,0 +1,205 @@
+//! Resolver module for abbreviated session IDs
+//!
+//! Session IDs are 36-character UUIDs. Like git short hashes, any prefix
+//! that matches exactly one known session can be used instead, and text
+//! output shows the shortest unique prefix (at least [`MIN_PREFIX_LEN`]
+//! characters).
+
+use crate::error::Error;
+
+/// Shortest prefix shown in text output, the first group of a UUID
+pub const MIN_PREFIX_LEN: usize = 8;
+
+/// Maximum number of candidates listed for an ambiguous prefix
+const MAX_CANDIDATES: usize = 10;
+
+/// Set of known session IDs that prefixes are resolved against
+#[derive(Debug, Clone, Default)]
+pub struct SessionResolver {
+    /// Known IDs, sorted and deduplicated
+    ids: Vec<String>,
+}
+
+impl SessionResolver {
+    /// Create a resolver over a set of session IDs
+    pub fn new(ids: impl IntoIterator<Item = String>) -> Self {
+        let mut ids: Vec<String> = ids.into_iter().collect();
+        ids.sort();
+        ids.dedup();
+        Self { ids }
+    }
+
+    /// Resolve a full ID or unique prefix to the full session ID
+    ///
+    /// An exact match always wins. Returns `None` when nothing matches and
+    /// an error listing the candidates when the prefix is ambiguous.
+    pub fn resolve(&self, input: &str) -> Result<Option<&str>, Error> {
+        if input.is_empty() {
+            return Ok(None);
+        }
+
+        let candidates = self.matching(input);
+        if let Some(exact) = candidates.iter().find(|id| id.as_str() == input) {
+            return Ok(Some(exact));
+        }
+
+        match candidates {
+            [] => Ok(None),
+            [id] => Ok(Some(id)),
+            _ => {
+                let mut listed: Vec<String> = candidates
+                    .iter()
+                    .take(MAX_CANDIDATES)
+                    .map(|id| id.to_string())
+                    .collect();
+                if candidates.len() > MAX_CANDIDATES {
+                    listed.push(format!(
+                        "... and {} more",
+                        candidates.len() - MAX_CANDIDATES
+                    ));
+                }
+                Err(Error::AmbiguousSessionId {
+                    prefix: input.to_string(),
+                    candidates: listed,
+                })
+            }
+        }
+    }
+
+    /// Shortest prefix of an ID that no other known ID starts with
+    ///
+    /// Unknown IDs and IDs shorter than [`MIN_PREFIX_LEN`] are returned whole.
+    pub fn abbreviate<'a>(&self, id: &'a str) -> &'a str {
+        let Ok(index) = self.ids.binary_search_by(|probe| probe.as_str().cmp(id)) else {
+            return id;
+        };
+
+        // In sorted order, the IDs sharing the longest prefix are the neighbours
+        let shared = [index.checked_sub(1), Some(index + 1)]
+            .into_iter()
+            .flatten()
+            .filter_map(|i| self.ids.get(i))
+            .map(|other| common_prefix_len(id, other))
+            .max()
+            .unwrap_or(0);
+
+        let len = (shared + 1).max(MIN_PREFIX_LEN);
+        match id.char_indices().nth(len) {
+            Some((cut, _)) => &id[..cut],
+            None => id,
+        }
+    }
+
+    /// Known IDs starting with a prefix
+    fn matching(&self, prefix: &str) -> &[String] {
+        let start = self.ids.partition_point(|id| id.as_str() < prefix);
+        let len = self.ids[start..]
+            .iter()
+            .take_while(|id| id.starts_with(prefix))
+            .count();
+        &self.ids[start..start + len]
+    }
+}
+
+/// Number of leading characters two strings have in common
+fn common_prefix_len(a: &str, b: &str) -> usize {
+    a.chars().zip(b.chars()).take_while(|(x, y)| x == y).count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resolver() -> SessionResolver {
+        SessionResolver::new(
+            [
+                "d55aaa1c-b149-4aa4-9809-7eab1dba8d4c",
+                "d55aaa1c-f000-4aa4-9809-7eab1dba8d4c",
+                "0b1e2c3d-1111-2222-3333-444455556666",
+                "abc",
+                "abcdef12-0000",
+            ]
+            .map(String::from),
+        )
+    }
+
+    #[test]
+    fn test_resolve_unique_prefix() {
+        let resolver = resolver();
+        assert_eq!(
+            resolver.resolve("0b1e").unwrap(),
+            Some("0b1e2c3d-1111-2222-3333-444455556666")
+        );
+        assert_eq!(
+            resolver.resolve("d55aaa1c-b").unwrap(),
+            Some("d55aaa1c-b149-4aa4-9809-7eab1dba8d4c")
+        );
+    }
+
+    #[test]
+    fn test_resolve_exact_match_wins() {
+        // "abc" is also a prefix of "abcdef12-0000"
+        assert_eq!(resolver().resolve("abc").unwrap(), Some("abc"));
+    }
+
+    #[test]
+    fn test_resolve_unknown() {
+        assert_eq!(resolver().resolve("ffff").unwrap(), None);
+        assert_eq!(resolver().resolve("").unwrap(), None);
+    }
+
+    #[test]
+    fn test_resolve_ambiguous_lists_candidates() {
+        let err = resolver().resolve("d55").unwrap_err();
+        match &err {
+            Error::AmbiguousSessionId { prefix, candidates } => {
+                assert_eq!(prefix, "d55");
+                assert_eq!(candidates.len(), 2);
+            }
+            other => panic!("unexpected error: {other}"),
+        }
+        let message = err.to_string();
+        assert!(message.contains("d55aaa1c-b149-4aa4-9809-7eab1dba8d4c"));
+        assert!(message.contains("d55aaa1c-f000-4aa4-9809-7eab1dba8d4c"));
+    }
+
+    #[test]
+    fn test_resolve_ambiguous_truncates_candidates() {
+        let resolver = SessionResolver::new((0..15).map(|i| format!("session-{i:02}")));
+        let Err(Error::AmbiguousSessionId { candidates, .. }) = resolver.resolve("session") else {
+            panic!("expected an ambiguous prefix");
+        };
+        assert_eq!(candidates.len(), MAX_CANDIDATES + 1);
+        assert_eq!(candidates.last().unwrap(), "... and 5 more");
+    }
+
+    #[test]
+    fn test_abbreviate() {
+        let resolver = resolver();
+        assert_eq!(
+            resolver.abbreviate("0b1e2c3d-1111-2222-3333-444455556666"),
+            "0b1e2c3d"
+        );
+        // Shares "d55aaa1c-" with another session
+        assert_eq!(
+            resolver.abbreviate("d55aaa1c-b149-4aa4-9809-7eab1dba8d4c"),
+            "d55aaa1c-b"
+        );
+        assert_eq!(resolver.abbreviate("abc"), "abc");
+        assert_eq!(
+            resolver.abbreviate("unknown-session-id"),
+            "unknown-session-id"
+        );
+    }
+
+    #[test]
+    fn test_abbreviated_ids_resolve_back() {
+        let resolver = resolver();
+        for id in resolver.ids.clone() {
+            assert_eq!(
+                resolver.resolve(resolver.abbreviate(&id)).unwrap(),
+                Some(id.as_str())
+            );
+        }
+    }
+}
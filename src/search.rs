@@ -1,58 +1,454 @@
 //! Search module for full-text search functionality
+//!
+//! Prompts are searched in `records`, the answers and tool activity of
+//! session transcripts in `messages`. A message that matches is reported
+//! as the prompt it followed, with the snippet taken from the message.
 
+use crate::indexer::{decode_pasted, SessionSummary};
 use crate::parser::SessionRecord;
+use crate::query::{Filter, MatchExpr, Query};
+use crate::timefmt::Zone;
+use crate::tokenizer::contains_cjk;
 use crate::Result;
-use rusqlite::{params, Connection};
+use chrono::{Duration, Utc};
+use rusqlite::types::Value;
+use rusqlite::{params, params_from_iter, Connection};
+use std::collections::HashMap;
+use std::fmt;
 use std::path::PathBuf;
+use std::str::FromStr;
+
+/// Escape `%`, `_` and `\` for use in a `LIKE ... ESCAPE '\'` pattern
+fn escape_like(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, '%' | '_' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Compile query filters into SQL conditions on `records r`
+///
+/// `tags` maps each tag to the IDs of the sessions marked with it. Message
+/// filters become conditions on `messages m`, so queries with them only
+/// run against transcript messages.
+fn compile_filters(
+    filters: &[Filter],
+    tags: &HashMap<String, Vec<String>>,
+    conditions: &mut Vec<String>,
+    values: &mut Vec<Value>,
+) {
+    for filter in filters {
+        match filter {
+            Filter::Project(project) => {
+                conditions.push("r.project LIKE ? ESCAPE '\\'".to_string());
+                values.push(Value::Text(format!("%{}%", escape_like(project))));
+            }
+            Filter::Session(prefix) => {
+                conditions.push("r.session_id LIKE ? ESCAPE '\\'".to_string());
+                values.push(Value::Text(format!("{}%", escape_like(prefix))));
+            }
+            Filter::Tag(tag) => {
+                conditions.push("r.session_id IN (SELECT value FROM json_each(?))".to_string());
+                let sessions = tags.get(tag).map(Vec::as_slice).unwrap_or_default();
+                values.push(Value::Text(serde_json::json!(sessions).to_string()));
+            }
+            Filter::After(millis) => {
+                conditions.push("r.timestamp >= ?".to_string());
+                values.push(Value::Integer(*millis));
+            }
+            Filter::Before(millis) => {
+                conditions.push("r.timestamp < ?".to_string());
+                values.push(Value::Integer(*millis));
+            }
+            Filter::Last(duration) => {
+                conditions.push("r.timestamp >= ?".to_string());
+                let since = Utc::now() - *duration;
+                values.push(Value::Integer(since.timestamp_millis()));
+            }
+            Filter::Tool(tool) => {
+                conditions.push("m.tool = ? COLLATE NOCASE".to_string());
+                values.push(Value::Text(tool.clone()));
+            }
+            Filter::File(path) => {
+                conditions.push("m.files LIKE ? ESCAPE '\\'".to_string());
+                values.push(Value::Text(format!("%{}%", escape_like(path))));
+            }
+        }
+    }
+}
 
-/// Sanitize a query string for FTS5
-/// FTS5 doesn't support certain special characters
-/// Returns empty string if query is invalid, otherwise returns sanitized query
-fn sanitize_fts5_query(query: &str) -> String {
-    // Characters that need to be escaped or removed for FTS5
-    // FTS5 special characters: ", ', (, ), {, }, [, ], -, ., |, *, ?, ~
-    let problematic: &[char] = &[
-        '"', '\'', '(', ')', '{', '}', '[', ']', '-', '.', '|', '*', '?', '~',
+/// How search results are ordered
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RankMode {
+    /// Best BM25 match first
+    Relevance,
+    /// Newest record first
+    #[default]
+    Recent,
+    /// BM25 score weighted by an exponential decay of the record's age
+    Hybrid,
+}
+
+impl RankMode {
+    /// All modes, in the order they are listed in help texts
+    pub const ALL: [RankMode; 3] = [RankMode::Relevance, RankMode::Recent, RankMode::Hybrid];
+
+    /// Name of the mode as accepted by [`FromStr`]
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RankMode::Relevance => "relevance",
+            RankMode::Recent => "recent",
+            RankMode::Hybrid => "hybrid",
+        }
+    }
+}
+
+impl fmt::Display for RankMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for RankMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        RankMode::ALL
+            .into_iter()
+            .find(|mode| mode.as_str() == s)
+            .ok_or_else(|| {
+                format!("unknown rank mode '{s}' (expected relevance, recent or hybrid)")
+            })
+    }
+}
+
+/// Which part of the records a query matches
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SearchScope {
+    /// Prompts, pasted contents, projects and transcript messages
+    #[default]
+    All,
+    /// Prompt text only
+    Prompts,
+    /// Pasted contents only
+    Pasted,
+    /// Answers of Claude in the transcripts
+    Answers,
+    /// Tool calls and their results in the transcripts
+    Tools,
+}
+
+impl SearchScope {
+    /// All scopes, in the order they are listed in help texts
+    pub const ALL: [SearchScope; 5] = [
+        SearchScope::All,
+        SearchScope::Prompts,
+        SearchScope::Pasted,
+        SearchScope::Answers,
+        SearchScope::Tools,
     ];
 
-    let mut result = String::new();
-    let mut has_valid_char = false;
+    /// Name of the scope as accepted by [`FromStr`]
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SearchScope::All => "all",
+            SearchScope::Prompts => "prompts",
+            SearchScope::Pasted => "pasted",
+            SearchScope::Answers => "answers",
+            SearchScope::Tools => "tools",
+        }
+    }
+
+    /// Check whether the scope covers the prompts of the history
+    pub fn includes_records(&self) -> bool {
+        matches!(
+            self,
+            SearchScope::All | SearchScope::Prompts | SearchScope::Pasted
+        )
+    }
+
+    /// Check whether the scope covers transcript messages
+    pub fn includes_messages(&self) -> bool {
+        matches!(
+            self,
+            SearchScope::All | SearchScope::Answers | SearchScope::Tools
+        )
+    }
 
-    for c in query.chars() {
-        if problematic.contains(&c) {
-            // Skip problematic characters
-            continue;
+    /// Restrict an FTS5 expression to the columns of the scope
+    fn restrict(&self, expr: String) -> String {
+        match self {
+            SearchScope::All => expr,
+            SearchScope::Prompts => format!("display : ({expr})"),
+            SearchScope::Pasted => format!("pasted : ({expr})"),
+            SearchScope::Answers | SearchScope::Tools => expr,
         }
-        // Also skip control characters
-        if c.is_control() {
-            continue;
+    }
+}
+
+impl fmt::Display for SearchScope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for SearchScope {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        SearchScope::ALL
+            .into_iter()
+            .find(|scope| scope.as_str() == s)
+            .ok_or_else(|| {
+                format!(
+                    "unknown search scope '{s}' (expected all, prompts, pasted, answers or tools)"
+                )
+            })
+    }
+}
+
+/// Where the snippet of a search result was taken from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SnippetSource {
+    /// The prompt text
+    #[default]
+    Prompt,
+    /// The pasted contents, when only they match
+    Pasted,
+    /// An answer that followed the prompt
+    Answer,
+    /// A tool call or result that followed the prompt
+    Tool,
+}
+
+impl SnippetSource {
+    /// Name of the source in JSON output
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SnippetSource::Prompt => "prompt",
+            SnippetSource::Pasted => "pasted",
+            SnippetSource::Answer => "answer",
+            SnippetSource::Tool => "tool",
         }
-        result.push(c);
-        if c.is_alphanumeric() || c == '_' {
-            has_valid_char = true;
+    }
+}
+
+/// Default half-life of the recency decay in hybrid ranking
+pub fn default_half_life() -> Duration {
+    Duration::days(7)
+}
+
+/// Weight of a record's age: 1.0 now, 0.5 after one half-life, and so on
+///
+/// Records from the future (clock skew) are not boosted above 1.0.
+fn recency_decay(timestamp: i64, now: i64, half_life: Duration) -> f64 {
+    let half_life = half_life.num_milliseconds();
+    if half_life <= 0 {
+        return 1.0;
+    }
+    let age = (now - timestamp).max(0) as f64;
+    0.5f64.powf(age / half_life as f64)
+}
+
+/// Marks the start of a match in `highlight()` and `snippet()` output
+const MATCH_START: char = '\u{2}';
+/// Marks the end of a match in `highlight()` and `snippet()` output
+const MATCH_END: char = '\u{3}';
+/// BM25 weight of the prompt text
+const PROMPT_WEIGHT: f64 = 100.0;
+/// BM25 weight of the pasted contents, lower than the prompt because pasted
+/// logs are long and mention many terms in passing
+const PASTED_WEIGHT: f64 = 30.0;
+/// BM25 weight of transcript message text, lower than the prompt because
+/// answers and tool output are long
+const MESSAGE_WEIGHT: f64 = 30.0;
+/// BM25 weight of the tool name of a transcript message
+const TOOL_NAME_WEIGHT: f64 = 30.0;
+/// Candidates fetched per result for [`RankMode::Hybrid`], as the best
+/// BM25 matches before the recency decay is applied
+const HYBRID_CANDIDATES: usize = 10;
+/// Number of tokens FTS5 puts in a snippet
+const SNIPPET_TOKENS: usize = 24;
+/// Number of characters kept when a snippet is cut without FTS5
+const SNIPPET_CHARS: usize = 120;
+
+/// Byte range of a match in a text
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MatchRange {
+    /// Byte offset of the first matched byte
+    pub start: usize,
+    /// Byte offset just past the match
+    pub end: usize,
+}
+
+/// Excerpt of a record around its matches
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Snippet {
+    /// Excerpt text, with `…` where the record was cut
+    pub text: String,
+    /// Matches within `text`
+    pub matches: Vec<MatchRange>,
+}
+
+impl Snippet {
+    /// Take the beginning of a text, for results without full-text matches
+    pub fn plain(text: &str) -> Self {
+        let text = match text.char_indices().nth(SNIPPET_CHARS) {
+            Some((cut, _)) => format!("{}…", &text[..cut]),
+            None => text.to_string(),
+        };
+        Self {
+            text,
+            matches: Vec::new(),
         }
     }
 
-    if !has_valid_char {
-        String::new()
-    } else {
-        result
+    /// Strip the match markers from FTS5 output, recording where they were
+    fn from_marked(marked: &str) -> Self {
+        let mut text = String::with_capacity(marked.len());
+        let mut matches = Vec::new();
+        let mut start = None;
+
+        for c in marked.chars() {
+            match c {
+                MATCH_START => start = Some(text.len()),
+                MATCH_END => {
+                    if let Some(start) = start.take() {
+                        matches.push(MatchRange {
+                            start,
+                            end: text.len(),
+                        });
+                    }
+                }
+                _ => text.push(c),
+            }
+        }
+
+        Self { text, matches }
+    }
+
+    /// Render the excerpt with matches in bold red, for terminals
+    pub fn to_ansi(&self) -> String {
+        let mut rendered = String::with_capacity(self.text.len() + self.matches.len() * 11);
+        let mut pos = 0;
+        for m in &self.matches {
+            rendered.push_str(&self.text[pos..m.start]);
+            rendered.push_str("\x1b[1;31m");
+            rendered.push_str(&self.text[m.start..m.end]);
+            rendered.push_str("\x1b[0m");
+            pos = m.end;
+        }
+        rendered.push_str(&self.text[pos..]);
+        rendered
     }
 }
 
 /// Search result containing a session record and its relevance info
 #[derive(Debug, Clone, PartialEq)]
 pub struct SearchResult {
-    /// The session record
+    /// The matching history record
     pub record: SessionRecord,
-    /// BM25 relevance score (lower is more relevant)
+    /// Ranking score, higher is more relevant
+    ///
+    /// The negated BM25 score, multiplied by the recency decay in hybrid
+    /// mode. Queries without positive terms score 0.
     pub score: f64,
+    /// The session the record belongs to
+    pub session: SessionSummary,
+    /// Excerpt of the prompt around the matches
+    pub snippet: Snippet,
+    /// Whether the snippet is taken from the prompt, the pasted contents or
+    /// a transcript message
+    pub snippet_source: SnippetSource,
+    /// Tool of the message the snippet is taken from, if any
+    pub tool: Option<String>,
+    /// Matches in the full prompt text (`record.display`)
+    pub matches: Vec<MatchRange>,
 }
 
 impl SearchResult {
     /// Create a new SearchResult
-    pub fn new(record: SessionRecord, score: f64) -> Self {
-        Self { record, score }
+    ///
+    /// The snippet is the beginning of the prompt, without matches.
+    pub fn new(record: SessionRecord, score: f64, session: SessionSummary) -> Self {
+        Self {
+            snippet: Snippet::plain(&record.display),
+            snippet_source: SnippetSource::Prompt,
+            tool: None,
+            matches: Vec::new(),
+            record,
+            score,
+            session,
+        }
+    }
+
+    /// Read a result from a row of record columns, the score, the session
+    /// columns, the marked-up `highlight()` and `snippet()` output, the
+    /// pasted contents and the `snippet()` of the pasted text, in that order
+    fn from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<Self> {
+        let mut result = Self::new(
+            SessionRecord::new(row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)
+                .with_pasted(decode_pasted(row.get(11)?)),
+            row.get(4)?,
+            SessionSummary {
+                session_id: row.get(3)?,
+                project: row.get(5)?,
+                first_timestamp: row.get(6)?,
+                last_timestamp: row.get(7)?,
+                prompt_count: row.get::<_, i64>(8)? as usize,
+            },
+        );
+
+        if let Some(highlighted) = row.get::<_, Option<String>>(9)? {
+            result.matches = Snippet::from_marked(&highlighted).matches;
+        }
+        if let Some(snippet) = row.get::<_, Option<String>>(10)? {
+            result.snippet = Snippet::from_marked(&snippet);
+        }
+        // Show where the query matched when it is only in the pasted text
+        if result.snippet.matches.is_empty() {
+            if let Some(snippet) = row.get::<_, Option<String>>(12)? {
+                let snippet = Snippet::from_marked(&snippet);
+                if !snippet.matches.is_empty() {
+                    result.snippet = snippet;
+                    result.snippet_source = SnippetSource::Pasted;
+                }
+            }
+        }
+        Ok(result)
+    }
+
+    /// Read a result from a row of record columns, the score, the session
+    /// columns, the marked-up `snippet()` of the message, the pasted
+    /// contents and the role, tool and text of the message, in that order
+    fn from_message_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<Self> {
+        let mut result = Self::new(
+            SessionRecord::new(row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)
+                .with_pasted(decode_pasted(row.get(10)?)),
+            row.get(4)?,
+            SessionSummary {
+                session_id: row.get(3)?,
+                project: row.get(5)?,
+                first_timestamp: row.get(6)?,
+                last_timestamp: row.get(7)?,
+                prompt_count: row.get::<_, i64>(8)? as usize,
+            },
+        );
+
+        result.snippet = match row.get::<_, Option<String>>(9)? {
+            Some(snippet) => Snippet::from_marked(&snippet),
+            None => Snippet::plain(&row.get::<_, String>(13)?),
+        };
+        result.snippet_source = match row.get::<_, String>(11)?.as_str() {
+            "answer" => SnippetSource::Answer,
+            _ => SnippetSource::Tool,
+        };
+        result.tool = Some(row.get::<_, String>(12)?).filter(|tool| !tool.is_empty());
+        Ok(result)
     }
 }
 
@@ -61,145 +457,431 @@ impl SearchResult {
 pub struct Search {
     /// Path to the SQLite database
     db_path: PathBuf,
+    /// How results are ordered
+    rank_mode: RankMode,
+    /// Half-life of the recency decay in hybrid mode
+    half_life: Duration,
+    /// Session IDs of each tag, for `tag:` filters; when not set, they are
+    /// read from the marks database by queries with such filters
+    tags: Option<HashMap<String, Vec<String>>>,
+    /// Which part of the records the query matches
+    scope: SearchScope,
+    /// Zone of `after:`/`before:` dates without an offset
+    zone: Zone,
 }
 
 impl Search {
     /// Create a new Search instance
     pub fn new() -> Result<Self> {
-        let data_dir = crate::storage::Storage::new()?.data_dir().clone();
-        let db_path = data_dir.join("index/sessions.db");
-        Ok(Self { db_path })
+        let db_path = crate::datadir::data_dir()?.join("index/sessions.db");
+        Ok(Self::from_db_path(db_path))
     }
 
     /// Create a Search with a custom database path (for testing)
     #[cfg(test)]
     pub fn with_db_path(db_path: PathBuf) -> Self {
-        Self { db_path }
+        Self::from_db_path(db_path)
+            .with_tags(HashMap::new())
+            .with_zone(Zone::Utc)
+    }
+
+    fn from_db_path(db_path: PathBuf) -> Self {
+        Self {
+            db_path,
+            rank_mode: RankMode::default(),
+            half_life: default_half_life(),
+            tags: None,
+            scope: SearchScope::default(),
+            zone: Zone::Local,
+        }
+    }
+
+    /// Set how results are ordered
+    pub fn with_rank_mode(mut self, rank_mode: RankMode) -> Self {
+        self.rank_mode = rank_mode;
+        self
+    }
+
+    /// Set the half-life of the recency decay used by [`RankMode::Hybrid`]
+    pub fn with_half_life(mut self, half_life: Duration) -> Self {
+        self.half_life = half_life;
+        self
+    }
+
+    /// Set the session IDs of each tag used by `tag:` filters
+    pub fn with_tags(mut self, tags: HashMap<String, Vec<String>>) -> Self {
+        self.tags = Some(tags);
+        self
     }
 
-    /// Search for records matching the query
+    /// Set which part of the records the query matches
+    pub fn with_scope(mut self, scope: SearchScope) -> Self {
+        self.scope = scope;
+        self
+    }
+
+    /// Set the time zone of `after:`/`before:` dates without an offset
+    pub fn with_zone(mut self, zone: Zone) -> Self {
+        self.zone = zone;
+        self
+    }
+
+    /// Open the index database with the CJK tokenizer registered
+    fn open(&self) -> Result<Connection> {
+        let conn = Connection::open(&self.db_path)?;
+        crate::tokenizer::register(&conn)?;
+        // Wait rather than fail while another process commits an update
+        conn.busy_timeout(std::time::Duration::from_secs(5))?;
+        Ok(conn)
+    }
+
+    /// Search for records matching a query string
     ///
-    /// Uses FTS5 BM25 ranking for relevance scoring
-    /// FTS5 requires special query syntax: prefix matching with *
+    /// See [`crate::query`] for the query syntax.
     pub fn search(&self, query: &str, limit: Option<usize>) -> Result<Vec<SearchResult>> {
-        let conn = Connection::open(&self.db_path)?;
+        let query = Query::parse_in(query, &self.zone)?;
+        self.search_query(&query, limit)
+    }
 
-        // Handle empty query - return empty results
-        if query.trim().is_empty() {
+    /// Search for records matching a parsed query
+    ///
+    /// The free-text expression becomes an FTS5 MATCH scored with BM25,
+    /// field filters become SQL conditions. Queries containing CJK text are
+    /// matched against the CJK bigram index. Results are ordered according
+    /// to the rank mode; a query without positive terms scores every record
+    /// 0 and lists them newest first.
+    ///
+    /// Prompts and transcript messages are searched separately and merged.
+    /// A prompt is listed once: with its own snippet when it matches, else
+    /// with the snippet of its best matching message.
+    pub fn search_query(&self, query: &Query, limit: Option<usize>) -> Result<Vec<SearchResult>> {
+        let fts = query.to_fts5();
+
+        // Nothing left to match on
+        if fts.is_empty() && query.filters.is_empty() {
             return Ok(Vec::new());
         }
 
-        // Handle special characters that FTS5 doesn't support
-        // Escape problematic characters or return empty results
-        let sanitized_query = sanitize_fts5_query(query);
-        if sanitized_query.is_empty() {
-            return Ok(Vec::new());
+        let message_filters = query.filters.iter().any(Filter::is_message_filter);
+        if message_filters && !self.scope.includes_messages() {
+            return Err(crate::error::Error::InvalidQuery(format!(
+                "tool: and file: filters do not apply to {}",
+                self.scope
+            ))
+            .into());
         }
 
-        // FTS5 query with BM25 ranking
-        // Use * prefix for simple word search (matches any word starting with query)
-        let fts_query = format!("{}*", sanitized_query);
-        let sql = r#"
+        // Only queries filtering by tag read the marks database
+        let loaded;
+        let tags = match &self.tags {
+            Some(tags) => tags,
+            None => {
+                let by_tag = query.filters.iter().any(|f| matches!(f, Filter::Tag(_)));
+                loaded = if by_tag {
+                    crate::storage::Storage::new()?.tagged_sessions()?
+                } else {
+                    HashMap::new()
+                };
+                &loaded
+            }
+        };
+
+        let conn = self.open()?;
+        // unicode61 cannot split CJK text into words, so such queries run
+        // against the bigram-tokenized copy of the index
+        let has_cjk = [&fts.include, &fts.exclude]
+            .into_iter()
+            .flatten()
+            .any(|text| contains_cjk(text));
+
+        // Hybrid scores depend on the current time, so the best BM25 matches
+        // are fetched and re-ranked with the recency decay
+        let limit = limit.unwrap_or(crate::config::DEFAULT_LIMIT);
+        let sql_limit = match self.rank_mode {
+            RankMode::Hybrid => limit.saturating_mul(HYBRID_CANDIDATES),
+            RankMode::Relevance | RankMode::Recent => limit,
+        };
+
+        let mut results = Vec::new();
+        if self.scope.includes_records() && !message_filters {
+            results = self.search_records(&conn, query, tags, &fts, has_cjk, sql_limit)?;
+        }
+        if self.scope.includes_messages() {
+            // Room for the messages of prompts that were found themselves
+            let sql_limit = sql_limit + results.len();
+            let found = self.search_messages(&conn, query, tags, &fts, has_cjk, sql_limit)?;
+            for result in found {
+                if !results.iter().any(|r| r.record == result.record) {
+                    results.push(result);
+                }
+            }
+        }
+
+        if self.rank_mode == RankMode::Hybrid {
+            let now = Utc::now().timestamp_millis();
+            for result in &mut results {
+                result.score *= recency_decay(result.record.timestamp, now, self.half_life);
+            }
+        }
+        // Stable sorts keep the newest-first order among equal scores
+        results.sort_by(|a, b| {
+            b.record
+                .timestamp
+                .cmp(&a.record.timestamp)
+                .then(b.score.total_cmp(&a.score))
+        });
+        if self.rank_mode != RankMode::Recent {
+            results.sort_by(|a, b| b.score.total_cmp(&a.score));
+        }
+        results.truncate(limit);
+
+        Ok(results)
+    }
+
+    /// Search the prompts of the history
+    fn search_records(
+        &self,
+        conn: &Connection,
+        query: &Query,
+        tags: &HashMap<String, Vec<String>>,
+        fts: &MatchExpr,
+        has_cjk: bool,
+        limit: usize,
+    ) -> Result<Vec<SearchResult>> {
+        let mut conditions = Vec::new();
+        let mut values = Vec::new();
+        let table = if has_cjk {
+            "records_cjk"
+        } else {
+            "records_fts"
+        };
+
+        let (source, score, highlight, snippet, pasted_snippet) = match &fts.include {
+            Some(include) => {
+                conditions.push(format!("{table} MATCH ?"));
+                values.push(Value::Text(self.scope.restrict(include.clone())));
+                (
+                    format!("{table} JOIN records r ON r.id = {table}.rowid"),
+                    format!("-bm25({table}, {PROMPT_WEIGHT}, 0, 0, {PASTED_WEIGHT})"),
+                    format!("highlight({table}, 0, char(2), char(3))"),
+                    format!("snippet({table}, 0, char(2), char(3), '…', {SNIPPET_TOKENS})"),
+                    format!("snippet({table}, 3, char(2), char(3), '…', {SNIPPET_TOKENS})"),
+                )
+            }
+            None => (
+                "records r".to_string(),
+                "0.0".to_string(),
+                "NULL".to_string(),
+                "NULL".to_string(),
+                "NULL".to_string(),
+            ),
+        };
+        if let Some(exclude) = &fts.exclude {
+            conditions.push(format!(
+                "r.id NOT IN (SELECT rowid FROM {table} WHERE {table} MATCH ?)"
+            ));
+            values.push(Value::Text(self.scope.restrict(exclude.clone())));
+        }
+        if self.scope == SearchScope::Pasted {
+            conditions.push("r.pasted != ''".to_string());
+        }
+        compile_filters(&query.filters, tags, &mut conditions, &mut values);
+
+        let order = match self.rank_mode {
+            RankMode::Relevance | RankMode::Hybrid => "score DESC, r.timestamp DESC",
+            RankMode::Recent => "r.timestamp DESC, score DESC",
+        };
+        values.push(Value::Integer(limit as i64));
+
+        let sql = format!(
+            r#"
             SELECT
-                s.display,
-                s.timestamp,
+                r.display,
+                r.timestamp,
+                r.project,
+                r.session_id,
+                {score} as score,
                 s.project,
-                s.session_id,
-                bm25(sessions_fts, 0, 100, 0, 0) as score
-            FROM sessions_fts
-            JOIN sessions s ON s.rowid = sessions_fts.rowid
-            WHERE sessions_fts MATCH ?1
-            ORDER BY s.timestamp DESC, score ASC
-            LIMIT ?2
-        "#;
-
-        let limit = limit.unwrap_or(20);
+                s.first_timestamp,
+                s.last_timestamp,
+                s.prompt_count,
+                {highlight},
+                {snippet},
+                r.pasted_json,
+                {pasted_snippet}
+            FROM {source}
+            JOIN sessions s ON s.session_id = r.session_id
+            WHERE {conditions}
+            ORDER BY {order}
+            LIMIT ?
+            "#,
+            conditions = conditions.join(" AND ")
+        );
 
-        let mut stmt = conn.prepare(sql)?;
-        let results = stmt.query_map(params![fts_query, limit as i64], |row| {
-            Ok(SearchResult::new(
-                SessionRecord::new(row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?),
-                row.get(4)?,
-            ))
-        })?;
+        let mut stmt = conn.prepare(&sql)?;
+        let results = stmt.query_map(params_from_iter(values), SearchResult::from_row)?;
 
         let mut search_results = Vec::new();
         for result in results {
             search_results.push(result?);
         }
-
         Ok(search_results)
     }
 
-    /// Search with project filter
-    pub fn search_with_project(
+    /// Search the transcript messages, keeping the best match of each prompt
+    ///
+    /// Field filters other than `tool:` and `file:` apply to the prompt a
+    /// message followed.
+    fn search_messages(
         &self,
-        query: &str,
-        project: &str,
-        limit: Option<usize>,
+        conn: &Connection,
+        query: &Query,
+        tags: &HashMap<String, Vec<String>>,
+        fts: &MatchExpr,
+        has_cjk: bool,
+        limit: usize,
     ) -> Result<Vec<SearchResult>> {
-        let conn = Connection::open(&self.db_path)?;
+        let mut conditions = Vec::new();
+        let mut values = Vec::new();
+        let table = if has_cjk {
+            "messages_cjk"
+        } else {
+            "messages_fts"
+        };
 
-        // Handle empty query - return empty results
-        if query.trim().is_empty() {
-            return Ok(Vec::new());
+        let (source, score, snippet) = match &fts.include {
+            Some(include) => {
+                conditions.push(format!("{table} MATCH ?"));
+                values.push(Value::Text(include.clone()));
+                (
+                    format!("{table} JOIN messages m ON m.id = {table}.rowid"),
+                    format!("-bm25({table}, {MESSAGE_WEIGHT}, {TOOL_NAME_WEIGHT}, 0)"),
+                    format!("snippet({table}, 0, char(2), char(3), '…', {SNIPPET_TOKENS})"),
+                )
+            }
+            None => (
+                "messages m".to_string(),
+                "0.0".to_string(),
+                "NULL".to_string(),
+            ),
+        };
+        if let Some(exclude) = &fts.exclude {
+            conditions.push(format!(
+                "m.id NOT IN (SELECT rowid FROM {table} WHERE {table} MATCH ?)"
+            ));
+            values.push(Value::Text(exclude.clone()));
         }
-
-        // Handle special characters
-        let sanitized_query = sanitize_fts5_query(query);
-        if sanitized_query.is_empty() {
-            return Ok(Vec::new());
+        // A prompt left out of the results is not brought back by its
+        // messages
+        if let Some(excluded) = query
+            .excluded_fts5()
+            .filter(|_| self.scope.includes_records())
+        {
+            let records = if has_cjk {
+                "records_cjk"
+            } else {
+                "records_fts"
+            };
+            conditions.push(format!(
+                "m.record_id NOT IN (SELECT rowid FROM {records} WHERE {records} MATCH ?)"
+            ));
+            values.push(Value::Text(self.scope.restrict(excluded)));
         }
+        match self.scope {
+            SearchScope::Answers => conditions.push("m.role = 'answer'".to_string()),
+            SearchScope::Tools => conditions.push("m.role != 'answer'".to_string()),
+            _ => {}
+        }
+        compile_filters(&query.filters, tags, &mut conditions, &mut values);
 
-        // Use * prefix for simple word search (matches any word starting with query)
-        let fts_query = format!("{}*", sanitized_query);
+        let order = match self.rank_mode {
+            RankMode::Relevance | RankMode::Hybrid => "hit.score DESC, r.timestamp DESC",
+            RankMode::Recent => "r.timestamp DESC, hit.score DESC",
+        };
+        values.push(Value::Integer(limit as i64));
 
-        let sql = r#"
+        // The innermost query scores the messages, the window keeps the
+        // best of each prompt
+        let sql = format!(
+            r#"
             SELECT
-                s.display,
-                s.timestamp,
+                r.display,
+                r.timestamp,
+                r.project,
+                r.session_id,
+                hit.score,
                 s.project,
-                s.session_id,
-                bm25(sessions_fts, 0, 100, 0, 0) as score
-            FROM sessions_fts
-            JOIN sessions s ON s.rowid = sessions_fts.rowid
-            WHERE sessions_fts MATCH ?1 AND s.project LIKE ?2
-            ORDER BY s.timestamp DESC, score ASC
-            LIMIT ?3
-        "#;
+                s.first_timestamp,
+                s.last_timestamp,
+                s.prompt_count,
+                hit.snippet,
+                r.pasted_json,
+                m.role,
+                m.tool,
+                m.text
+            FROM (
+                SELECT
+                    hit.*,
+                    ROW_NUMBER() OVER (
+                        PARTITION BY hit.record_id ORDER BY hit.score DESC, hit.timestamp
+                    ) AS n
+                FROM (
+                    SELECT m.id, m.record_id, m.timestamp, {score} AS score, {snippet} AS snippet
+                    FROM {source}
+                    JOIN records r ON r.id = m.record_id
+                    WHERE {conditions}
+                ) hit
+            ) hit
+            JOIN messages m ON m.id = hit.id
+            JOIN records r ON r.id = hit.record_id
+            JOIN sessions s ON s.session_id = r.session_id
+            WHERE hit.n = 1
+            ORDER BY {order}
+            LIMIT ?
+            "#,
+            conditions = conditions.join(" AND ")
+        );
 
-        let limit = limit.unwrap_or(20);
-        let project_pattern = format!("%{}%", project);
-
-        let mut stmt = conn.prepare(sql)?;
-        let results = stmt.query_map(params![fts_query, project_pattern, limit as i64], |row| {
-            Ok(SearchResult::new(
-                SessionRecord::new(row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?),
-                row.get(4)?,
-            ))
-        })?;
+        let mut stmt = conn.prepare(&sql)?;
+        let results = stmt.query_map(params_from_iter(values), SearchResult::from_message_row)?;
 
         let mut search_results = Vec::new();
         for result in results {
             search_results.push(result?);
         }
-
         Ok(search_results)
     }
 
+    /// Search with project filter
+    pub fn search_with_project(
+        &self,
+        query: &str,
+        project: &str,
+        limit: Option<usize>,
+    ) -> Result<Vec<SearchResult>> {
+        // Handle empty query - return empty results
+        if query.trim().is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let mut query = Query::parse_in(query, &self.zone)?;
+        query.filters.push(Filter::Project(project.to_string()));
+        self.search_query(&query, limit)
+    }
+
     /// Simple text search (fallback without FTS5)
     pub fn simple_search(&self, keyword: &str, limit: Option<usize>) -> Result<Vec<SessionRecord>> {
         let conn = Connection::open(&self.db_path)?;
 
         let sql = r#"
             SELECT display, timestamp, project, session_id
-            FROM sessions
+            FROM records
             WHERE display LIKE ?1 OR project LIKE ?1
             ORDER BY timestamp DESC
             LIMIT ?2
         "#;
 
-        let limit = limit.unwrap_or(20);
+        let limit = limit.unwrap_or(crate::config::DEFAULT_LIMIT);
         let pattern = format!("%{}%", keyword);
 
         let mut stmt = conn.prepare(sql)?;
@@ -234,10 +916,15 @@ impl Search {
 
         let conn = Connection::open(&self.db_path)?;
         // Handle case where table doesn't exist yet
-        let count: i64 = match conn.query_row("SELECT COUNT(*) FROM sessions", [], |row| row.get(0))
+        let count: i64 = match conn.query_row("SELECT COUNT(*) FROM records", [], |row| row.get(0))
         {
             Ok(c) => c,
             Err(rusqlite::Error::QueryReturnedNoRows) => 0,
+            Err(rusqlite::Error::SqliteFailure(_, Some(msg)))
+                if msg.starts_with("no such table") =>
+            {
+                0
+            }
             Err(e) => return Err(e.into()),
         };
         Ok(count as usize)
@@ -295,6 +982,36 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_search_finds_earlier_prompts_of_a_session() {
+        let temp_dir = TempDir::new().unwrap();
+        let (indexer, search) = create_test_indexer(&temp_dir);
+
+        let records = vec![
+            SessionRecord::new(
+                "/refactor the parser".to_string(),
+                1766567616338,
+                "/Users/elliotxx".to_string(),
+                "abc123".to_string(),
+            ),
+            SessionRecord::new(
+                "/run the tests".to_string(),
+                1766567617000,
+                "/Users/elliotxx".to_string(),
+                "abc123".to_string(),
+            ),
+        ];
+
+        indexer.build_index(&records).unwrap();
+
+        let results = search.search("refactor", Some(10)).unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].record.display, "/refactor the parser");
+        assert_eq!(results[0].session.session_id, "abc123");
+        assert_eq!(results[0].session.prompt_count, 2);
+        assert_eq!(results[0].session.last_timestamp, 1766567617000);
+    }
+
     #[test]
     fn test_search_limit() {
         let temp_dir = TempDir::new().unwrap();
@@ -367,6 +1084,457 @@ mod tests {
         assert!(results[0].record.project.contains("project1"));
     }
 
+    fn filter_test_records() -> Vec<SessionRecord> {
+        vec![
+            SessionRecord::new(
+                "/fix login bug".to_string(),
+                1766567616338, // 2025-12-24
+                "/Users/elliotxx/backend".to_string(),
+                "abc123-0001".to_string(),
+            ),
+            SessionRecord::new(
+                "/fix layout bug".to_string(),
+                1769682600000, // 2026-01-29 10:30
+                "/Users/elliotxx/frontend".to_string(),
+                "def456-0002".to_string(),
+            ),
+            SessionRecord::new(
+                "/write docs".to_string(),
+                chrono::Utc::now().timestamp_millis() - 60_000,
+                "/Users/elliotxx/frontend".to_string(),
+                "abc999-0003".to_string(),
+            ),
+        ]
+    }
+
+    #[test]
+    fn test_search_query_field_filters() {
+        let temp_dir = TempDir::new().unwrap();
+        let (indexer, search) = create_test_indexer(&temp_dir);
+        indexer.build_index(&filter_test_records()).unwrap();
+
+        let results = search.search("fix project:frontend", Some(10)).unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].record.display, "/fix layout bug");
+
+        let results = search.search("session:abc", Some(10)).unwrap();
+        assert_eq!(results.len(), 2);
+
+        let results = search.search("last:1h", Some(10)).unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].record.display, "/write docs");
+    }
+
+    #[test]
+    fn test_search_query_date_range() {
+        let temp_dir = TempDir::new().unwrap();
+        let (indexer, search) = create_test_indexer(&temp_dir);
+        indexer.build_index(&filter_test_records()).unwrap();
+
+        let results = search.search("fix after:2026-01-01", Some(10)).unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].record.display, "/fix layout bug");
+
+        let results = search.search("before:2026-01-01", Some(10)).unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].record.display, "/fix login bug");
+
+        let results = search
+            .search("after:2026-01-29 before:2026-01-30", Some(10))
+            .unwrap();
+        assert_eq!(results.len(), 1);
+    }
+
+    #[test]
+    fn test_search_query_tag_filter() {
+        let temp_dir = TempDir::new().unwrap();
+        let (indexer, search) = create_test_indexer(&temp_dir);
+        indexer.build_index(&filter_test_records()).unwrap();
+
+        let search = search.with_tags(HashMap::from([(
+            "frontend".to_string(),
+            vec!["def456-0002".to_string(), "abc999-0003".to_string()],
+        )]));
+
+        let results = search.search("tag:frontend", Some(10)).unwrap();
+        assert_eq!(results.len(), 2);
+
+        let results = search.search("fix tag:frontend", Some(10)).unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].record.display, "/fix layout bug");
+
+        // Unknown tags match no session
+        assert!(search.search("tag:missing", Some(10)).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_search_filter_values_are_literal() {
+        // LIKE wildcards in filter values must not match arbitrary text
+        let temp_dir = TempDir::new().unwrap();
+        let (indexer, search) = create_test_indexer(&temp_dir);
+        indexer.build_index(&filter_test_records()).unwrap();
+
+        assert!(search.search("project:%", Some(10)).unwrap().is_empty());
+        assert!(search.search("session:___", Some(10)).unwrap().is_empty());
+    }
+
+    fn operator_test_records() -> Vec<SessionRecord> {
+        [
+            "cargo test --all",
+            "cargo build",
+            "test cargo later",
+            "/model opus",
+        ]
+        .iter()
+        .enumerate()
+        .map(|(i, display)| {
+            SessionRecord::new(
+                display.to_string(),
+                1766567616000 + i as i64,
+                "/Users/elliotxx".to_string(),
+                format!("id-{i}"),
+            )
+        })
+        .collect()
+    }
+
+    fn displays(results: &[SearchResult]) -> Vec<&str> {
+        let mut displays: Vec<&str> = results.iter().map(|r| r.record.display.as_str()).collect();
+        displays.sort();
+        displays
+    }
+
+    #[test]
+    fn test_search_phrase() {
+        let temp_dir = TempDir::new().unwrap();
+        let (indexer, search) = create_test_indexer(&temp_dir);
+        indexer.build_index(&operator_test_records()).unwrap();
+
+        let results = search.search(r#""cargo test""#, Some(10)).unwrap();
+        assert_eq!(displays(&results), vec!["cargo test --all"]);
+    }
+
+    #[test]
+    fn test_search_or_and_exclusion() {
+        let temp_dir = TempDir::new().unwrap();
+        let (indexer, search) = create_test_indexer(&temp_dir);
+        indexer.build_index(&operator_test_records()).unwrap();
+
+        let results = search.search("build OR opus", Some(10)).unwrap();
+        assert_eq!(displays(&results), vec!["/model opus", "cargo build"]);
+
+        let results = search.search("cargo -test", Some(10)).unwrap();
+        assert_eq!(displays(&results), vec!["cargo build"]);
+
+        let results = search
+            .search("cargo NOT (build OR later)", Some(10))
+            .unwrap();
+        assert_eq!(displays(&results), vec!["cargo test --all"]);
+
+        // Purely negative query
+        let results = search.search("-cargo", Some(10)).unwrap();
+        assert_eq!(displays(&results), vec!["/model opus"]);
+    }
+
+    #[test]
+    fn test_search_explicit_prefix() {
+        let temp_dir = TempDir::new().unwrap();
+        let (indexer, search) = create_test_indexer(&temp_dir);
+        indexer.build_index(&operator_test_records()).unwrap();
+
+        assert!(search.search("carg", Some(10)).unwrap().is_empty());
+        assert_eq!(search.search("carg*", Some(10)).unwrap().len(), 3);
+    }
+
+    fn cjk_test_records() -> Vec<SessionRecord> {
+        [
+            "帮我重构这个模块",
+            "给 parser 模块写单元测试",
+            "重新构建索引",
+            "fix the build",
+        ]
+        .iter()
+        .enumerate()
+        .map(|(i, display)| {
+            SessionRecord::new(
+                display.to_string(),
+                1766567616000 + i as i64,
+                "/Users/elliotxx".to_string(),
+                format!("id-{i}"),
+            )
+        })
+        .collect()
+    }
+
+    #[test]
+    fn test_search_cjk_substring() {
+        let temp_dir = TempDir::new().unwrap();
+        let (indexer, search) = create_test_indexer(&temp_dir);
+        indexer.build_index(&cjk_test_records()).unwrap();
+
+        let results = search.search("重构", Some(10)).unwrap();
+        assert_eq!(displays(&results), vec!["帮我重构这个模块"]);
+
+        let results = search.search("模块", Some(10)).unwrap();
+        assert_eq!(
+            displays(&results),
+            vec!["帮我重构这个模块", "给 parser 模块写单元测试"]
+        );
+
+        // Single characters match anywhere in a run
+        let results = search.search("构", Some(10)).unwrap();
+        assert_eq!(displays(&results), vec!["帮我重构这个模块", "重新构建索引"]);
+
+        // Non-adjacent characters do not form a match
+        assert!(search.search("重构建", Some(10)).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_search_cjk_mixed_with_latin() {
+        let temp_dir = TempDir::new().unwrap();
+        let (indexer, search) = create_test_indexer(&temp_dir);
+        indexer.build_index(&cjk_test_records()).unwrap();
+
+        let results = search.search("Parser 模块", Some(10)).unwrap();
+        assert_eq!(displays(&results), vec!["给 parser 模块写单元测试"]);
+
+        let results = search.search("模块 -重构", Some(10)).unwrap();
+        assert_eq!(displays(&results), vec!["给 parser 模块写单元测试"]);
+
+        let results = search.search("索引 OR build", Some(10)).unwrap();
+        assert_eq!(displays(&results), vec!["fix the build", "重新构建索引"]);
+    }
+
+    /// An old record that matches "cargo" strongly and a new one that
+    /// matches it weakly
+    fn ranking_test_records() -> Vec<SessionRecord> {
+        let now = Utc::now().timestamp_millis();
+        let day = Duration::days(1).num_milliseconds();
+        vec![
+            SessionRecord::new(
+                "cargo cargo cargo".to_string(),
+                now - 30 * day,
+                "/Users/elliotxx".to_string(),
+                "old".to_string(),
+            ),
+            SessionRecord::new(
+                "run cargo after updating the lock file and the changelog".to_string(),
+                now - day,
+                "/Users/elliotxx".to_string(),
+                "new".to_string(),
+            ),
+        ]
+    }
+
+    fn session_ids(results: &[SearchResult]) -> Vec<&str> {
+        results
+            .iter()
+            .map(|r| r.record.session_id.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn test_search_rank_modes() {
+        let temp_dir = TempDir::new().unwrap();
+        let (indexer, search) = create_test_indexer(&temp_dir);
+        indexer.build_index(&ranking_test_records()).unwrap();
+
+        let recent = search.search("cargo", Some(10)).unwrap();
+        assert_eq!(session_ids(&recent), vec!["new", "old"]);
+        assert!(recent.iter().all(|r| r.score > 0.0));
+
+        let relevance = search
+            .clone()
+            .with_rank_mode(RankMode::Relevance)
+            .search("cargo", Some(10))
+            .unwrap();
+        assert_eq!(session_ids(&relevance), vec!["old", "new"]);
+        assert!(relevance[0].score > relevance[1].score);
+    }
+
+    #[test]
+    fn test_search_hybrid_half_life() {
+        let temp_dir = TempDir::new().unwrap();
+        let (indexer, search) = create_test_indexer(&temp_dir);
+        indexer.build_index(&ranking_test_records()).unwrap();
+        let search = search.with_rank_mode(RankMode::Hybrid);
+
+        // A short half-life lets recency dominate
+        let results = search
+            .clone()
+            .with_half_life(Duration::days(1))
+            .search("cargo", Some(10))
+            .unwrap();
+        assert_eq!(session_ids(&results), vec!["new", "old"]);
+
+        // A long half-life falls back to relevance
+        let results = search
+            .with_half_life(Duration::weeks(1000))
+            .search("cargo", Some(1))
+            .unwrap();
+        assert_eq!(session_ids(&results), vec!["old"]);
+    }
+
+    #[test]
+    fn test_search_hybrid_reranks_best_matches() {
+        let temp_dir = TempDir::new().unwrap();
+        let (indexer, search) = create_test_indexer(&temp_dir);
+        let now = Utc::now().timestamp_millis();
+        let day = Duration::days(1).num_milliseconds();
+        let mut records: Vec<_> = (1..=HYBRID_CANDIDATES as i64)
+            .map(|i| {
+                SessionRecord::new(
+                    "cargo cargo cargo".to_string(),
+                    now - (30 + i) * day,
+                    "/Users/elliotxx".to_string(),
+                    format!("old-{i}"),
+                )
+            })
+            .collect();
+        records.push(SessionRecord::new(
+            "run cargo after updating the lock file and the changelog".to_string(),
+            now,
+            "/Users/elliotxx".to_string(),
+            "new".to_string(),
+        ));
+        indexer.build_index(&records).unwrap();
+        let search = search
+            .with_rank_mode(RankMode::Hybrid)
+            .with_half_life(Duration::days(1));
+
+        // The weak match is not among the best candidates for one result
+        let results = search.clone().search("cargo", Some(1)).unwrap();
+        assert_eq!(session_ids(&results), vec!["old-1"]);
+
+        // With room for it, recency puts it first
+        let results = search.search("cargo", Some(2)).unwrap();
+        assert_eq!(session_ids(&results), vec!["new", "old-1"]);
+    }
+
+    #[test]
+    fn test_recency_decay() {
+        let hour = Duration::hours(1);
+        assert_eq!(recency_decay(1000, 1000, hour), 1.0);
+        assert!((recency_decay(0, hour.num_milliseconds(), hour) - 0.5).abs() < 1e-9);
+        assert_eq!(recency_decay(2000, 1000, hour), 1.0);
+    }
+
+    #[test]
+    fn test_snippet_from_marked() {
+        let snippet = Snippet::from_marked("run \u{2}cargo\u{3} and \u{2}测试\u{3}");
+        assert_eq!(snippet.text, "run cargo and 测试");
+        assert_eq!(
+            snippet.matches,
+            vec![
+                MatchRange { start: 4, end: 9 },
+                MatchRange { start: 14, end: 20 }
+            ]
+        );
+        assert_eq!(
+            snippet.to_ansi(),
+            "run \x1b[1;31mcargo\x1b[0m and \x1b[1;31m测试\x1b[0m"
+        );
+    }
+
+    #[test]
+    fn test_snippet_plain_truncates() {
+        let long = "字".repeat(SNIPPET_CHARS + 10);
+        let snippet = Snippet::plain(&long);
+        assert_eq!(snippet.text.chars().count(), SNIPPET_CHARS + 1);
+        assert!(snippet.text.ends_with('…'));
+        assert_eq!(Snippet::plain("short").text, "short");
+    }
+
+    #[test]
+    fn test_search_snippet_and_matches() {
+        let temp_dir = TempDir::new().unwrap();
+        let (indexer, search) = create_test_indexer(&temp_dir);
+        let long = format!(
+            "{} the parser fails on nested tables {}",
+            "lorem ipsum ".repeat(40),
+            "dolor sit ".repeat(40)
+        );
+        indexer
+            .build_index(&[SessionRecord::new(
+                long.clone(),
+                1766567616000,
+                "/Users/elliotxx".to_string(),
+                "abc".to_string(),
+            )])
+            .unwrap();
+
+        let results = search.search("parser", Some(10)).unwrap();
+        let result = &results[0];
+
+        // Offsets point into the full prompt
+        assert_eq!(result.matches.len(), 1);
+        let m = result.matches[0];
+        assert_eq!(&long[m.start..m.end], "parser");
+
+        // The snippet is a short excerpt around the match
+        let snippet = &result.snippet;
+        assert!(snippet.text.len() < long.len() / 4);
+        assert!(snippet.text.starts_with('…') && snippet.text.ends_with('…'));
+        let m = snippet.matches[0];
+        assert_eq!(&snippet.text[m.start..m.end], "parser");
+    }
+
+    #[test]
+    fn test_search_cjk_matches() {
+        let temp_dir = TempDir::new().unwrap();
+        let (indexer, search) = create_test_indexer(&temp_dir);
+        indexer.build_index(&cjk_test_records()).unwrap();
+
+        let results = search.search("重构", Some(10)).unwrap();
+        let result = &results[0];
+        let m = result.matches[0];
+        assert_eq!(&result.record.display[m.start..m.end], "重构");
+    }
+
+    #[test]
+    fn test_search_without_terms_has_plain_snippet() {
+        let temp_dir = TempDir::new().unwrap();
+        let (indexer, search) = create_test_indexer(&temp_dir);
+        indexer.build_index(&filter_test_records()).unwrap();
+
+        let results = search.search("project:frontend", Some(10)).unwrap();
+        assert!(!results.is_empty());
+        for result in results {
+            assert!(result.matches.is_empty());
+            assert_eq!(result.snippet.text, result.record.display);
+        }
+    }
+
+    #[test]
+    fn test_rank_mode_from_str() {
+        for mode in RankMode::ALL {
+            assert_eq!(mode.as_str().parse::<RankMode>(), Ok(mode));
+        }
+        assert!("best".parse::<RankMode>().is_err());
+    }
+
+    #[test]
+    fn test_search_malformed_query() {
+        let temp_dir = TempDir::new().unwrap();
+        let (indexer, search) = create_test_indexer(&temp_dir);
+        indexer.build_index(&operator_test_records()).unwrap();
+
+        let result = search.search("(cargo OR", Some(10));
+        assert!(result.is_err());
+        assert!(result.unwrap_err().to_string().contains("Invalid query"));
+    }
+
+    #[test]
+    fn test_search_invalid_query() {
+        let temp_dir = TempDir::new().unwrap();
+        let (indexer, search) = create_test_indexer(&temp_dir);
+        indexer.build_index(&filter_test_records()).unwrap();
+
+        let result = search.search("fix last:forever", Some(10));
+        assert!(result.is_err());
+        assert!(result.unwrap_err().to_string().contains("Invalid query"));
+    }
+
     #[test]
     fn test_simple_search() {
         let temp_dir = TempDir::new().unwrap();
@@ -513,6 +1681,164 @@ mod tests {
         assert_eq!(results.len(), 2);
     }
 
+    #[test]
+    fn test_search_pasted_contents() {
+        use crate::parser::{PastedContent, PastedKind};
+
+        let temp_dir = TempDir::new().unwrap();
+        let (indexer, search) = create_test_indexer(&temp_dir);
+
+        let pasted = |content: &str| {
+            vec![PastedContent {
+                id: 1,
+                kind: PastedKind::Text,
+                content: content.to_string(),
+                media_type: None,
+            }]
+        };
+        let records = vec![
+            SessionRecord::new(
+                "why does this fail? [Pasted text #1 +2 lines]".to_string(),
+                1766567617000,
+                "/Users/elliotxx".to_string(),
+                "pasted-1".to_string(),
+            )
+            .with_pasted(pasted("error: linker failed\nnote: ld returned 1")),
+            SessionRecord::new(
+                "the linker is slow".to_string(),
+                1766567616000,
+                "/Users/elliotxx".to_string(),
+                "prompt-1".to_string(),
+            ),
+        ];
+        indexer.build_index(&records).unwrap();
+
+        // Matches in the prompt outrank matches in pasted text
+        let results = search
+            .clone()
+            .with_rank_mode(RankMode::Relevance)
+            .search("linker", Some(10))
+            .unwrap();
+        let ids: Vec<&str> = results
+            .iter()
+            .map(|r| r.record.session_id.as_str())
+            .collect();
+        assert_eq!(ids, vec!["prompt-1", "pasted-1"]);
+        assert_eq!(results[0].snippet_source, SnippetSource::Prompt);
+        assert_eq!(results[1].snippet_source, SnippetSource::Pasted);
+        assert!(results[1].snippet.text.contains("error: linker failed"));
+        assert_eq!(results[1].record.pasted.len(), 1);
+
+        let results = search
+            .clone()
+            .with_scope(SearchScope::Pasted)
+            .search("linker", Some(10))
+            .unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].record.session_id, "pasted-1");
+
+        let results = search
+            .clone()
+            .with_scope(SearchScope::Prompts)
+            .search("linker", Some(10))
+            .unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].record.session_id, "prompt-1");
+
+        // Without search terms the pasted scope lists records with pastes
+        let results = search
+            .with_scope(SearchScope::Pasted)
+            .search("session:pasted", Some(10))
+            .unwrap();
+        assert_eq!(results.len(), 1);
+    }
+
+    #[test]
+    fn test_search_transcript_messages() {
+        use crate::transcript::TranscriptStore;
+
+        let temp_dir = TempDir::new().unwrap();
+        let (indexer, search) = create_test_indexer(&temp_dir);
+        let records = vec![
+            SessionRecord::new(
+                "fix the failing test".to_string(),
+                1766567616000,
+                "/Users/elliotxx/app".to_string(),
+                "session-1".to_string(),
+            ),
+            SessionRecord::new(
+                "now update the changelog".to_string(),
+                1766567700000,
+                "/Users/elliotxx/app".to_string(),
+                "session-1".to_string(),
+            ),
+        ];
+        indexer.build_index(&records).unwrap();
+
+        let project_dir = temp_dir.path().join("projects/-Users-elliotxx-app");
+        std::fs::create_dir_all(&project_dir).unwrap();
+        std::fs::write(
+            project_dir.join("session-1.jsonl"),
+            r#"{"type":"assistant","timestamp":"2025-12-24T09:13:40.000Z","message":{"content":[{"type":"text","text":"The parser drops trailing commas."},{"type":"tool_use","id":"t1","name":"Edit","input":{"file_path":"src/parser.rs","old_string":"a","new_string":"b"}}]}}
+{"type":"user","timestamp":"2025-12-24T09:13:45.000Z","message":{"content":[{"type":"tool_result","tool_use_id":"t1","content":"edited"}]}}
+{"type":"assistant","timestamp":"2025-12-24T09:15:10.000Z","message":{"content":[{"type":"tool_use","id":"t2","name":"Bash","input":{"command":"git log --oneline"}}]}}
+"#,
+        )
+        .unwrap();
+        let store = TranscriptStore::new(temp_dir.path().join("projects"));
+        assert_eq!(indexer.update_transcripts(&store).unwrap(), 1);
+
+        // Messages are reported as the prompt they followed
+        let results = search.search("commas", Some(10)).unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].record.display, "fix the failing test");
+        assert_eq!(results[0].snippet_source, SnippetSource::Answer);
+        assert!(results[0].snippet.text.contains("trailing commas"));
+
+        let results = search
+            .clone()
+            .with_scope(SearchScope::Tools)
+            .search("git", Some(10))
+            .unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].record.display, "now update the changelog");
+        assert_eq!(results[0].tool.as_deref(), Some("Bash"));
+
+        let results = search
+            .clone()
+            .with_scope(SearchScope::Answers)
+            .search("git", Some(10))
+            .unwrap();
+        assert!(results.is_empty());
+
+        // Tool calls and their results both carry the tool and its files
+        let results = search.search("tool:edit file:parser.rs", Some(10)).unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].record.display, "fix the failing test");
+        let results = search.search("edited tool:Edit", Some(10)).unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].snippet_source, SnippetSource::Tool);
+
+        // A prompt matching itself is listed once, with its own snippet
+        let results = search.search("changelog OR git", Some(10)).unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].snippet_source, SnippetSource::Prompt);
+
+        let err = search
+            .with_scope(SearchScope::Prompts)
+            .search("tool:Bash", Some(10))
+            .unwrap_err();
+        assert!(err.to_string().contains("do not apply to prompts"));
+    }
+
+    #[test]
+    fn test_search_scope_from_str() {
+        assert_eq!("pasted".parse::<SearchScope>(), Ok(SearchScope::Pasted));
+        assert_eq!("tools".parse::<SearchScope>(), Ok(SearchScope::Tools));
+        assert_eq!("all".parse::<SearchScope>(), Ok(SearchScope::All));
+        assert!("everything".parse::<SearchScope>().is_err());
+    }
+
     #[test]
     fn test_search_with_case_insensitive() {
         let temp_dir = TempDir::new().unwrap();
@@ -0,0 +1,477 @@
+//! Session module for assembling a session's timeline from its records
+
+use crate::parser::SessionRecord;
+use crate::timefmt::{FormatTime, TimeFormat};
+use crate::transcript::{tool_input_summary, Message, Transcript};
+use std::fmt;
+use std::str::FromStr;
+
+/// All prompts of one session in chronological order
+#[derive(Debug, Clone, PartialEq)]
+pub struct Session {
+    /// Unique session identifier (UUID)
+    pub session_id: String,
+    /// Records of the session, oldest first
+    pub records: Vec<SessionRecord>,
+    /// Full conversation, when its transcript was loaded
+    pub transcript: Option<Transcript>,
+}
+
+impl Session {
+    /// Build a session from its records
+    ///
+    /// Records are sorted by timestamp, keeping the history order for equal
+    /// timestamps. Returns `None` when there are no records.
+    pub fn from_records(mut records: Vec<SessionRecord>) -> Option<Self> {
+        let session_id = records.first()?.session_id.clone();
+        records.sort_by_key(|r| r.timestamp);
+        Some(Self {
+            session_id,
+            records,
+            transcript: None,
+        })
+    }
+
+    /// Attach the full conversation of the session
+    pub fn with_transcript(mut self, transcript: Transcript) -> Self {
+        self.transcript = Some(transcript);
+        self
+    }
+
+    /// Replies and tool activity that followed a prompt, up to the next one
+    ///
+    /// `index` counts prompts from 0. The prompts themselves are left out.
+    /// Empty when no transcript is attached.
+    pub fn replies(&self, index: usize) -> Vec<&Message> {
+        let Some(transcript) = &self.transcript else {
+            return Vec::new();
+        };
+        let start = self.records[index].timestamp;
+        let end = self.records.get(index + 1).map(|r| r.timestamp);
+        transcript
+            .between(start, end)
+            .filter(|m| !matches!(m, Message::User { .. }))
+            .collect()
+    }
+
+    /// Project path from the most recent record
+    pub fn project(&self) -> &str {
+        &self.last().project
+    }
+
+    /// Timestamp of the first prompt (milliseconds)
+    pub fn start(&self) -> i64 {
+        self.first().timestamp
+    }
+
+    /// Timestamp of the last prompt (milliseconds)
+    pub fn end(&self) -> i64 {
+        self.last().timestamp
+    }
+
+    /// Time between the first and the last prompt (milliseconds)
+    pub fn duration(&self) -> i64 {
+        self.end() - self.start()
+    }
+
+    /// Number of prompts in the session
+    pub fn prompt_count(&self) -> usize {
+        self.records.len()
+    }
+
+    /// Prompts with their offset from the start of the session
+    pub fn timeline(&self) -> impl Iterator<Item = (i64, &SessionRecord)> {
+        let start = self.start();
+        self.records.iter().map(move |r| (r.timestamp - start, r))
+    }
+
+    /// Keep only the prompts in a range
+    pub fn select(&self, range: PromptRange) -> Result<Session, crate::error::Error> {
+        let last = range.last.unwrap_or(self.prompt_count());
+        if last > self.prompt_count() || range.first > last {
+            return Err(crate::error::Error::PromptRangeOutOfBounds {
+                range: range.to_string(),
+                prompt_count: self.prompt_count(),
+            });
+        }
+        // Keep the replies to the selected prompts only
+        let start = self.records[range.first - 1].timestamp;
+        let end = self.records.get(last).map(|r| r.timestamp);
+        let transcript = self.transcript.as_ref().map(|t| Transcript {
+            session_id: t.session_id.clone(),
+            messages: t
+                .messages
+                .iter()
+                .filter(|m| match m.timestamp() {
+                    Some(t) => t >= start && end.is_none_or(|end| t < end),
+                    None => true,
+                })
+                .cloned()
+                .collect(),
+        });
+        Ok(Session {
+            session_id: self.session_id.clone(),
+            records: self.records[range.first - 1..last].to_vec(),
+            transcript,
+        })
+    }
+
+    fn first(&self) -> &SessionRecord {
+        self.records.first().expect("session has records")
+    }
+
+    fn last(&self) -> &SessionRecord {
+        self.records.last().expect("session has records")
+    }
+}
+
+impl std::fmt::Display for Session {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.fmt_time(f, &TimeFormat::default())
+    }
+}
+
+impl FormatTime for Session {
+    fn fmt_time(&self, f: &mut fmt::Formatter<'_>, format: &TimeFormat) -> fmt::Result {
+        writeln!(f, "Session:  {}", self.session_id)?;
+        writeln!(f, "Project:  {}", self.project())?;
+        writeln!(f, "Start:    {}", format.format(self.start()))?;
+        writeln!(f, "End:      {}", format.format(self.end()))?;
+        writeln!(f, "Duration: {}", format_duration(self.duration()))?;
+        writeln!(f, "Prompts:  {}", self.prompt_count())?;
+
+        let offsets: Vec<String> = self
+            .timeline()
+            .map(|(offset, _)| format!("+{}", format_duration(offset)))
+            .collect();
+        let width = offsets.iter().map(|o| o.len()).max().unwrap_or(0);
+
+        if let Some(transcript) = &self.transcript {
+            for summary in transcript.summaries() {
+                writeln!(f, "Summary:  {summary}")?;
+            }
+        }
+
+        for (i, (offset, record)) in offsets.iter().zip(&self.records).enumerate() {
+            writeln!(f)?;
+            // Continuation lines of multi-line prompts stay aligned with the first
+            let mut lines = record.display.lines();
+            writeln!(f, "  {offset:<width$}  {}", lines.next().unwrap_or(""))?;
+            for line in lines {
+                writeln!(f, "  {:width$}  {line}", "")?;
+            }
+            for pasted in &record.pasted {
+                writeln!(f, "  {:width$}  ── {}", "", pasted.label())?;
+                for line in pasted.content.lines() {
+                    writeln!(f, "  {:width$}  │ {line}", "")?;
+                }
+            }
+            for reply in self.replies(i) {
+                for line in reply_lines(reply) {
+                    writeln!(f, "  {:width$}  {line}", "")?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Lines showing a reply in the text timeline
+///
+/// Answers are shown in full, tool calls and results on one line each.
+fn reply_lines(message: &Message) -> Vec<String> {
+    match message {
+        Message::AssistantText { text, .. } => {
+            let mut lines: Vec<String> = text.lines().map(|l| format!("  {l}")).collect();
+            if let Some(first) = lines.first_mut() {
+                first.replace_range(..2, "⏺ ");
+            }
+            lines
+        }
+        Message::ToolUse { name, input, .. } => {
+            let summary = tool_input_summary(input);
+            vec![format!(
+                "→ {name}: {}",
+                summary.lines().next().unwrap_or("")
+            )]
+        }
+        Message::ToolResult {
+            content, is_error, ..
+        } => {
+            let first = content.lines().find(|l| !l.trim().is_empty()).unwrap_or("");
+            let lines = content.lines().count();
+            let status = if *is_error { "error: " } else { "" };
+            if lines > 1 {
+                vec![format!("← {status}{first} ({lines} lines)")]
+            } else {
+                vec![format!("← {status}{first}")]
+            }
+        }
+        Message::User { .. } | Message::Summary { .. } => Vec::new(),
+    }
+}
+
+/// Prompts of a session, numbered from 1, e.g. `3`, `2-5` or `4-`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PromptRange {
+    /// First prompt, at least 1
+    pub first: usize,
+    /// Last prompt (inclusive), or `None` for the end of the session
+    pub last: Option<usize>,
+}
+
+impl fmt::Display for PromptRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.last {
+            Some(last) if last == self.first => write!(f, "{last}"),
+            Some(last) => write!(f, "{}-{last}", self.first),
+            None => write!(f, "{}-", self.first),
+        }
+    }
+}
+
+impl FromStr for PromptRange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid prompt range '{s}' (expected e.g. 3, 2-5 or 4-)");
+        let number = |n: &str| n.trim().parse::<usize>().ok().filter(|n| *n > 0);
+
+        let (first, last) = match s.split_once('-') {
+            Some((first, "")) => (number(first).ok_or_else(invalid)?, None),
+            Some((first, last)) => (
+                number(first).ok_or_else(invalid)?,
+                Some(number(last).ok_or_else(invalid)?),
+            ),
+            None => {
+                let n = number(s).ok_or_else(invalid)?;
+                (n, Some(n))
+            }
+        };
+        if last.is_some_and(|last| last < first) {
+            return Err(invalid());
+        }
+        Ok(Self { first, last })
+    }
+}
+
+/// Format a duration in milliseconds as e.g. `45s`, `12m 3s` or `2h 5m`
+pub fn format_duration(millis: i64) -> String {
+    let seconds = millis.max(0) / 1000;
+    let (days, hours, minutes, seconds) = (
+        seconds / 86_400,
+        seconds / 3_600 % 24,
+        seconds / 60 % 60,
+        seconds % 60,
+    );
+
+    if days > 0 {
+        format!("{days}d {hours}h")
+    } else if hours > 0 {
+        format!("{hours}h {minutes}m")
+    } else if minutes > 0 {
+        format!("{minutes}m {seconds}s")
+    } else {
+        format!("{seconds}s")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{PastedContent, PastedKind};
+    use serde_json::json;
+
+    fn record(display: &str, timestamp: i64, project: &str) -> SessionRecord {
+        SessionRecord::new(
+            display.to_string(),
+            timestamp,
+            project.to_string(),
+            "abc123".to_string(),
+        )
+    }
+
+    #[test]
+    fn test_from_records_sorts_chronologically() {
+        let session = Session::from_records(vec![
+            record("second", 1766567676000, "/Users/elliotxx/app"),
+            record("first", 1766567616000, "/Users/elliotxx"),
+            record("third", 1766571216000, "/Users/elliotxx/app"),
+        ])
+        .unwrap();
+
+        let displays: Vec<&str> = session.records.iter().map(|r| r.display.as_str()).collect();
+        assert_eq!(displays, vec!["first", "second", "third"]);
+        assert_eq!(session.session_id, "abc123");
+        assert_eq!(session.project(), "/Users/elliotxx/app");
+        assert_eq!(session.duration(), 3_600_000);
+        assert_eq!(session.prompt_count(), 3);
+
+        let offsets: Vec<i64> = session.timeline().map(|(offset, _)| offset).collect();
+        assert_eq!(offsets, vec![0, 60_000, 3_600_000]);
+    }
+
+    #[test]
+    fn test_from_records_empty() {
+        assert!(Session::from_records(Vec::new()).is_none());
+    }
+
+    #[test]
+    fn test_prompt_range_from_str() {
+        let range = |first, last| PromptRange { first, last };
+        assert_eq!("3".parse(), Ok(range(3, Some(3))));
+        assert_eq!("2-5".parse(), Ok(range(2, Some(5))));
+        assert_eq!("4-".parse(), Ok(range(4, None)));
+        for invalid in ["", "0", "-3", "5-2", "a-b", "1-2-3"] {
+            assert!(invalid.parse::<PromptRange>().is_err(), "{invalid}");
+        }
+        assert_eq!(range(2, Some(5)).to_string(), "2-5");
+        assert_eq!(range(4, None).to_string(), "4-");
+    }
+
+    #[test]
+    fn test_select_prompts() {
+        let session = Session::from_records(vec![
+            record("one", 1000, "/app"),
+            record("two", 2000, "/app"),
+            record("three", 3000, "/app"),
+        ])
+        .unwrap();
+
+        let selected = session.select("2-".parse().unwrap()).unwrap();
+        let prompts: Vec<&str> = selected
+            .records
+            .iter()
+            .map(|r| r.display.as_str())
+            .collect();
+        assert_eq!(prompts, ["two", "three"]);
+        assert_eq!(selected.start(), 2000);
+
+        for out_of_bounds in ["4", "2-4", "4-"] {
+            let err = session.select(out_of_bounds.parse().unwrap()).unwrap_err();
+            assert!(
+                err.to_string().contains("the session has 3 prompts"),
+                "{out_of_bounds}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(0), "0s");
+        assert_eq!(format_duration(45_999), "45s");
+        assert_eq!(format_duration(723_000), "12m 3s");
+        assert_eq!(format_duration(7_500_000), "2h 5m");
+        assert_eq!(format_duration(90_000_000), "1d 1h");
+    }
+
+    #[test]
+    fn test_display_timeline() {
+        let session = Session::from_records(vec![
+            record("/model", 1766567616000, "/Users/elliotxx"),
+            record(
+                "fix the parser\nand add tests",
+                1766568339000,
+                "/Users/elliotxx",
+            ),
+        ])
+        .unwrap();
+
+        let output = session.to_string();
+        assert!(output.contains("Project:  /Users/elliotxx"));
+        assert!(output.contains("Duration: 12m 3s"));
+        assert!(output.contains("Prompts:  2"));
+        assert!(output.contains("  +0s      /model\n"));
+        assert!(output.contains("  +12m 3s  fix the parser\n           and add tests\n"));
+    }
+
+    #[test]
+    fn test_display_pasted_contents() {
+        let session = Session::from_records(vec![record(
+            "why? [Pasted text #1 +2 lines]",
+            1766567616000,
+            "/Users/elliotxx",
+        )
+        .with_pasted(vec![PastedContent {
+            id: 1,
+            kind: PastedKind::Text,
+            content: "error: boom\nat main.rs:3".to_string(),
+            media_type: None,
+        }])])
+        .unwrap();
+
+        assert!(session.to_string().ends_with(
+            "  +0s  why? [Pasted text #1 +2 lines]\n       ── Pasted text #1 (2 lines)\n       │ error: boom\n       │ at main.rs:3\n"
+        ));
+    }
+
+    fn transcript() -> Transcript {
+        Transcript {
+            session_id: "abc123".to_string(),
+            messages: vec![
+                Message::Summary {
+                    text: "Parser fixes".to_string(),
+                },
+                Message::User {
+                    timestamp: Some(1000),
+                    text: "one".to_string(),
+                },
+                Message::AssistantText {
+                    timestamp: Some(1500),
+                    text: "Running the tests.".to_string(),
+                },
+                Message::ToolUse {
+                    timestamp: Some(1600),
+                    id: "toolu_1".to_string(),
+                    name: "Bash".to_string(),
+                    input: json!({"command": "cargo test"}),
+                },
+                Message::ToolResult {
+                    timestamp: Some(1700),
+                    tool_use_id: "toolu_1".to_string(),
+                    content: "test result: ok\n3 passed".to_string(),
+                    is_error: false,
+                },
+                Message::User {
+                    timestamp: Some(2000),
+                    text: "two".to_string(),
+                },
+                Message::AssistantText {
+                    timestamp: Some(2500),
+                    text: "Done.".to_string(),
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn test_replies_follow_their_prompt() {
+        let session = Session::from_records(vec![
+            record("one", 1000, "/app"),
+            record("two", 2000, "/app"),
+        ])
+        .unwrap()
+        .with_transcript(transcript());
+
+        assert_eq!(session.replies(0).len(), 3);
+        assert_eq!(
+            session.replies(1),
+            [&Message::AssistantText {
+                timestamp: Some(2500),
+                text: "Done.".to_string()
+            }]
+        );
+
+        let selected = session.select("2".parse().unwrap()).unwrap();
+        assert_eq!(selected.replies(0).len(), 1);
+        let summaries: Vec<&str> = selected.transcript.as_ref().unwrap().summaries().collect();
+        assert_eq!(summaries, ["Parser fixes"]);
+
+        let output = session.to_string();
+        assert!(output.contains("Summary:  Parser fixes"), "{output}");
+        assert!(output.contains("⏺ Running the tests.\n"), "{output}");
+        assert!(output.contains("→ Bash: cargo test\n"), "{output}");
+        assert!(output.contains("← test result: ok (2 lines)\n"), "{output}");
+    }
+}
@@ -1,8 +1,11 @@
 //! Storage module for managing ~/.claude-memo/ data
 
-use chrono::{DateTime, TimeZone, Utc};
+use crate::parser::SessionRecord;
+use crate::timefmt::{FormatTime, TimeFormat};
+use chrono::{TimeZone, Utc};
+use rusqlite::{params, params_from_iter, Connection, OptionalExtension, TransactionBehavior};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::fs;
 use std::path::{Path, PathBuf};
 
@@ -26,21 +29,41 @@ impl FavoriteSession {
     }
 }
 
-impl std::fmt::Display for FavoriteSession {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let datetime: DateTime<Utc> = Utc
-            .timestamp_millis_opt(self.favorited_at)
-            .single()
-            .unwrap_or(Utc::now());
+impl FormatTime for FavoriteSession {
+    fn fmt_time(&self, f: &mut std::fmt::Formatter<'_>, format: &TimeFormat) -> std::fmt::Result {
         write!(
             f,
             "⭐ {} ({})",
             self.session_id,
-            datetime.format("%Y-%m-%d %H:%M")
+            format.format(self.favorited_at)
         )
     }
 }
 
+impl std::fmt::Display for FavoriteSession {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.fmt_time(f, &TimeFormat::default())
+    }
+}
+
+/// Title and note attached to a favorited session
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Annotation {
+    /// Short title shown instead of the last prompt
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    /// Free-form note
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+}
+
+impl Annotation {
+    /// Whether neither a title nor a note is set
+    pub fn is_empty(&self) -> bool {
+        self.title.is_none() && self.note.is_none()
+    }
+}
+
 /// Enriched favorite with session details from history
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct FavoriteWithDetails {
@@ -54,6 +77,12 @@ pub struct FavoriteWithDetails {
     pub project: String,
     /// Timestamp from the session's most recent record (milliseconds)
     pub session_timestamp: i64,
+    /// Tags of the session, sorted
+    pub tags: Vec<String>,
+    /// Title given when marking the session
+    pub title: Option<String>,
+    /// Note attached to the session
+    pub note: Option<String>,
 }
 
 impl FavoriteWithDetails {
@@ -72,72 +101,140 @@ impl FavoriteWithDetails {
             display,
             project,
             session_timestamp,
+            tags: Vec::new(),
+            title: None,
+            note: None,
         }
     }
 }
 
-impl std::fmt::Display for FavoriteWithDetails {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let datetime: DateTime<Utc> = Utc
+impl FormatTime for FavoriteWithDetails {
+    fn fmt_time(&self, f: &mut std::fmt::Formatter<'_>, format: &TimeFormat) -> std::fmt::Result {
+        // Sessions no longer in the history fall back to the time of the mark
+        let timestamp = if Utc
             .timestamp_millis_opt(self.session_timestamp)
             .single()
-            .unwrap_or_else(|| {
-                Utc.timestamp_millis_opt(self.favorited_at)
-                    .single()
-                    .unwrap_or(Utc::now())
-            });
+            .is_some()
+        {
+            self.session_timestamp
+        } else {
+            self.favorited_at
+        };
         write!(
             f,
             "{} {} > {}  [{}]",
-            datetime.format("%Y-%m-%d %H:%M"),
+            format.format(timestamp),
             self.project,
-            self.display,
+            self.title.as_deref().unwrap_or(&self.display),
             self.session_id
-        )
+        )?;
+        for tag in &self.tags {
+            write!(f, " #{tag}")?;
+        }
+        for line in self.note.iter().flat_map(|note| note.lines()) {
+            write!(f, "\n    {line}")?;
+        }
+        Ok(())
     }
 }
 
-/// Storage for favorites using TOML format
-#[derive(Debug, Clone)]
+impl std::fmt::Display for FavoriteWithDetails {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.fmt_time(f, &TimeFormat::default())
+    }
+}
+
+/// File name of the favorites database inside the data directory
+const STORE_FILE: &str = "marks.db";
+
+/// Version of the favorites database schema, stored in `PRAGMA user_version`
+pub const STORE_SCHEMA_VERSION: i32 = MIGRATIONS.len() as i32;
+
+/// Schema migrations; entry `i` upgrades the database from version `i`
+const MIGRATIONS: &[&str] = &[
+    // 1: favorites with titles and notes, tags, and full-text search on notes
+    r#"
+    CREATE TABLE favorites (
+        id INTEGER PRIMARY KEY,
+        session_id TEXT NOT NULL UNIQUE,
+        favorited_at INTEGER NOT NULL,
+        title TEXT,
+        note TEXT
+    );
+
+    CREATE TABLE tags (
+        session_id TEXT NOT NULL REFERENCES favorites(session_id) ON DELETE CASCADE,
+        tag TEXT NOT NULL,
+        PRIMARY KEY (session_id, tag)
+    );
+    CREATE INDEX tags_tag_idx ON tags(tag);
+
+    CREATE VIRTUAL TABLE notes_fts USING fts5(
+        title,
+        note,
+        content='favorites',
+        content_rowid='id',
+        tokenize='cjk_bigram'
+    );
+
+    CREATE TRIGGER favorites_ai AFTER INSERT ON favorites BEGIN
+        INSERT INTO notes_fts(rowid, title, note) VALUES (new.id, new.title, new.note);
+    END;
+    CREATE TRIGGER favorites_ad AFTER DELETE ON favorites BEGIN
+        INSERT INTO notes_fts(notes_fts, rowid, title, note)
+        VALUES ('delete', old.id, old.title, old.note);
+    END;
+    CREATE TRIGGER favorites_au AFTER UPDATE ON favorites BEGIN
+        INSERT INTO notes_fts(notes_fts, rowid, title, note)
+        VALUES ('delete', old.id, old.title, old.note);
+        INSERT INTO notes_fts(rowid, title, note) VALUES (new.id, new.title, new.note);
+    END;
+    "#,
+];
+
+/// Storage for favorites, tags and notes in a SQLite database
+#[derive(Debug)]
 pub struct Storage {
     /// Path to the data directory (~/.claude-memo/)
     data_dir: PathBuf,
-    /// Path to the favorites TOML file
-    favorites_file: PathBuf,
-    /// In-memory cache of favorites
-    favorites: HashMap<String, i64>,
+    /// Connection to the favorites database
+    conn: Connection,
 }
 
 impl Storage {
     /// Create a new Storage instance
-    /// Initializes the data directory and loads favorites
+    /// Initializes the data directory and opens the favorites database
     pub fn new() -> Result<Self, crate::error::Error> {
-        let data_dir = get_data_dir()?;
-        let favorites_file = data_dir.join("favorites/sessions.toml");
+        Self::open(&crate::datadir::data_dir()?)
+    }
 
-        // Create directories if they don't exist
-        if !data_dir.exists() {
-            fs::create_dir_all(&data_dir)?;
+    /// Open the storage in a data directory
+    ///
+    /// Creates or upgrades the database schema. On first run, favorites,
+    /// titles, notes and tags from the old TOML files are imported.
+    pub fn open(data_dir: &Path) -> Result<Self, crate::error::Error> {
+        fs::create_dir_all(data_dir)?;
+
+        let mut conn = Connection::open(data_dir.join(STORE_FILE))?;
+        // Triggers on `favorites` feed the notes index
+        crate::tokenizer::register(&conn)?;
+        conn.busy_timeout(std::time::Duration::from_secs(5))?;
+        conn.pragma_update(None, "foreign_keys", true)?;
+
+        if user_version(&conn)? != STORE_SCHEMA_VERSION {
+            migrate(&mut conn, data_dir)?;
         }
-        if !favorites_file.parent().map(|p| p.exists()).unwrap_or(false) {
-            fs::create_dir_all(favorites_file.parent().unwrap())?;
-        }
-
-        // Load favorites or create empty
-        let favorites = if favorites_file.exists() {
-            load_favorites(&favorites_file)?
-        } else {
-            HashMap::new()
-        };
 
         Ok(Self {
-            data_dir,
-            favorites_file,
-            favorites,
+            data_dir: data_dir.to_path_buf(),
+            conn,
         })
     }
 
     /// Add a session to favorites
+    ///
+    /// Marking a session again refreshes its timestamp and keeps its title,
+    /// note and tags.
     pub fn add_favorite(&mut self, session_id: &str) -> Result<(), crate::error::Error> {
         // Validate session_id format (basic UUID check)
         if session_id.is_empty() {
@@ -147,133 +244,553 @@ impl Storage {
         }
 
         let now = chrono::Utc::now().timestamp_millis();
-        self.favorites.insert(session_id.to_string(), now);
-        save_favorites(&self.favorites_file, &self.favorites)?;
+        self.conn.execute(
+            "INSERT INTO favorites (session_id, favorited_at) VALUES (?1, ?2)
+             ON CONFLICT(session_id) DO UPDATE SET favorited_at = excluded.favorited_at",
+            params![session_id, now],
+        )?;
         Ok(())
     }
 
-    /// Remove a session from favorites
+    /// Remove a session from favorites, along with its title, note and tags
     pub fn remove_favorite(&mut self, session_id: &str) -> Result<(), crate::error::Error> {
-        if !self.favorites.contains_key(session_id) {
+        let removed = self.conn.execute(
+            "DELETE FROM favorites WHERE session_id = ?1",
+            params![session_id],
+        )?;
+        if removed == 0 {
             return Err(crate::error::Error::SessionNotFound(session_id.to_string()));
         }
-
-        self.favorites.remove(session_id);
-        save_favorites(&self.favorites_file, &self.favorites)?;
         Ok(())
     }
 
-    /// List all favorites
-    pub fn list_favorites(&self) -> Vec<FavoriteSession> {
-        let mut favorites: Vec<FavoriteSession> = self
-            .favorites
-            .iter()
-            .map(|(session_id, &favorited_at)| {
-                FavoriteSession::new(session_id.clone(), favorited_at)
-            })
-            .collect();
+    /// Remove several sessions from favorites at once
+    ///
+    /// IDs that are not favorited are ignored. Returns the number removed.
+    pub fn remove_favorites(
+        &mut self,
+        session_ids: &[String],
+    ) -> Result<usize, crate::error::Error> {
+        let tx = self.conn.transaction()?;
+        let mut removed = 0;
+        {
+            let mut stmt = tx.prepare("DELETE FROM favorites WHERE session_id = ?1")?;
+            for session_id in session_ids {
+                removed += stmt.execute(params![session_id])?;
+            }
+        }
+        tx.commit()?;
+        Ok(removed)
+    }
 
-        // Sort by favorited_at descending (most recent first)
-        favorites.sort_by_key(|b| std::cmp::Reverse(b.favorited_at));
-        favorites
+    /// Set or clear the title of a favorited session
+    ///
+    /// Blank titles clear it.
+    pub fn set_title(
+        &mut self,
+        session_id: &str,
+        title: Option<&str>,
+    ) -> Result<(), crate::error::Error> {
+        self.annotate(session_id, "title", non_blank(title))
     }
 
-    /// Check if a session is favorited
-    pub fn is_favorited(&self, session_id: &str) -> bool {
-        self.favorites.contains_key(session_id)
+    /// Set or clear the note of a favorited session
+    ///
+    /// Trailing whitespace is trimmed and blank notes clear it.
+    pub fn set_note(
+        &mut self,
+        session_id: &str,
+        note: Option<&str>,
+    ) -> Result<(), crate::error::Error> {
+        self.annotate(session_id, "note", non_blank(note))
     }
 
-    /// Get the data directory path
-    pub fn data_dir(&self) -> &PathBuf {
-        &self.data_dir
+    /// Get the title and note of a session
+    pub fn annotation(&self, session_id: &str) -> Result<Annotation, crate::error::Error> {
+        let annotation = self
+            .conn
+            .query_row(
+                "SELECT title, note FROM favorites WHERE session_id = ?1",
+                params![session_id],
+                |row| {
+                    Ok(Annotation {
+                        title: row.get(0)?,
+                        note: row.get(1)?,
+                    })
+                },
+            )
+            .optional()?;
+        Ok(annotation.unwrap_or_default())
     }
 
-    /// Get favorites enriched with session details from history file
-    /// This provides useful context (display, project, timestamp) for each favorite
-    pub fn list_favorites_with_details(
-        &self,
-        history_path: &Path,
-    ) -> Result<Vec<FavoriteWithDetails>, crate::error::Error> {
-        let favorites = self.list_favorites();
+    /// Update the title or note column of a favorited session
+    fn annotate(
+        &mut self,
+        session_id: &str,
+        column: &str,
+        value: Option<String>,
+    ) -> Result<(), crate::error::Error> {
+        let updated = self.conn.execute(
+            &format!("UPDATE favorites SET {column} = ?1 WHERE session_id = ?2"),
+            params![value, session_id],
+        )?;
+        if updated == 0 {
+            return Err(crate::error::Error::SessionNotFound(session_id.to_string()));
+        }
+        Ok(())
+    }
 
-        if favorites.is_empty() {
+    /// Full-text search the titles and notes of favorited sessions
+    ///
+    /// Uses the `search` query syntax; field filters are ignored. Returns the
+    /// matching session IDs, best match first.
+    pub fn search_notes(&self, query: &str) -> Result<Vec<String>, crate::error::Error> {
+        let fts = crate::query::Query::parse(query)?.to_fts5();
+        if fts.is_empty() {
             return Ok(Vec::new());
         }
 
-        // Parse history file to get session details
-        let records = if history_path.exists() {
-            crate::parser::parse_history_file(history_path)?
-        } else {
-            Vec::new()
+        let mut conditions = Vec::new();
+        let mut values = Vec::new();
+        // Titles are short and deliberate, so a hit there counts for more
+        let (source, order) = match fts.include {
+            Some(include) => {
+                conditions.push("notes_fts MATCH ?");
+                values.push(include);
+                (
+                    "notes_fts JOIN favorites f ON f.id = notes_fts.rowid",
+                    "bm25(notes_fts, 5, 1), f.session_id",
+                )
+            }
+            None => ("favorites f", "f.session_id"),
         };
+        if let Some(exclude) = fts.exclude {
+            conditions.push("f.id NOT IN (SELECT rowid FROM notes_fts WHERE notes_fts MATCH ?)");
+            values.push(exclude);
+        }
 
-        // Build a map from session_id to most recent record
-        let mut session_map: std::collections::HashMap<String, &crate::parser::SessionRecord> =
-            std::collections::HashMap::new();
-        for record in &records {
-            // Keep the most recent record for each session
-            if !session_map.contains_key(&record.session_id) {
-                session_map.insert(record.session_id.clone(), record);
-            } else {
-                // Replace if this record is newer
-                if record.timestamp > session_map[&record.session_id].timestamp {
-                    session_map.insert(record.session_id.clone(), record);
+        let sql = format!(
+            "SELECT f.session_id FROM {source} WHERE {} ORDER BY {order}",
+            conditions.join(" AND ")
+        );
+        let mut stmt = self.conn.prepare(&sql)?;
+        let ids = stmt
+            .query_map(params_from_iter(values), |row| row.get(0))?
+            .collect::<Result<Vec<String>, _>>()?;
+        Ok(ids)
+    }
+
+    /// Add tags to a session, marking it first if needed
+    ///
+    /// Returns the tags that were not already set.
+    pub fn tag_session(
+        &mut self,
+        session_id: &str,
+        tags: &[String],
+    ) -> Result<Vec<String>, crate::error::Error> {
+        for tag in tags {
+            validate_tag(tag)?;
+        }
+        if session_id.is_empty() {
+            return Err(crate::error::Error::InvalidSessionId(
+                "session_id cannot be empty".to_string(),
+            ));
+        }
+
+        let tx = self.conn.transaction()?;
+        let now = chrono::Utc::now().timestamp_millis();
+        tx.execute(
+            "INSERT INTO favorites (session_id, favorited_at) VALUES (?1, ?2)
+             ON CONFLICT(session_id) DO NOTHING",
+            params![session_id, now],
+        )?;
+
+        let mut added = Vec::new();
+        {
+            let mut stmt =
+                tx.prepare("INSERT OR IGNORE INTO tags (session_id, tag) VALUES (?1, ?2)")?;
+            for tag in tags {
+                if stmt.execute(params![session_id, tag])? > 0 {
+                    added.push(tag.clone());
+                }
+            }
+        }
+        tx.commit()?;
+        Ok(added)
+    }
+
+    /// Remove tags from a session
+    ///
+    /// Returns the tags that were actually removed.
+    pub fn untag_session(
+        &mut self,
+        session_id: &str,
+        tags: &[String],
+    ) -> Result<Vec<String>, crate::error::Error> {
+        let tx = self.conn.transaction()?;
+        let mut removed = Vec::new();
+        {
+            let mut stmt = tx.prepare("DELETE FROM tags WHERE session_id = ?1 AND tag = ?2")?;
+            for tag in tags {
+                if stmt.execute(params![session_id, tag])? > 0 {
+                    removed.push(tag.clone());
                 }
             }
         }
+        tx.commit()?;
+        Ok(removed)
+    }
+
+    /// Get the tags of a session, sorted by name
+    pub fn session_tags(&self, session_id: &str) -> Result<Vec<String>, crate::error::Error> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT tag FROM tags WHERE session_id = ?1 ORDER BY tag")?;
+        let tags = stmt
+            .query_map(params![session_id], |row| row.get(0))?
+            .collect::<Result<Vec<String>, _>>()?;
+        Ok(tags)
+    }
+
+    /// Get every tag with the number of sessions using it, sorted by name
+    pub fn tag_counts(&self) -> Result<Vec<(String, usize)>, crate::error::Error> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT tag, COUNT(*) FROM tags GROUP BY tag ORDER BY tag")?;
+        let counts = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<Vec<(String, usize)>, _>>()?;
+        Ok(counts)
+    }
+
+    /// Get the IDs of all sessions with a tag
+    pub fn sessions_with_tag(&self, tag: &str) -> Result<Vec<String>, crate::error::Error> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT session_id FROM tags WHERE tag = ?1 ORDER BY session_id")?;
+        let sessions = stmt
+            .query_map(params![tag], |row| row.get(0))?
+            .collect::<Result<Vec<String>, _>>()?;
+        Ok(sessions)
+    }
+
+    /// Get the IDs of the sessions using each tag
+    pub fn tagged_sessions(&self) -> Result<HashMap<String, Vec<String>>, crate::error::Error> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT tag, session_id FROM tags ORDER BY tag, session_id")?;
+        let mut tagged: HashMap<String, Vec<String>> = HashMap::new();
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        for row in rows {
+            let (tag, session_id): (String, String) = row?;
+            tagged.entry(tag).or_default().push(session_id);
+        }
+        Ok(tagged)
+    }
+
+    /// Rename a tag on every session
+    ///
+    /// Fails if the new name is already in use; merge the tags instead.
+    /// Returns the number of sessions updated.
+    pub fn rename_tag(&mut self, from: &str, to: &str) -> Result<usize, crate::error::Error> {
+        validate_tag(to)?;
+        if self.sessions_with_tag(from)?.is_empty() {
+            return Err(crate::error::Error::TagNotFound(from.to_string()));
+        }
+        if from != to && !self.sessions_with_tag(to)?.is_empty() {
+            return Err(crate::error::Error::TagExists(to.to_string()));
+        }
+        self.merge_tags(&[from.to_string()], to)
+    }
+
+    /// Replace several tags by one, on every session
+    ///
+    /// Returns the number of sessions updated.
+    pub fn merge_tags(&mut self, from: &[String], to: &str) -> Result<usize, crate::error::Error> {
+        validate_tag(to)?;
+
+        let tx = self.conn.transaction()?;
+        for tag in from {
+            let used: bool = tx.query_row(
+                "SELECT EXISTS (SELECT 1 FROM tags WHERE tag = ?1)",
+                params![tag],
+                |row| row.get(0),
+            )?;
+            if !used {
+                return Err(crate::error::Error::TagNotFound(tag.clone()));
+            }
+        }
 
-        // Enrich favorites with session details
-        let mut enriched: Vec<FavoriteWithDetails> = favorites
-            .iter()
-            .map(|fav| {
-                if let Some(record) = session_map.get(&fav.session_id) {
-                    FavoriteWithDetails::new(
-                        fav.session_id.clone(),
-                        fav.favorited_at,
-                        record.display.clone(),
-                        record.project.clone(),
-                        record.timestamp,
-                    )
-                } else {
-                    // Session not found in history, still include basic info
-                    FavoriteWithDetails::new(
-                        fav.session_id.clone(),
-                        fav.favorited_at,
-                        String::from("(session not found in history)"),
-                        String::new(),
-                        fav.favorited_at,
-                    )
+        // Sessions that already have the target tag only lose the sources
+        let mut updated = BTreeSet::new();
+        for tag in from.iter().filter(|tag| *tag != to) {
+            {
+                let mut stmt = tx.prepare("SELECT session_id FROM tags WHERE tag = ?1")?;
+                let sessions = stmt.query_map(params![tag], |row| row.get::<_, String>(0))?;
+                for session_id in sessions {
+                    updated.insert(session_id?);
                 }
-            })
-            .collect();
+            }
+            tx.execute(
+                "INSERT OR IGNORE INTO tags (session_id, tag)
+                 SELECT session_id, ?2 FROM tags WHERE tag = ?1",
+                params![tag, to],
+            )?;
+            tx.execute("DELETE FROM tags WHERE tag = ?1", params![tag])?;
+        }
+        tx.commit()?;
+        Ok(updated.len())
+    }
 
+    /// List all favorites
+    pub fn list_favorites(&self) -> Result<Vec<FavoriteSession>, crate::error::Error> {
         // Sort by favorited_at descending (most recent first)
-        enriched.sort_by_key(|b| std::cmp::Reverse(b.favorited_at));
+        let mut stmt = self.conn.prepare(
+            "SELECT session_id, favorited_at FROM favorites
+             ORDER BY favorited_at DESC, session_id",
+        )?;
+        let favorites = stmt
+            .query_map([], |row| Ok(FavoriteSession::new(row.get(0)?, row.get(1)?)))?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(favorites)
+    }
+
+    /// Check if a session is favorited
+    pub fn is_favorited(&self, session_id: &str) -> Result<bool, crate::error::Error> {
+        let favorited = self.conn.query_row(
+            "SELECT EXISTS (SELECT 1 FROM favorites WHERE session_id = ?1)",
+            params![session_id],
+            |row| row.get(0),
+        )?;
+        Ok(favorited)
+    }
+
+    /// Get the data directory path
+    pub fn data_dir(&self) -> &PathBuf {
+        &self.data_dir
+    }
+
+    /// Get favorites enriched with their tags, title and note, and with the
+    /// details (display, project, timestamp) of the latest record of each
+    /// session
+    ///
+    /// `latest` holds the latest indexed record of each session, see
+    /// [`crate::indexer::Indexer::latest_records`]. Most recently favorited
+    /// first.
+    pub fn list_favorites_with_details(
+        &self,
+        latest: &HashMap<String, SessionRecord>,
+    ) -> Result<Vec<FavoriteWithDetails>, crate::error::Error> {
+        // Tags contain no whitespace, so they are joined with spaces
+        let mut stmt = self.conn.prepare(
+            "SELECT f.session_id, f.favorited_at, f.title, f.note, group_concat(t.tag, ' ')
+             FROM favorites f
+             LEFT JOIN tags t ON t.session_id = f.session_id
+             GROUP BY f.id
+             ORDER BY f.favorited_at DESC, f.session_id",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, i64>(1)?,
+                row.get::<_, Option<String>>(2)?,
+                row.get::<_, Option<String>>(3)?,
+                row.get::<_, Option<String>>(4)?,
+            ))
+        })?;
+
+        let mut enriched = Vec::new();
+        for row in rows {
+            let (session_id, favorited_at, title, note, tags) = row?;
+            let mut favorite = match latest.get(&session_id) {
+                Some(record) => FavoriteWithDetails::new(
+                    session_id,
+                    favorited_at,
+                    record.display.clone(),
+                    record.project.clone(),
+                    record.timestamp,
+                ),
+                // Session not found in history, still include basic info
+                None => FavoriteWithDetails::new(
+                    session_id,
+                    favorited_at,
+                    String::from("(session not found in history)"),
+                    String::new(),
+                    favorited_at,
+                ),
+            };
+            let mut tags: Vec<String> = tags
+                .unwrap_or_default()
+                .split_whitespace()
+                .map(str::to_string)
+                .collect();
+            tags.sort();
+            favorite.tags = tags;
+            favorite.title = title;
+            favorite.note = note;
+            enriched.push(favorite);
+        }
 
         Ok(enriched)
     }
 }
 
-/// Get the data directory path (~/.claude-memo)
+/// Read the schema version of the favorites database
+fn user_version(conn: &Connection) -> Result<i32, crate::error::Error> {
+    Ok(conn.pragma_query_value(None, "user_version", |row| row.get(0))?)
+}
+
+/// Bring the database schema up to date in a single transaction
 ///
-/// Supports CLAUDE_MEMO_DATA_DIR environment variable for testing.
-/// When CLAUDE_MEMO_DATA_DIR is set, it is used as-is (user provides full path).
-/// Otherwise defaults to ~/.claude-memo
-fn get_data_dir() -> Result<PathBuf, crate::error::Error> {
-    if let Ok(data_dir) = std::env::var("CLAUDE_MEMO_DATA_DIR") {
-        // User provides the full data directory path
-        Ok(PathBuf::from(data_dir))
+/// When the database is first created, the TOML files of earlier versions
+/// are imported and then renamed to `*.migrated`, which keeps them as a
+/// backup without importing them again.
+fn migrate(conn: &mut Connection, data_dir: &Path) -> Result<(), crate::error::Error> {
+    // A concurrent process migrating first makes this one wait, after which
+    // it sees the new version and has nothing left to do
+    let _lock = crate::datadir::DataDirLock::acquire(data_dir)?;
+    let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+    let version = user_version(&tx)?;
+    if version > STORE_SCHEMA_VERSION {
+        return Err(crate::error::Error::UnsupportedSchemaVersion {
+            found: version,
+            supported: STORE_SCHEMA_VERSION,
+        });
+    }
+
+    for migration in &MIGRATIONS[version as usize..] {
+        tx.execute_batch(migration)?;
+    }
+    let imported = if version == 0 {
+        import_legacy_files(&tx, data_dir)?
     } else {
-        // Use default ~/.claude-memo
-        let home = dirs::home_dir().ok_or(crate::error::Error::HomeDirNotFound)?;
-        Ok(home.join(".claude-memo"))
+        Vec::new()
+    };
+    tx.pragma_update(None, "user_version", STORE_SCHEMA_VERSION)?;
+    tx.commit()?;
+
+    for (path, skipped) in imported {
+        let mut backup = path.clone().into_os_string();
+        backup.push(".migrated");
+        fs::rename(&path, &backup)?;
+        if skipped > 0 {
+            eprintln!(
+                "Warning: skipped {skipped} unreadable lines of {}, the original is kept as {}",
+                path.display(),
+                PathBuf::from(backup).display()
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Import favorites, titles, notes and tags from the old TOML files
+///
+/// Returns the files that were imported, with the number of lines that
+/// could not be read.
+fn import_legacy_files(
+    conn: &Connection,
+    data_dir: &Path,
+) -> Result<Vec<(PathBuf, usize)>, crate::error::Error> {
+    let favorites_file = data_dir.join("favorites/sessions.toml");
+    let tags_file = data_dir.join("tags/sessions.toml");
+    let mut imported = Vec::new();
+
+    if favorites_file.exists() {
+        let (data, skipped) = read_legacy_file(&favorites_file)?;
+        let annotations = load_annotations(&data)?;
+        let mut stmt = conn.prepare(
+            "INSERT OR IGNORE INTO favorites (session_id, favorited_at, title, note)
+             VALUES (?1, ?2, ?3, ?4)",
+        )?;
+        for (session_id, favorited_at) in load_favorites(&data) {
+            let annotation = annotations.get(&session_id).cloned().unwrap_or_default();
+            stmt.execute(params![
+                session_id,
+                favorited_at,
+                annotation.title,
+                annotation.note
+            ])?;
+        }
+        imported.push((favorites_file, skipped));
     }
+
+    if tags_file.exists() {
+        let (data, skipped) = read_legacy_file(&tags_file)?;
+        // Tags were only ever kept for favorited sessions
+        let mut stmt = conn.prepare(
+            "INSERT OR IGNORE INTO tags (session_id, tag)
+             SELECT ?1, ?2 WHERE EXISTS (SELECT 1 FROM favorites WHERE session_id = ?1)",
+        )?;
+        for (session_id, tags) in load_tags(&data)? {
+            for tag in tags {
+                stmt.execute(params![session_id, tag])?;
+            }
+        }
+        imported.push((tags_file, skipped));
+    }
+
+    Ok(imported)
 }
 
-/// Load favorites from TOML file
-fn load_favorites(path: &PathBuf) -> Result<HashMap<String, i64>, crate::error::Error> {
+/// Read an old TOML file, skipping the lines that do not parse
+///
+/// The old files were written by hand and may contain IDs with unescaped
+/// quotes. When the whole file does not parse, it is read line by line:
+/// each line is kept if the file read so far still parses with it, and a
+/// broken table header drops the keys under it too. Returns the table and
+/// the number of lines skipped.
+fn read_legacy_file(path: &Path) -> Result<(toml::Table, usize), crate::error::Error> {
     let content = fs::read_to_string(path)?;
-    let data: toml::Value = content.parse().map_err(crate::error::Error::TomlParse)?;
+    if let Ok(table) = content.parse() {
+        return Ok((table, 0));
+    }
+
+    let mut accepted = String::new();
+    // Lines that do not parse yet, e.g. the start of a multi-line string
+    let mut pending = String::new();
+    let mut pending_lines = 0;
+    let mut skipped = 0;
+    let mut in_broken_table = false;
+    for line in content.lines() {
+        let is_header = line.trim_start().starts_with('[');
+        if in_broken_table && !is_header {
+            skipped += 1;
+            continue;
+        }
+        let with_pending = format!("{accepted}{pending}{line}\n");
+        if with_pending.parse::<toml::Table>().is_ok() {
+            accepted = with_pending;
+            pending.clear();
+            pending_lines = 0;
+            in_broken_table = false;
+            continue;
+        }
+        let alone = format!("{accepted}{line}\n");
+        if pending_lines > 0 && alone.parse::<toml::Table>().is_ok() {
+            accepted = alone;
+            skipped += pending_lines;
+            pending.clear();
+            pending_lines = 0;
+            in_broken_table = false;
+        } else if is_header {
+            skipped += pending_lines + 1;
+            pending.clear();
+            pending_lines = 0;
+            in_broken_table = true;
+        } else {
+            pending.push_str(line);
+            pending.push('\n');
+            pending_lines += 1;
+        }
+    }
+    skipped += pending_lines;
+
+    Ok((accepted.parse()?, skipped))
+}
+
+/// Load favorites from the old TOML file
+fn load_favorites(data: &toml::Table) -> HashMap<String, i64> {
     let mut favorites = HashMap::new();
 
     if let Some(sessions) = data.get("sessions").and_then(|s| s.as_table()) {
@@ -284,29 +801,59 @@ fn load_favorites(path: &PathBuf) -> Result<HashMap<String, i64>, crate::error::
         }
     }
 
-    Ok(favorites)
+    favorites
 }
 
-/// Save favorites to TOML file
-fn save_favorites(
-    path: &PathBuf,
-    favorites: &HashMap<String, i64>,
-) -> Result<(), crate::error::Error> {
-    let mut content = String::new();
-    content.push_str("[sessions]\n");
+/// Load titles and notes from the old favorites TOML file
+fn load_annotations(
+    data: &toml::Table,
+) -> Result<HashMap<String, Annotation>, crate::error::Error> {
+    let data: FavoritesFile = toml::Value::Table(data.clone()).try_into()?;
+    Ok(data.annotations.into_iter().collect())
+}
 
-    // Sort by timestamp descending for readability
-    let mut entries: Vec<_> = favorites.iter().collect();
-    entries.sort_by(|a, b| b.1.cmp(a.1));
+/// Part of the favorites file layout holding titles and notes
+#[derive(Debug, Default, Deserialize)]
+struct FavoritesFile {
+    #[serde(default)]
+    annotations: BTreeMap<String, Annotation>,
+}
 
-    for (session_id, &timestamp) in entries {
-        content.push_str(&format!("\"{}\" = {}\n", session_id, timestamp));
-    }
+/// Trim a title or note, treating blank text as unset
+fn non_blank(text: Option<&str>) -> Option<String> {
+    text.map(str::trim_end)
+        .filter(|text| !text.trim().is_empty())
+        .map(String::from)
+}
 
-    fs::write(path, content)?;
+/// Check that a tag is usable on the command line and in `tag:` filters
+fn validate_tag(tag: &str) -> Result<(), crate::error::Error> {
+    if tag.is_empty() {
+        return Err(crate::error::Error::InvalidTag(
+            "tag cannot be empty".to_string(),
+        ));
+    }
+    if tag.chars().any(|c| c.is_whitespace() || c == '"') {
+        return Err(crate::error::Error::InvalidTag(format!(
+            "'{tag}' contains whitespace or quotes"
+        )));
+    }
     Ok(())
 }
 
+/// Tags file layout: session ID to its tags
+#[derive(Debug, Default, Deserialize)]
+struct TagsFile {
+    #[serde(default)]
+    sessions: BTreeMap<String, BTreeSet<String>>,
+}
+
+/// Load tags from the old TOML file
+fn load_tags(data: &toml::Table) -> Result<HashMap<String, BTreeSet<String>>, crate::error::Error> {
+    let data: TagsFile = toml::Value::Table(data.clone()).try_into()?;
+    Ok(data.sessions.into_iter().collect())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -323,69 +870,45 @@ mod tests {
     #[test]
     fn test_storage_add_favorite() {
         let temp_dir = TempDir::new().unwrap();
-        let favorites_file = temp_dir.path().join("sessions.toml");
-
-        let mut storage = Storage {
-            data_dir: temp_dir.path().to_path_buf(),
-            favorites_file: favorites_file.clone(),
-            favorites: HashMap::new(),
-        };
+        let mut storage = Storage::open(temp_dir.path()).unwrap();
 
         let result = storage.add_favorite("test-session-id");
         assert!(result.is_ok());
-        assert!(storage.is_favorited("test-session-id"));
+        assert!(storage.is_favorited("test-session-id").unwrap());
 
-        // Verify file was created
-        assert!(favorites_file.exists());
+        // Verify the database was created
+        assert!(temp_dir.path().join(STORE_FILE).exists());
     }
 
     #[test]
     fn test_storage_remove_favorite() {
         let temp_dir = TempDir::new().unwrap();
-        let favorites_file = temp_dir.path().join("sessions.toml");
-
-        let mut storage = Storage {
-            data_dir: temp_dir.path().to_path_buf(),
-            favorites_file: favorites_file.clone(),
-            favorites: HashMap::new(),
-        };
+        let mut storage = Storage::open(temp_dir.path()).unwrap();
 
         storage.add_favorite("test-session-id").unwrap();
-        assert!(storage.is_favorited("test-session-id"));
+        assert!(storage.is_favorited("test-session-id").unwrap());
 
         storage.remove_favorite("test-session-id").unwrap();
-        assert!(!storage.is_favorited("test-session-id"));
+        assert!(!storage.is_favorited("test-session-id").unwrap());
     }
 
     #[test]
     fn test_storage_list_favorites() {
         let temp_dir = TempDir::new().unwrap();
-        let favorites_file = temp_dir.path().join("sessions.toml");
-
-        let mut storage = Storage {
-            data_dir: temp_dir.path().to_path_buf(),
-            favorites_file,
-            favorites: HashMap::new(),
-        };
+        let mut storage = Storage::open(temp_dir.path()).unwrap();
 
         storage.add_favorite("session-1").unwrap();
         storage.add_favorite("session-2").unwrap();
         storage.add_favorite("session-3").unwrap();
 
-        let favorites = storage.list_favorites();
+        let favorites = storage.list_favorites().unwrap();
         assert_eq!(favorites.len(), 3);
     }
 
     #[test]
     fn test_add_empty_session_id_fails() {
         let temp_dir = TempDir::new().unwrap();
-        let favorites_file = temp_dir.path().join("sessions.toml");
-
-        let mut storage = Storage {
-            data_dir: temp_dir.path().to_path_buf(),
-            favorites_file,
-            favorites: HashMap::new(),
-        };
+        let mut storage = Storage::open(temp_dir.path()).unwrap();
 
         let result = storage.add_favorite("");
         assert!(result.is_err());
@@ -394,35 +917,326 @@ mod tests {
     #[test]
     fn test_remove_nonexistent_session_fails() {
         let temp_dir = TempDir::new().unwrap();
-        let favorites_file = temp_dir.path().join("sessions.toml");
-
-        let mut storage = Storage {
-            data_dir: temp_dir.path().to_path_buf(),
-            favorites_file,
-            favorites: HashMap::new(),
-        };
+        let mut storage = Storage::open(temp_dir.path()).unwrap();
 
         let result = storage.remove_favorite("nonexistent");
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_remove_favorites() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut storage = Storage::open(temp_dir.path()).unwrap();
+
+        storage.add_favorite("session-1").unwrap();
+        storage.add_favorite("session-2").unwrap();
+        storage.add_favorite("session-3").unwrap();
+
+        let removed = storage
+            .remove_favorites(&[
+                "session-1".to_string(),
+                "missing".to_string(),
+                "session-3".to_string(),
+            ])
+            .unwrap();
+        assert_eq!(removed, 2);
+        assert!(storage.is_favorited("session-2").unwrap());
+        assert!(!storage.is_favorited("session-1").unwrap());
+
+        // The removal is persisted
+        let reloaded = Storage::open(temp_dir.path()).unwrap();
+        assert_eq!(reloaded.list_favorites().unwrap().len(), 1);
+    }
+
+    fn tags(names: &[&str]) -> Vec<String> {
+        names.iter().map(|n| n.to_string()).collect()
+    }
+
+    #[test]
+    fn test_tag_session_marks_and_persists() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut storage = Storage::open(temp_dir.path()).unwrap();
+
+        let added = storage
+            .tag_session("session-1", &tags(&["refactor", "rust"]))
+            .unwrap();
+        assert_eq!(added, tags(&["refactor", "rust"]));
+        assert!(storage.is_favorited("session-1").unwrap());
+
+        // Already set tags are not reported again
+        let added = storage
+            .tag_session("session-1", &tags(&["rust", "cli"]))
+            .unwrap();
+        assert_eq!(added, tags(&["cli"]));
+
+        let reloaded = Storage::open(temp_dir.path()).unwrap();
+        assert_eq!(reloaded.session_tags("session-1").unwrap().len(), 3);
+        assert_eq!(
+            storage.session_tags("session-1").unwrap(),
+            tags(&["cli", "refactor", "rust"])
+        );
+    }
+
+    #[test]
+    fn test_list_favorites_with_details() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut storage = Storage::open(temp_dir.path()).unwrap();
+        storage
+            .tag_session("session-1", &tags(&["rust", "cli"]))
+            .unwrap();
+        storage.set_title("session-1", Some("Parser fix")).unwrap();
+        storage.add_favorite("session-2").unwrap();
+        let latest = HashMap::from([(
+            "session-1".to_string(),
+            SessionRecord::new(
+                "fix the parser".to_string(),
+                1766567616000,
+                "/Users/elliotxx/app".to_string(),
+                "session-1".to_string(),
+            ),
+        )]);
+
+        let favorites = storage.list_favorites_with_details(&latest).unwrap();
+        assert_eq!(favorites.len(), 2);
+        let favorite = |id: &str| favorites.iter().find(|f| f.session_id == id).unwrap();
+
+        let found = favorite("session-1");
+        assert_eq!(found.display, "fix the parser");
+        assert_eq!(found.session_timestamp, 1766567616000);
+        assert_eq!(found.tags, tags(&["cli", "rust"]));
+        assert_eq!(found.title.as_deref(), Some("Parser fix"));
+
+        let missing = favorite("session-2");
+        assert_eq!(missing.display, "(session not found in history)");
+        assert!(missing.tags.is_empty());
+        assert_eq!(missing.title, None);
+    }
+
+    #[test]
+    fn test_tag_session_rejects_invalid_tags() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut storage = Storage::open(temp_dir.path()).unwrap();
+
+        assert!(storage.tag_session("session-1", &tags(&[""])).is_err());
+        assert!(storage
+            .tag_session("session-1", &tags(&["two words"]))
+            .is_err());
+        assert!(!storage.is_favorited("session-1").unwrap());
+    }
+
+    #[test]
+    fn test_untag_and_unmark_remove_tags() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut storage = Storage::open(temp_dir.path()).unwrap();
+        storage
+            .tag_session("session-1", &tags(&["refactor", "rust"]))
+            .unwrap();
+        storage.tag_session("session-2", &tags(&["rust"])).unwrap();
+
+        let removed = storage
+            .untag_session("session-1", &tags(&["refactor", "missing"]))
+            .unwrap();
+        assert_eq!(removed, tags(&["refactor"]));
+
+        storage.remove_favorite("session-2").unwrap();
+        assert!(storage.session_tags("session-2").unwrap().is_empty());
+        assert_eq!(storage.tag_counts().unwrap(), vec![("rust".to_string(), 1)]);
+    }
+
+    #[test]
+    fn test_tag_counts_and_sessions_with_tag() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut storage = Storage::open(temp_dir.path()).unwrap();
+        storage
+            .tag_session("session-1", &tags(&["refactor", "rust"]))
+            .unwrap();
+        storage.tag_session("session-2", &tags(&["rust"])).unwrap();
+
+        assert_eq!(
+            storage.tag_counts().unwrap(),
+            vec![("refactor".to_string(), 1), ("rust".to_string(), 2)]
+        );
+        assert_eq!(
+            storage.sessions_with_tag("rust").unwrap(),
+            tags(&["session-1", "session-2"])
+        );
+        assert!(storage.sessions_with_tag("missing").unwrap().is_empty());
+
+        let tagged = storage.tagged_sessions().unwrap();
+        assert_eq!(tagged["refactor"], tags(&["session-1"]));
+        assert_eq!(tagged["rust"].len(), 2);
+    }
+
+    #[test]
+    fn test_rename_and_merge_tags() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut storage = Storage::open(temp_dir.path()).unwrap();
+        storage
+            .tag_session("session-1", &tags(&["bug", "rust"]))
+            .unwrap();
+        storage
+            .tag_session("session-2", &tags(&["bugfix"]))
+            .unwrap();
+
+        assert_eq!(storage.rename_tag("rust", "rustlang").unwrap(), 1);
+        assert_eq!(
+            storage.session_tags("session-1").unwrap(),
+            tags(&["bug", "rustlang"])
+        );
+
+        // Renaming onto an existing tag must be an explicit merge
+        assert!(matches!(
+            storage.rename_tag("bug", "bugfix"),
+            Err(crate::error::Error::TagExists(_))
+        ));
+        assert!(matches!(
+            storage.rename_tag("missing", "other"),
+            Err(crate::error::Error::TagNotFound(_))
+        ));
+
+        assert_eq!(
+            storage
+                .merge_tags(&tags(&["bug", "bugfix"]), "fix")
+                .unwrap(),
+            2
+        );
+        assert_eq!(
+            storage.tag_counts().unwrap(),
+            vec![("fix".to_string(), 2), ("rustlang".to_string(), 1)]
+        );
+    }
+
+    #[test]
+    fn test_annotations_persist_with_favorites() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut storage = Storage::open(temp_dir.path()).unwrap();
+
+        assert!(matches!(
+            storage.set_title("session-1", Some("not marked")),
+            Err(crate::error::Error::SessionNotFound(_))
+        ));
+
+        storage.add_favorite("session-1").unwrap();
+        storage
+            .set_title("session-1", Some("Parser \"rewrite\""))
+            .unwrap();
+        storage
+            .set_note("session-1", Some("why:\nthe lexer = fixed\n\n"))
+            .unwrap();
+
+        let reloaded = Storage::open(temp_dir.path()).unwrap();
+        let annotation = reloaded.annotation("session-1").unwrap();
+        assert_eq!(annotation.title.as_deref(), Some("Parser \"rewrite\""));
+        assert_eq!(annotation.note.as_deref(), Some("why:\nthe lexer = fixed"));
+
+        // Blank text clears
+        storage.set_note("session-1", Some("  \n")).unwrap();
+        assert_eq!(storage.annotation("session-1").unwrap().note, None);
+
+        storage.remove_favorite("session-1").unwrap();
+        assert_eq!(
+            storage.annotation("session-1").unwrap(),
+            Annotation::default()
+        );
+    }
+
+    #[test]
+    fn test_search_notes() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut storage = Storage::open(temp_dir.path()).unwrap();
+        for (id, title, note) in [
+            (
+                "session-1",
+                "Parser rewrite",
+                "switched to a hand-written lexer",
+            ),
+            (
+                "session-2",
+                "Release",
+                "lexer regression found before the release",
+            ),
+            ("session-3", "数据库迁移", "记录了迁移步骤"),
+        ] {
+            storage.add_favorite(id).unwrap();
+            storage.set_title(id, Some(title)).unwrap();
+            storage.set_note(id, Some(note)).unwrap();
+        }
+
+        let mut ids = storage.search_notes("lexer").unwrap();
+        ids.sort();
+        assert_eq!(ids, tags(&["session-1", "session-2"]));
+
+        assert_eq!(
+            storage.search_notes("lexer -release").unwrap(),
+            tags(&["session-1"])
+        );
+        assert_eq!(
+            storage.search_notes("release").unwrap(),
+            tags(&["session-2"])
+        );
+        assert_eq!(storage.search_notes("迁移").unwrap(), tags(&["session-3"]));
+        assert!(storage.search_notes("missing").unwrap().is_empty());
+        assert!(storage.search_notes("tag:rust").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_open_rejects_newer_schema() {
+        let temp_dir = TempDir::new().unwrap();
+        drop(Storage::open(temp_dir.path()).unwrap());
+
+        let conn = Connection::open(temp_dir.path().join(STORE_FILE)).unwrap();
+        conn.pragma_update(None, "user_version", STORE_SCHEMA_VERSION + 1)
+            .unwrap();
+        drop(conn);
+
+        assert!(matches!(
+            Storage::open(temp_dir.path()),
+            Err(crate::error::Error::UnsupportedSchemaVersion { .. })
+        ));
+    }
+
+    #[test]
+    fn test_migration_skips_unreadable_lines() {
+        let temp_dir = TempDir::new().unwrap();
+        let favorites_dir = temp_dir.path().join("favorites");
+        fs::create_dir_all(&favorites_dir).unwrap();
+        // What the old hand-written TOML produced for an ID with a quote
+        let content = "[sessions]\n\"ok\" = 1\n\"bad\"id\" = 2\n\"also-ok\" = 3\n\n\
+                       [annotations.\"ok\"]\nnote = \"\"\"\nfirst\nsecond\"\"\"\n\n\
+                       [annotations.\"bad\"id\"]\ntitle = \"lost\"\n";
+        fs::write(favorites_dir.join("sessions.toml"), content).unwrap();
+
+        let (data, skipped) = read_legacy_file(&favorites_dir.join("sessions.toml")).unwrap();
+        assert_eq!(skipped, 3);
+        assert_eq!(load_favorites(&data).len(), 2);
+
+        let storage = Storage::open(temp_dir.path()).unwrap();
+        assert!(storage.is_favorited("ok").unwrap());
+        assert!(storage.is_favorited("also-ok").unwrap());
+        assert_eq!(
+            storage.annotation("ok").unwrap().note.as_deref(),
+            Some("first\nsecond")
+        );
+        assert_eq!(storage.list_favorites().unwrap().len(), 2);
+        // The original is kept as a backup
+        assert!(!favorites_dir.join("sessions.toml").exists());
+        assert_eq!(
+            fs::read_to_string(favorites_dir.join("sessions.toml.migrated")).unwrap(),
+            content
+        );
+    }
+
     // === Edge Case Tests ===
 
     #[test]
     fn test_duplicate_session_id() {
         // Adding the same session_id twice should succeed
         let temp_dir = TempDir::new().unwrap();
-        let favorites_file = temp_dir.path().join("sessions.toml");
-
-        let mut storage = Storage {
-            data_dir: temp_dir.path().to_path_buf(),
-            favorites_file: favorites_file.clone(),
-            favorites: HashMap::new(),
-        };
+        let mut storage = Storage::open(temp_dir.path()).unwrap();
 
         // Add same session twice
         storage.add_favorite("same-session").unwrap();
-        assert!(storage.is_favorited("same-session"));
+        assert!(storage.is_favorited("same-session").unwrap());
 
         // Second add should still succeed
         let result = storage.add_favorite("same-session");
@@ -432,30 +1246,16 @@ mod tests {
     #[test]
     fn test_is_favorited_returns_false_for_nonexistent() {
         let temp_dir = TempDir::new().unwrap();
-        let favorites_file = temp_dir.path().join("sessions.toml");
-
-        let storage = Storage {
-            data_dir: temp_dir.path().to_path_buf(),
-            favorites_file,
-            favorites: HashMap::new(),
-        };
+        let storage = Storage::open(temp_dir.path()).unwrap();
 
-        assert!(!storage.is_favorited("nonexistent-session"));
+        assert!(!storage.is_favorited("nonexistent-session").unwrap());
     }
 
     #[test]
     fn test_favorites_sorted_by_timestamp_descending() {
         let temp_dir = TempDir::new().unwrap();
-        let favorites_file = temp_dir.path().join("sessions.toml");
+        let mut storage = Storage::open(temp_dir.path()).unwrap();
 
-        let mut storage = Storage {
-            data_dir: temp_dir.path().to_path_buf(),
-            favorites_file: favorites_file.clone(),
-            favorites: HashMap::new(),
-        };
-
-        // Use different timestamps directly by modifying the HashMap
-        // This avoids timing issues with add_favorite
         use std::thread;
         use std::time::Duration;
 
@@ -466,7 +1266,7 @@ mod tests {
         thread::sleep(Duration::from_millis(10));
         storage.add_favorite("newest").unwrap();
 
-        let favorites = storage.list_favorites();
+        let favorites = storage.list_favorites().unwrap();
         assert_eq!(favorites.len(), 3);
 
         // Verify descending order by checking each favorite is >= next
@@ -478,15 +1278,9 @@ mod tests {
     #[test]
     fn test_list_favorites_empty() {
         let temp_dir = TempDir::new().unwrap();
-        let favorites_file = temp_dir.path().join("sessions.toml");
-
-        let storage = Storage {
-            data_dir: temp_dir.path().to_path_buf(),
-            favorites_file,
-            favorites: HashMap::new(),
-        };
+        let storage = Storage::open(temp_dir.path()).unwrap();
 
-        let favorites = storage.list_favorites();
+        let favorites = storage.list_favorites().unwrap();
         assert!(favorites.is_empty());
     }
 
@@ -494,18 +1288,12 @@ mod tests {
     fn test_add_favorite_with_special_chars_in_id() {
         // Session IDs with special characters should be allowed
         let temp_dir = TempDir::new().unwrap();
-        let favorites_file = temp_dir.path().join("sessions.toml");
-
-        let mut storage = Storage {
-            data_dir: temp_dir.path().to_path_buf(),
-            favorites_file,
-            favorites: HashMap::new(),
-        };
+        let mut storage = Storage::open(temp_dir.path()).unwrap();
 
         let special_id = "abc123-def456_789.012";
         let result = storage.add_favorite(special_id);
         assert!(result.is_ok());
-        assert!(storage.is_favorited(special_id));
+        assert!(storage.is_favorited(special_id).unwrap());
     }
 
     // === Performance Tests ===
@@ -514,13 +1302,7 @@ mod tests {
     fn test_favorite_operation_performance() {
         // SC-003: Favorite operation should complete in < 1 second
         let temp_dir = TempDir::new().unwrap();
-        let favorites_file = temp_dir.path().join("sessions.toml");
-
-        let mut storage = Storage {
-            data_dir: temp_dir.path().to_path_buf(),
-            favorites_file,
-            favorites: HashMap::new(),
-        };
+        let mut storage = Storage::open(temp_dir.path()).unwrap();
 
         // Add favorite should complete in < 1 second
         let start = std::time::Instant::now();
@@ -535,7 +1317,7 @@ mod tests {
 
         // Check is_favorited should be fast
         let start = std::time::Instant::now();
-        let is_fav = storage.is_favorited("perf-test-session");
+        let is_fav = storage.is_favorited("perf-test-session").unwrap();
         let check_time = start.elapsed();
 
         assert!(is_fav);
@@ -561,13 +1343,7 @@ mod tests {
     fn test_multiple_favorites_performance() {
         // Test adding multiple favorites efficiently
         let temp_dir = TempDir::new().unwrap();
-        let favorites_file = temp_dir.path().join("sessions.toml");
-
-        let mut storage = Storage {
-            data_dir: temp_dir.path().to_path_buf(),
-            favorites_file,
-            favorites: HashMap::new(),
-        };
+        let mut storage = Storage::open(temp_dir.path()).unwrap();
 
         // Add 100 favorites
         let start = std::time::Instant::now();
@@ -584,6 +1360,6 @@ mod tests {
         );
 
         // Verify all are stored
-        assert_eq!(storage.list_favorites().len(), 100);
+        assert_eq!(storage.list_favorites().unwrap().len(), 100);
     }
 }
@@ -0,0 +1,495 @@
+//! Template module for user-defined export layouts
+//!
+//! Templates use the Jinja syntax of [minijinja](https://docs.rs/minijinja)
+//! and are looked up by name, first in `~/.claude-memo/templates/` and then
+//! among the templates built into the binary. A name matches a file either
+//! exactly (`summary.md`) or by its stem (`summary`). Files ending in
+//! `.html`, `.htm` or `.xml` escape their values as HTML.
+//!
+//! Templates cannot read files or run commands; they only see the context.
+//!
+//! # Context
+//!
+//! - `session`: `id`, `project`, `start`, `end` (ISO-8601), `start_ms`,
+//!   `end_ms` (milliseconds), `start_display`, `end_display`, `duration`,
+//!   `duration_ms` and `prompt_count`
+//! - `records`: prompts oldest first, each with `display`, `project`,
+//!   `timestamp` (ISO-8601), `timestamp_ms`, `time_display`, `offset` and
+//!   `offset_ms` (time since the start of the session), and `pasted`: the
+//!   pasted contents, each with `id`, `kind` (`text` or `image`), `label`,
+//!   `content` and `media_type`, and `replies`: the answers and tool calls
+//!   after the prompt when a transcript is attached, each with `kind`
+//!   (`answer`, `tool_use` or `tool_result`), `text`, `tool` and `is_error`
+//! - `mark`: `favorited_at`, `title` and `note` of the mark, or none when the
+//!   session is not marked
+//! - `tags`: tags of the session, sorted
+//! - `exported_at`: time of the export (ISO-8601)
+//!
+//! # Filters
+//!
+//! Besides the minijinja built-ins: `markdown_escape`, `inline_code`,
+//! `fenced` (a fenced code block that survives backticks in the text) and
+//! `first_line`.
+
+use crate::exporter::{display_time, escape_markdown, fenced, inline_code, iso_time, MarkInfo};
+use crate::session::{format_duration, Session};
+use crate::timefmt::TimeFormat;
+use crate::transcript::{tool_input_summary, Message, Transcript};
+use chrono::{DateTime, Utc};
+use minijinja::{Environment, UndefinedBehavior};
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Templates shipped in the binary, by file name
+const BUILTIN_TEMPLATES: &[(&str, &str)] = &[
+    ("summary.md", include_str!("templates/summary.md")),
+    ("transcript.txt", include_str!("templates/transcript.txt")),
+];
+
+/// Data a template is rendered with
+#[derive(Debug, Clone, Serialize)]
+pub struct ExportContext {
+    /// The exported session
+    pub session: SessionContext,
+    /// Prompts of the session, oldest first
+    pub records: Vec<RecordContext>,
+    /// Mark metadata, if the session is marked
+    pub mark: Option<MarkContext>,
+    /// Tags of the session, sorted
+    pub tags: Vec<String>,
+    /// Time of the export (ISO-8601)
+    pub exported_at: String,
+}
+
+/// Session fields of the template context
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionContext {
+    /// Session ID
+    pub id: String,
+    /// Project path from the most recent prompt
+    pub project: String,
+    /// Time of the first prompt (ISO-8601)
+    pub start: String,
+    /// Time of the first prompt (milliseconds)
+    pub start_ms: i64,
+    /// Time of the first prompt, e.g. `2025-12-24 09:13 UTC`
+    pub start_display: String,
+    /// Time of the last prompt (ISO-8601)
+    pub end: String,
+    /// Time of the last prompt (milliseconds)
+    pub end_ms: i64,
+    /// Time of the last prompt, e.g. `2025-12-24 09:25 UTC`
+    pub end_display: String,
+    /// Duration, e.g. `12m 3s`
+    pub duration: String,
+    /// Duration (milliseconds)
+    pub duration_ms: i64,
+    /// Number of prompts
+    pub prompt_count: usize,
+}
+
+/// Prompt fields of the template context
+#[derive(Debug, Clone, Serialize)]
+pub struct RecordContext {
+    /// Prompt text
+    pub display: String,
+    /// Project path the prompt was sent from
+    pub project: String,
+    /// Time of the prompt (ISO-8601)
+    pub timestamp: String,
+    /// Time of the prompt (milliseconds)
+    pub timestamp_ms: i64,
+    /// Time of the prompt, e.g. `2025-12-24 09:13 UTC`
+    pub time_display: String,
+    /// Time since the start of the session, e.g. `12m 3s`
+    pub offset: String,
+    /// Time since the start of the session (milliseconds)
+    pub offset_ms: i64,
+    /// Contents pasted into the prompt
+    pub pasted: Vec<PastedContext>,
+    /// Answers and tool calls after the prompt, from the transcript
+    pub replies: Vec<ReplyContext>,
+}
+
+/// Pasted content fields of the template context
+#[derive(Debug, Clone, Serialize)]
+pub struct PastedContext {
+    /// Number of the paste within the prompt
+    pub id: u64,
+    /// `text`, `image` or another kind
+    pub kind: String,
+    /// Description, e.g. `Pasted text #1 (42 lines)`
+    pub label: String,
+    /// Pasted text, empty for images
+    pub content: String,
+    /// Media type of an image
+    pub media_type: Option<String>,
+}
+
+/// Reply fields of the template context
+#[derive(Debug, Clone, Serialize)]
+pub struct ReplyContext {
+    /// `answer`, `tool_use` or `tool_result`
+    pub kind: String,
+    /// Answer text, summary of the tool input, or tool output
+    pub text: String,
+    /// Name of the tool, for tool calls and their results
+    pub tool: Option<String>,
+    /// Whether a tool result is an error
+    pub is_error: bool,
+}
+
+impl ReplyContext {
+    fn new(message: &Message, transcript: Option<&Transcript>) -> Option<Self> {
+        let (kind, text, tool, is_error) = match message {
+            Message::AssistantText { text, .. } => ("answer", text.clone(), None, false),
+            Message::ToolUse { name, input, .. } => (
+                "tool_use",
+                tool_input_summary(input),
+                Some(name.clone()),
+                false,
+            ),
+            Message::ToolResult {
+                tool_use_id,
+                content,
+                is_error,
+                ..
+            } => (
+                "tool_result",
+                content.clone(),
+                transcript
+                    .and_then(|t| t.tool_name(tool_use_id))
+                    .map(str::to_string),
+                *is_error,
+            ),
+            Message::User { .. } | Message::Summary { .. } => return None,
+        };
+        Some(Self {
+            kind: kind.to_string(),
+            text,
+            tool,
+            is_error,
+        })
+    }
+}
+
+/// Mark fields of the template context
+#[derive(Debug, Clone, Serialize)]
+pub struct MarkContext {
+    /// When the session was marked (ISO-8601)
+    pub favorited_at: String,
+    /// Title of the mark
+    pub title: Option<String>,
+    /// Note of the mark
+    pub note: Option<String>,
+}
+
+impl ExportContext {
+    /// Build the context of a session export, with times shown in the given format
+    pub fn new(
+        session: &Session,
+        mark: Option<&MarkInfo>,
+        exported_at: DateTime<Utc>,
+        time: &TimeFormat,
+    ) -> Self {
+        Self {
+            session: SessionContext {
+                id: session.session_id.clone(),
+                project: session.project().to_string(),
+                start: iso_time(time, session.start()),
+                start_ms: session.start(),
+                start_display: display_time(time, session.start()),
+                end: iso_time(time, session.end()),
+                end_ms: session.end(),
+                end_display: display_time(time, session.end()),
+                duration: format_duration(session.duration()),
+                duration_ms: session.duration(),
+                prompt_count: session.prompt_count(),
+            },
+            records: session
+                .timeline()
+                .enumerate()
+                .map(|(i, (offset, record))| RecordContext {
+                    display: record.display.clone(),
+                    project: record.project.clone(),
+                    timestamp: iso_time(time, record.timestamp),
+                    timestamp_ms: record.timestamp,
+                    time_display: display_time(time, record.timestamp),
+                    offset: format_duration(offset),
+                    offset_ms: offset,
+                    pasted: record
+                        .pasted
+                        .iter()
+                        .map(|p| PastedContext {
+                            id: p.id,
+                            kind: p.kind.as_str().to_string(),
+                            label: p.label(),
+                            content: p.content.clone(),
+                            media_type: p.media_type.clone(),
+                        })
+                        .collect(),
+                    replies: session
+                        .replies(i)
+                        .into_iter()
+                        .filter_map(|m| ReplyContext::new(m, session.transcript.as_ref()))
+                        .collect(),
+                })
+                .collect(),
+            mark: mark.map(|m| MarkContext {
+                favorited_at: iso_time(time, m.favorited_at),
+                title: m.title.clone(),
+                note: m.note.clone(),
+            }),
+            tags: mark.map(|m| m.tags.clone()).unwrap_or_default(),
+            exported_at: iso_time(time, exported_at.timestamp_millis()),
+        }
+    }
+}
+
+/// Export templates of a user, with the built-in templates as fallback
+#[derive(Debug, Clone)]
+pub struct Templates {
+    /// Directory of user templates
+    dir: PathBuf,
+}
+
+impl Templates {
+    /// Look up user templates in the given directory
+    ///
+    /// The directory does not have to exist.
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    /// Directory of user templates
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// File names of all templates, user templates first
+    ///
+    /// Built-in templates overridden by a user template are left out.
+    pub fn names(&self) -> Result<Vec<String>, crate::error::Error> {
+        let mut names = self.user_templates()?;
+        for (builtin, _) in BUILTIN_TEMPLATES {
+            if !names.iter().any(|name| name == builtin) {
+                names.push(builtin.to_string());
+            }
+        }
+        Ok(names)
+    }
+
+    /// Render a template by name
+    pub fn render(
+        &self,
+        name: &str,
+        context: &ExportContext,
+    ) -> Result<String, crate::error::Error> {
+        let (file_name, source) = self.source(name)?;
+
+        let mut env = environment();
+        env.add_template_owned(file_name.clone(), source)?;
+        Ok(env.get_template(&file_name)?.render(context)?)
+    }
+
+    /// Find the file name and source of a template
+    fn source(&self, name: &str) -> Result<(String, String), crate::error::Error> {
+        let user_templates = self.user_templates()?;
+        if let Some(file_name) = find_template(user_templates.iter().map(String::as_str), name) {
+            let source = fs::read_to_string(self.dir.join(file_name))?;
+            return Ok((file_name.to_string(), source));
+        }
+
+        let builtin = find_template(BUILTIN_TEMPLATES.iter().map(|(n, _)| *n), name);
+        if let Some((file_name, source)) =
+            BUILTIN_TEMPLATES.iter().find(|(n, _)| Some(*n) == builtin)
+        {
+            return Ok((file_name.to_string(), source.to_string()));
+        }
+
+        Err(crate::error::Error::TemplateNotFound {
+            name: name.to_string(),
+            available: self.names()?,
+        })
+    }
+
+    /// File names in the user template directory, sorted
+    fn user_templates(&self) -> Result<Vec<String>, crate::error::Error> {
+        if !self.dir.is_dir() {
+            return Ok(Vec::new());
+        }
+
+        let mut names = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            // Skip editor backups and temporary files
+            if name.starts_with('.') || name.ends_with('~') || !entry.file_type()?.is_file() {
+                continue;
+            }
+            names.push(name);
+        }
+        names.sort();
+        Ok(names)
+    }
+}
+
+/// Find a template by exact file name, or else by file stem
+///
+/// Of several files with the same stem, the first in order wins.
+fn find_template<'a>(
+    mut file_names: impl Iterator<Item = &'a str> + Clone,
+    name: &str,
+) -> Option<&'a str> {
+    file_names
+        .clone()
+        .find(|file_name| *file_name == name)
+        .or_else(|| {
+            file_names.find(|file_name| {
+                Path::new(file_name).file_stem().and_then(|s| s.to_str()) == Some(name)
+            })
+        })
+}
+
+/// Template environment with the export filters
+fn environment() -> Environment<'static> {
+    let mut env = Environment::new();
+    // Fail on typos such as `{{ sesion.id }}` instead of printing nothing
+    env.set_undefined_behavior(UndefinedBehavior::Strict);
+    env.set_trim_blocks(true);
+    env.set_lstrip_blocks(true);
+    env.set_keep_trailing_newline(true);
+    env.add_filter("markdown_escape", |text: String| escape_markdown(&text));
+    env.add_filter("inline_code", |text: String| inline_code(&text));
+    env.add_filter("fenced", |text: String| fenced(&text));
+    env.add_filter("first_line", |text: String| {
+        text.lines()
+            .find(|line| !line.trim().is_empty())
+            .unwrap_or("")
+            .to_string()
+    });
+    env
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::SessionRecord;
+    use crate::timefmt::Zone;
+    use chrono::TimeZone;
+    use tempfile::TempDir;
+
+    fn context(mark: Option<&MarkInfo>) -> ExportContext {
+        let session = Session::from_records(vec![
+            SessionRecord::new(
+                "/model".to_string(),
+                1766567616000,
+                "/Users/elliotxx/app".to_string(),
+                "session-001".to_string(),
+            ),
+            SessionRecord::new(
+                "fix the parser\nit panics on `<empty>` input".to_string(),
+                1766568339000,
+                "/Users/elliotxx/app".to_string(),
+                "session-001".to_string(),
+            ),
+        ])
+        .unwrap();
+        let exported_at = Utc.timestamp_millis_opt(1769682600000).unwrap();
+        ExportContext::new(
+            &session,
+            mark,
+            exported_at,
+            &TimeFormat::default().with_zone(Zone::Utc),
+        )
+    }
+
+    fn mark() -> MarkInfo {
+        MarkInfo {
+            favorited_at: 1769682600000,
+            tags: vec!["bug".to_string(), "rust".to_string()],
+            title: Some("Parser fix".to_string()),
+            note: Some("Root cause: empty input".to_string()),
+        }
+    }
+
+    #[test]
+    fn test_builtin_summary() {
+        let temp_dir = TempDir::new().unwrap();
+        let templates = Templates::new(temp_dir.path().join("templates"));
+
+        let output = templates
+            .render("summary", &context(Some(&mark())))
+            .unwrap();
+        assert_eq!(
+            output,
+            "## Parser fix\n\
+             \n\
+             - Session: `session-001`\n\
+             - Project: `/Users/elliotxx/app`\n\
+             - When: 2025-12-24 09:13 UTC (12m 3s, 2 prompts)\n\
+             - Tags: #bug #rust\n\
+             \n\
+             > Root cause: empty input\n\
+             \n\
+             1. `/model`\n\
+             2. `fix the parser`\n"
+        );
+    }
+
+    #[test]
+    fn test_builtin_transcript_without_mark() {
+        let templates = Templates::new(PathBuf::from("/nonexistent"));
+
+        let output = templates.render("transcript.txt", &context(None)).unwrap();
+        assert!(output.starts_with("Session:  session-001\nProject:  /Users/elliotxx/app\n"));
+        assert!(!output.contains("Tags:"));
+        assert!(output.ends_with(
+            "\n[+12m 3s] 2025-12-24 09:25 UTC\nfix the parser\nit panics on `<empty>` input\n"
+        ));
+    }
+
+    #[test]
+    fn test_user_template_overrides_builtin_and_escapes_html() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::write(
+            temp_dir.path().join("summary.html"),
+            "<h1>{{ session.id }}</h1>{% for r in records %}<p>{{ r.display }}</p>{% endfor %}",
+        )
+        .unwrap();
+        fs::write(temp_dir.path().join(".hidden"), "ignored").unwrap();
+        let templates = Templates::new(temp_dir.path().to_path_buf());
+
+        let output = templates.render("summary", &context(None)).unwrap();
+        assert!(output.contains("<p>fix the parser\nit panics on `&lt;empty&gt;` input</p>"));
+
+        // The built-in stays reachable by its full name
+        let builtin = templates.render("summary.md", &context(None)).unwrap();
+        assert!(builtin.starts_with("## /Users/elliotxx/app\n"));
+
+        assert_eq!(
+            templates.names().unwrap(),
+            ["summary.html", "summary.md", "transcript.txt"]
+        );
+    }
+
+    #[test]
+    fn test_template_errors() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::write(temp_dir.path().join("typo.md"), "{{ sesion.id }}").unwrap();
+        fs::write(temp_dir.path().join("broken.md"), "{% for x in records %}").unwrap();
+        let templates = Templates::new(temp_dir.path().to_path_buf());
+
+        let err = templates.render("missing", &context(None)).unwrap_err();
+        assert!(matches!(err, crate::error::Error::TemplateNotFound { .. }));
+        assert!(err
+            .to_string()
+            .contains("available: broken.md, typo.md, summary.md"));
+
+        for name in ["typo", "broken"] {
+            let err = templates.render(name, &context(None)).unwrap_err();
+            assert!(matches!(err, crate::error::Error::Template(_)), "{name}");
+        }
+    }
+}
@@ -0,0 +1,390 @@
+//! Time formatting module for timestamps in text and JSON output
+//!
+//! Text output shows timestamps with the `date_format` setting, a chrono
+//! strftime pattern such as `%Y-%m-%d %H:%M`, in the `timezone` setting,
+//! or relative to now ("3h ago") with `relative_time`. JSON output adds
+//! ISO-8601 times with the offset of that zone. Types whose text output
+//! contains timestamps implement [`FormatTime`]; their `Display` impls use
+//! the default format, absolute and in the local time zone.
+//!
+//! Invalid timestamps are shown as `invalid timestamp <millis>` in text
+//! and as `null` in JSON.
+
+use chrono::format::{Item, StrftimeItems};
+use chrono::{
+    DateTime, Duration, FixedOffset, Local, NaiveDateTime, Offset, SecondsFormat, TimeZone, Utc,
+};
+use chrono_tz::Tz;
+use std::fmt;
+use std::sync::OnceLock;
+
+/// Default pattern of timestamps in text output
+pub const DEFAULT_DATE_FORMAT: &str = "%Y-%m-%d %H:%M";
+
+/// Time zone in which timestamps are shown
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Zone {
+    /// The system time zone, following `$TZ` and the system setting
+    Local,
+    /// Coordinated Universal Time
+    Utc,
+    /// A zone of the IANA time zone database, e.g. `Europe/Berlin`
+    Named(Tz),
+}
+
+impl Zone {
+    /// Parse `local`, `UTC` or an IANA time zone name
+    pub fn parse(name: &str) -> Result<Self, String> {
+        let name = name.trim();
+        if name.eq_ignore_ascii_case("local") {
+            return Ok(Zone::Local);
+        }
+        if name.eq_ignore_ascii_case("utc") {
+            return Ok(Zone::Utc);
+        }
+        name.parse().map(Zone::Named).map_err(|_| {
+            format!("unknown time zone '{name}' (expected local, UTC or an IANA name like Asia/Shanghai)")
+        })
+    }
+
+    /// Name of the zone as accepted by [`Zone::parse`]
+    pub fn name(&self) -> &str {
+        match self {
+            Zone::Local => "local",
+            Zone::Utc => "UTC",
+            Zone::Named(tz) => tz.name(),
+        }
+    }
+
+    /// Offset from UTC at an instant
+    pub fn offset(&self, time: &DateTime<Utc>) -> FixedOffset {
+        let time = time.naive_utc();
+        match self {
+            Zone::Local => match local_zone() {
+                Some(tz) => tz.offset_from_utc_datetime(&time).fix(),
+                None => Local.offset_from_utc_datetime(&time).fix(),
+            },
+            Zone::Utc => Utc.fix(),
+            Zone::Named(tz) => tz.offset_from_utc_datetime(&time).fix(),
+        }
+    }
+
+    /// An instant in this zone
+    pub fn convert(&self, time: &DateTime<Utc>) -> DateTime<FixedOffset> {
+        time.with_timezone(&self.offset(time))
+    }
+
+    /// The instant a wall-clock time in this zone refers to
+    ///
+    /// Times repeated by a DST transition resolve to the earlier instant,
+    /// times skipped by one are read with the offset from before it.
+    pub fn resolve(&self, local: &NaiveDateTime) -> DateTime<Utc> {
+        fn earliest<T: TimeZone>(tz: &T, local: &NaiveDateTime) -> Option<DateTime<Utc>> {
+            tz.from_local_datetime(local)
+                .earliest()
+                .map(|time| time.with_timezone(&Utc))
+        }
+
+        let resolved = match self {
+            Zone::Local => match local_zone() {
+                Some(tz) => earliest(&tz, local),
+                None => earliest(&Local, local),
+            },
+            Zone::Utc => Some(local.and_utc()),
+            Zone::Named(tz) => earliest(tz, local),
+        };
+        resolved.unwrap_or_else(|| {
+            let before = self.offset(&(local.and_utc() - Duration::days(1)));
+            local.and_utc() - Duration::seconds(before.local_minus_utc().into())
+        })
+    }
+}
+
+/// The system time zone: the zone named by `$TZ`, or else the one the
+/// system is set to
+///
+/// `None` if neither names a known zone, e.g. for a POSIX `$TZ` rule, in
+/// which case chrono's [`Local`] applies.
+fn local_zone() -> Option<Tz> {
+    static LOCAL: OnceLock<Option<Tz>> = OnceLock::new();
+    *LOCAL.get_or_init(|| {
+        let name = match std::env::var("TZ") {
+            Ok(name) => name.trim_start_matches(':').to_string(),
+            Err(_) => iana_time_zone::get_timezone().ok()?,
+        };
+        name.parse().ok()
+    })
+}
+
+/// How timestamps are shown in text output
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimeFormat {
+    /// strftime pattern
+    pattern: String,
+    /// Zone the timestamps are shown in
+    zone: Zone,
+    /// Show recent timestamps relative to now
+    relative: bool,
+}
+
+impl TimeFormat {
+    /// Use a strftime pattern, rejecting unknown specifiers
+    pub fn new(pattern: &str) -> Result<Self, String> {
+        if StrftimeItems::new(pattern).any(|item| item == Item::Error) {
+            return Err(format!("'{pattern}' is not a valid strftime format"));
+        }
+        Ok(Self {
+            pattern: pattern.to_string(),
+            ..Self::default()
+        })
+    }
+
+    /// Show timestamps in a time zone
+    pub fn with_zone(mut self, zone: Zone) -> Self {
+        self.zone = zone;
+        self
+    }
+
+    /// Show timestamps of the last week relative to now, e.g. `3h ago`
+    pub fn with_relative(mut self, relative: bool) -> Self {
+        self.relative = relative;
+        self
+    }
+
+    /// The strftime pattern
+    pub fn pattern(&self) -> &str {
+        &self.pattern
+    }
+
+    /// The time zone
+    pub fn zone(&self) -> &Zone {
+        &self.zone
+    }
+
+    /// Format a timestamp in milliseconds
+    pub fn format(&self, millis: i64) -> String {
+        self.format_at(millis, Utc::now())
+    }
+
+    /// Format a timestamp in milliseconds, relative to `now` if enabled
+    pub fn format_at(&self, millis: i64, now: DateTime<Utc>) -> String {
+        let Some(time) = DateTime::from_timestamp_millis(millis) else {
+            return format!("invalid timestamp {millis}");
+        };
+        if self.relative {
+            if let Some(relative) = self.relative_to(time, now) {
+                return relative;
+            }
+        }
+        self.zone.convert(&time).format(&self.pattern).to_string()
+    }
+
+    /// Format a timestamp followed by its zone, e.g. `2025-12-24 09:13 UTC`
+    /// or `2025-12-24 17:13 +08:00`, for documents read elsewhere
+    ///
+    /// Always absolute, as such documents are read later.
+    pub fn format_with_zone(&self, millis: i64) -> String {
+        let Some(time) = DateTime::from_timestamp_millis(millis) else {
+            return format!("invalid timestamp {millis}");
+        };
+        let local = self.zone.convert(&time);
+        let label = match local.offset().local_minus_utc() {
+            0 => "UTC".to_string(),
+            _ => local.format("%:z").to_string(),
+        };
+        format!("{} {label}", local.format(&self.pattern))
+    }
+
+    /// ISO-8601 time with offset of a timestamp in milliseconds, e.g.
+    /// `2025-12-24T17:13:36.338+08:00`
+    pub fn iso(&self, millis: i64) -> Option<String> {
+        let time = DateTime::from_timestamp_millis(millis)?;
+        Some(
+            self.zone
+                .convert(&time)
+                .to_rfc3339_opts(SecondsFormat::AutoSi, false),
+        )
+    }
+
+    /// Describe a time of the last week relative to now
+    fn relative_to(&self, time: DateTime<Utc>, now: DateTime<Utc>) -> Option<String> {
+        let elapsed = now - time;
+        // Allow for clocks a little out of step
+        if elapsed < -Duration::minutes(1) {
+            return None;
+        }
+        if elapsed < Duration::minutes(1) {
+            return Some("just now".to_string());
+        }
+        if elapsed < Duration::hours(1) {
+            return Some(format!("{}m ago", elapsed.num_minutes()));
+        }
+        let days = (self.zone.convert(&now).date_naive() - self.zone.convert(&time).date_naive())
+            .num_days();
+        match days {
+            0 => Some(format!("{}h ago", elapsed.num_hours())),
+            1 => Some("yesterday".to_string()),
+            2..=6 => Some(format!("{days}d ago")),
+            _ => None,
+        }
+    }
+}
+
+impl Default for TimeFormat {
+    fn default() -> Self {
+        Self {
+            pattern: DEFAULT_DATE_FORMAT.to_string(),
+            zone: Zone::Local,
+            relative: false,
+        }
+    }
+}
+
+/// Values whose text output contains timestamps
+pub trait FormatTime {
+    /// Write the text output with timestamps in the given format
+    fn fmt_time(&self, f: &mut fmt::Formatter<'_>, format: &TimeFormat) -> fmt::Result;
+
+    /// Display the value with timestamps in the given format
+    fn with_time_format<'a>(&'a self, format: &'a TimeFormat) -> WithTimeFormat<'a, Self>
+    where
+        Self: Sized,
+    {
+        WithTimeFormat {
+            value: self,
+            format,
+        }
+    }
+}
+
+/// A value displayed with a time format, see [`FormatTime::with_time_format`]
+pub struct WithTimeFormat<'a, T> {
+    value: &'a T,
+    format: &'a TimeFormat,
+}
+
+impl<T: FormatTime> fmt::Display for WithTimeFormat<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.value.fmt_time(f, self.format)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The default format in UTC, independent of the host's zone
+    fn utc() -> TimeFormat {
+        TimeFormat::default().with_zone(Zone::Utc)
+    }
+
+    #[test]
+    fn test_time_format() {
+        assert_eq!(utc().format(1766567616000), "2025-12-24 09:13");
+        let format = TimeFormat::new("%d/%m/%Y").unwrap().with_zone(Zone::Utc);
+        assert_eq!(format.format(1766567616000), "24/12/2025");
+        assert_eq!(format.pattern(), "%d/%m/%Y");
+        assert!(TimeFormat::new("%Q").is_err());
+    }
+
+    #[test]
+    fn test_invalid_timestamps_are_shown() {
+        assert_eq!(
+            TimeFormat::default().format(i64::MAX),
+            format!("invalid timestamp {}", i64::MAX)
+        );
+        assert_eq!(TimeFormat::default().iso(i64::MAX), None);
+    }
+
+    #[test]
+    fn test_iso_time_has_offset() {
+        let format = utc();
+        assert_eq!(
+            format.iso(1766567616338).unwrap(),
+            "2025-12-24T09:13:36.338+00:00"
+        );
+        assert_eq!(
+            format.iso(1766567616000).unwrap(),
+            "2025-12-24T09:13:36+00:00"
+        );
+        assert_eq!(
+            format.format_with_zone(1766567616000),
+            "2025-12-24 09:13 UTC"
+        );
+    }
+
+    #[test]
+    fn test_relative_time() {
+        let format = utc().with_relative(true);
+        let now = DateTime::from_timestamp_millis(1766567616000).unwrap(); // 2025-12-24 09:13
+        let ago = |duration: Duration| (now - duration).timestamp_millis();
+
+        assert_eq!(
+            format.format_at(ago(Duration::seconds(20)), now),
+            "just now"
+        );
+        assert_eq!(format.format_at(ago(Duration::minutes(5)), now), "5m ago");
+        assert_eq!(format.format_at(ago(Duration::hours(3)), now), "3h ago");
+        // 2025-12-23 20:13 is yesterday, although less than a day ago
+        assert_eq!(format.format_at(ago(Duration::hours(13)), now), "yesterday");
+        assert_eq!(format.format_at(ago(Duration::days(3)), now), "3d ago");
+        assert_eq!(
+            format.format_at(ago(Duration::days(30)), now),
+            "2025-11-24 09:13"
+        );
+        assert_eq!(
+            format.format_at(ago(-Duration::hours(2)), now),
+            "2025-12-24 11:13"
+        );
+    }
+
+    #[test]
+    fn test_parse_zone() {
+        assert_eq!(Zone::parse("local").unwrap(), Zone::Local);
+        assert_eq!(Zone::parse("UTC").unwrap(), Zone::Utc);
+        assert_eq!(Zone::parse("utc").unwrap().name(), "UTC");
+        assert_eq!(
+            Zone::parse("Asia/Shanghai").unwrap().name(),
+            "Asia/Shanghai"
+        );
+        assert!(Zone::parse("../../etc/passwd").is_err());
+        assert!(Zone::parse("Mars/Olympus_Mons")
+            .unwrap_err()
+            .starts_with("unknown time zone 'Mars/Olympus_Mons'"));
+    }
+
+    #[test]
+    fn test_named_zone_follows_summer_time() {
+        let berlin = Zone::parse("Europe/Berlin").unwrap();
+        let offset = |y, m, d, h| {
+            let time = Utc.with_ymd_and_hms(y, m, d, h, 0, 0).unwrap();
+            berlin.offset(&time).local_minus_utc() / 3600
+        };
+        assert_eq!(offset(2026, 1, 15, 12), 1);
+        assert_eq!(offset(2026, 7, 15, 12), 2);
+        // Summer time starts at 2026-03-29 01:00 UTC
+        assert_eq!(offset(2026, 3, 29, 0), 1);
+        assert_eq!(offset(2026, 3, 29, 1), 2);
+
+        let format = TimeFormat::default().with_zone(berlin);
+        assert_eq!(format.format(1766567616000), "2025-12-24 10:13");
+        assert_eq!(
+            format.iso(1782292416000).unwrap(),
+            "2026-06-24T11:13:36+02:00"
+        );
+    }
+
+    #[test]
+    fn test_resolve_wall_clock_time() {
+        let berlin = Zone::parse("Europe/Berlin").unwrap();
+        let resolve = |value| {
+            let local = NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M").unwrap();
+            berlin.resolve(&local).to_rfc3339()
+        };
+        assert_eq!(resolve("2026-01-15 12:00"), "2026-01-15T11:00:00+00:00");
+        assert_eq!(resolve("2026-07-15 12:00"), "2026-07-15T10:00:00+00:00");
+        // Skipped and repeated by the summer time transitions
+        assert_eq!(resolve("2026-03-29 02:30"), "2026-03-29T01:30:00+00:00");
+        assert_eq!(resolve("2026-10-25 02:30"), "2026-10-25T00:30:00+00:00");
+    }
+}
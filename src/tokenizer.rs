@@ -0,0 +1,352 @@
+//! CJK bigram tokenizer for the FTS5 index
+//!
+//! SQLite's `unicode61` tokenizer treats a run of Han characters as one
+//! token, so `重构` never matches `帮我重构这个模块`. This tokenizer splits CJK
+//! runs into overlapping bigrams instead, and indexes every character as a
+//! colocated unigram so single-character queries match too. Other letters
+//! and digits form lowercased word tokens as usual.
+//!
+//! The tokenizer has to be registered on every connection that reads or
+//! writes a table using it, see [`register`].
+
+use rusqlite::{ffi, Connection};
+use std::os::raw::{c_char, c_int, c_void};
+use std::ptr;
+
+extern "C" {
+    // Available since SQLite 3.20, but missing from the minimal bindings
+    // libsqlite3-sys ships with
+    fn sqlite3_bind_pointer(
+        stmt: *mut ffi::sqlite3_stmt,
+        index: c_int,
+        ptr: *mut c_void,
+        type_name: *const c_char,
+        destructor: Option<unsafe extern "C" fn(*mut c_void)>,
+    ) -> c_int;
+}
+
+/// Register the tokenizer as `cjk_bigram` on a connection
+pub fn register(conn: &Connection) -> Result<(), crate::error::Error> {
+    let api = fts5_api(conn)?;
+
+    // FTS5 copies the callbacks, so the struct does not need to outlive the call
+    let mut tokenizer = ffi::fts5_tokenizer {
+        xCreate: Some(x_create),
+        xDelete: Some(x_delete),
+        xTokenize: Some(x_tokenize),
+    };
+
+    // SAFETY: `api` is the live fts5_api of this connection and the name is
+    // a NUL-terminated static string
+    let rc = unsafe {
+        match (*api).xCreateTokenizer {
+            Some(create) => create(
+                api,
+                c"cjk_bigram".as_ptr(),
+                ptr::null_mut(),
+                &mut tokenizer,
+                None,
+            ),
+            None => ffi::SQLITE_ERROR,
+        }
+    };
+    check(rc, "failed to register the CJK tokenizer")
+}
+
+/// Check whether text contains any CJK character
+pub fn contains_cjk(text: &str) -> bool {
+    text.chars().any(is_cjk)
+}
+
+/// Get the FTS5 extension API of a connection
+fn fts5_api(conn: &Connection) -> Result<*mut ffi::fts5_api, crate::error::Error> {
+    let mut api: *mut ffi::fts5_api = ptr::null_mut();
+
+    // SAFETY: the statement is prepared on the connection's own handle and
+    // finalized before returning; `api` outlives the statement
+    unsafe {
+        let db = conn.handle();
+        let mut stmt = ptr::null_mut();
+        let rc = ffi::sqlite3_prepare_v2(
+            db,
+            c"SELECT fts5(?1)".as_ptr(),
+            -1,
+            &mut stmt,
+            ptr::null_mut(),
+        );
+        check(rc, "FTS5 is not available")?;
+
+        sqlite3_bind_pointer(
+            stmt,
+            1,
+            &mut api as *mut *mut ffi::fts5_api as *mut c_void,
+            c"fts5_api_ptr".as_ptr(),
+            None,
+        );
+        ffi::sqlite3_step(stmt);
+        ffi::sqlite3_finalize(stmt);
+    }
+
+    if api.is_null() {
+        return Err(sqlite_error(ffi::SQLITE_ERROR, "FTS5 is not available"));
+    }
+    Ok(api)
+}
+
+fn check(rc: c_int, message: &str) -> Result<(), crate::error::Error> {
+    if rc == ffi::SQLITE_OK {
+        Ok(())
+    } else {
+        Err(sqlite_error(rc, message))
+    }
+}
+
+fn sqlite_error(rc: c_int, message: &str) -> crate::error::Error {
+    crate::error::Error::Database(rusqlite::Error::SqliteFailure(
+        ffi::Error::new(rc),
+        Some(message.to_string()),
+    ))
+}
+
+/// The tokenizer is stateless; FTS5 only needs a non-null handle
+struct CjkTokenizer;
+
+unsafe extern "C" fn x_create(
+    _context: *mut c_void,
+    _args: *mut *const c_char,
+    _n_args: c_int,
+    out: *mut *mut ffi::Fts5Tokenizer,
+) -> c_int {
+    *out = Box::into_raw(Box::new(CjkTokenizer)) as *mut ffi::Fts5Tokenizer;
+    ffi::SQLITE_OK
+}
+
+unsafe extern "C" fn x_delete(tokenizer: *mut ffi::Fts5Tokenizer) {
+    if !tokenizer.is_null() {
+        drop(Box::from_raw(tokenizer as *mut CjkTokenizer));
+    }
+}
+
+unsafe extern "C" fn x_tokenize(
+    _tokenizer: *mut ffi::Fts5Tokenizer,
+    ctx: *mut c_void,
+    flags: c_int,
+    text: *const c_char,
+    n_text: c_int,
+    x_token: Option<
+        unsafe extern "C" fn(*mut c_void, c_int, *const c_char, c_int, c_int, c_int) -> c_int,
+    >,
+) -> c_int {
+    let Some(x_token) = x_token else {
+        return ffi::SQLITE_ERROR;
+    };
+    if text.is_null() || n_text <= 0 {
+        return ffi::SQLITE_OK;
+    }
+
+    let bytes = std::slice::from_raw_parts(text as *const u8, n_text as usize);
+    // Offsets must point into the original bytes, so stop at invalid UTF-8
+    // instead of replacing it
+    let text = match std::str::from_utf8(bytes) {
+        Ok(text) => text,
+        Err(e) => std::str::from_utf8_unchecked(&bytes[..e.valid_up_to()]),
+    };
+
+    // Documents and auxiliary functions such as highlight() need the same
+    // positions, queries must not get colocated unigrams
+    let colocate = flags & ffi::FTS5_TOKENIZE_QUERY == 0;
+
+    for token in tokenize(text, colocate) {
+        let token_flags = if token.colocated {
+            ffi::FTS5_TOKEN_COLOCATED
+        } else {
+            0
+        };
+        let rc = x_token(
+            ctx,
+            token_flags,
+            token.text.as_ptr() as *const c_char,
+            token.text.len() as c_int,
+            token.start as c_int,
+            token.end as c_int,
+        );
+        if rc != ffi::SQLITE_OK {
+            return rc;
+        }
+    }
+
+    ffi::SQLITE_OK
+}
+
+/// A token with its byte range in the source text
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Token {
+    text: String,
+    start: usize,
+    end: usize,
+    /// Occupies the same position as the previous token
+    colocated: bool,
+}
+
+/// Split text into tokens
+///
+/// With `colocate`, each CJK character is also emitted as a unigram at the
+/// position of the bigram it starts, and the last character of a run gets a
+/// position of its own.
+fn tokenize(text: &str, colocate: bool) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut chars = text.char_indices().peekable();
+
+    while let Some(&(start, c)) = chars.peek() {
+        if is_cjk(c) {
+            let mut run = Vec::new();
+            while let Some(&(offset, c)) = chars.peek() {
+                if !is_cjk(c) {
+                    break;
+                }
+                run.push((offset, c));
+                chars.next();
+            }
+            push_cjk_run(&mut tokens, &run, colocate);
+        } else if c.is_alphanumeric() {
+            let mut word = String::new();
+            let mut end = start;
+            while let Some(&(offset, c)) = chars.peek() {
+                if !c.is_alphanumeric() || is_cjk(c) {
+                    break;
+                }
+                word.extend(c.to_lowercase());
+                end = offset + c.len_utf8();
+                chars.next();
+            }
+            tokens.push(Token {
+                text: word,
+                start,
+                end,
+                colocated: false,
+            });
+        } else {
+            chars.next();
+        }
+    }
+
+    tokens
+}
+
+fn push_cjk_run(tokens: &mut Vec<Token>, run: &[(usize, char)], colocate: bool) {
+    let unigram = |(offset, c): (usize, char), colocated| Token {
+        text: c.to_string(),
+        start: offset,
+        end: offset + c.len_utf8(),
+        colocated,
+    };
+
+    if run.len() == 1 {
+        tokens.push(unigram(run[0], false));
+        return;
+    }
+
+    for pair in run.windows(2) {
+        let (start, first) = pair[0];
+        let (second_start, second) = pair[1];
+        tokens.push(Token {
+            text: format!("{first}{second}"),
+            start,
+            end: second_start + second.len_utf8(),
+            colocated: false,
+        });
+        if colocate {
+            tokens.push(unigram(pair[0], true));
+        }
+    }
+    if colocate {
+        tokens.push(unigram(run[run.len() - 1], false));
+    }
+}
+
+/// Han, Kana and Hangul characters, which are written without spaces
+fn is_cjk(c: char) -> bool {
+    matches!(c,
+        '\u{3040}'..='\u{30FF}'     // Hiragana, Katakana
+        | '\u{31F0}'..='\u{31FF}'   // Katakana phonetic extensions
+        | '\u{3400}'..='\u{4DBF}'   // CJK Extension A
+        | '\u{4E00}'..='\u{9FFF}'   // CJK Unified Ideographs
+        | '\u{AC00}'..='\u{D7AF}'   // Hangul syllables
+        | '\u{F900}'..='\u{FAFF}'   // CJK Compatibility Ideographs
+        | '\u{20000}'..='\u{2FA1F}' // CJK Extension B and later
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts(tokens: &[Token]) -> Vec<&str> {
+        tokens.iter().map(|t| t.text.as_str()).collect()
+    }
+
+    #[test]
+    fn test_tokenize_cjk_query_into_bigrams() {
+        let tokens = tokenize("重构这个", false);
+        assert_eq!(texts(&tokens), vec!["重构", "构这", "这个"]);
+        assert_eq!((tokens[0].start, tokens[0].end), (0, 6));
+    }
+
+    #[test]
+    fn test_tokenize_cjk_document_with_unigrams() {
+        let tokens = tokenize("重构了", true);
+        assert_eq!(texts(&tokens), vec!["重构", "重", "构了", "构", "了"]);
+        let colocated: Vec<bool> = tokens.iter().map(|t| t.colocated).collect();
+        assert_eq!(colocated, vec![false, true, false, true, false]);
+    }
+
+    #[test]
+    fn test_tokenize_mixed_text() {
+        let tokens = tokenize("帮我重构 Parser模块!", false);
+        assert_eq!(
+            texts(&tokens),
+            vec!["帮我", "我重", "重构", "parser", "模块"]
+        );
+        let parser = &tokens[3];
+        assert_eq!(&"帮我重构 Parser模块!"[parser.start..parser.end], "Parser");
+    }
+
+    #[test]
+    fn test_tokenize_single_character_run() {
+        assert_eq!(texts(&tokenize("a 中 b", true)), vec!["a", "中", "b"]);
+    }
+
+    #[test]
+    fn test_contains_cjk() {
+        assert!(contains_cjk("重构 parser"));
+        assert!(contains_cjk("カタカナ"));
+        assert!(contains_cjk("한국어"));
+        assert!(!contains_cjk("refactor, ”quoted”"));
+        assert!(!contains_cjk("，。"));
+    }
+
+    #[test]
+    fn test_register_and_match() {
+        let conn = Connection::open_in_memory().unwrap();
+        register(&conn).unwrap();
+        conn.execute_batch(
+            "CREATE VIRTUAL TABLE t USING fts5(body, tokenize='cjk_bigram');
+             INSERT INTO t(body) VALUES ('帮我重构这个模块'), ('写单元测试'), ('refactor 模块化');",
+        )
+        .unwrap();
+
+        let count = |query: &str| -> i64 {
+            conn.query_row("SELECT COUNT(*) FROM t WHERE t MATCH ?1", [query], |row| {
+                row.get(0)
+            })
+            .unwrap()
+        };
+
+        assert_eq!(count("\"重构\""), 1);
+        assert_eq!(count("\"模块\""), 2);
+        assert_eq!(count("\"块\""), 2);
+        assert_eq!(count("\"重构这个\""), 1);
+        assert_eq!(count("\"构模\""), 0);
+        assert_eq!(count("\"REFACTOR\""), 1);
+    }
+}
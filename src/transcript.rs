@@ -0,0 +1,443 @@
+//! Transcript module for the full conversations of Claude Code sessions
+//!
+//! history.jsonl only holds the prompts. Claude Code writes the whole
+//! conversation of each session, including replies and tool activity, to
+//! `~/.claude/projects/<encoded-project>/<session-id>.jsonl`. Each line is
+//! an event; the ones carrying a user or assistant message are split into
+//! [`Message`]s, one per content block. Other events, thinking blocks and
+//! unknown fields are ignored so newer Claude Code versions keep working.
+
+use chrono::DateTime;
+use serde::Serialize;
+use serde_json::Value;
+use std::io::BufRead;
+use std::path::{Path, PathBuf};
+
+/// One message of a conversation
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Message {
+    /// Text typed by the user
+    User {
+        /// Time of the message (milliseconds)
+        timestamp: Option<i64>,
+        /// Message text
+        text: String,
+    },
+    /// Text answered by Claude
+    AssistantText {
+        /// Time of the message (milliseconds)
+        timestamp: Option<i64>,
+        /// Message text
+        text: String,
+    },
+    /// A tool call made by Claude
+    ToolUse {
+        /// Time of the call (milliseconds)
+        timestamp: Option<i64>,
+        /// ID that the result refers to
+        id: String,
+        /// Tool name, e.g. `Bash` or `Edit`
+        name: String,
+        /// Tool arguments
+        input: Value,
+    },
+    /// The output of a tool call
+    ToolResult {
+        /// Time of the result (milliseconds)
+        timestamp: Option<i64>,
+        /// ID of the tool call
+        tool_use_id: String,
+        /// Output text
+        content: String,
+        /// Whether the tool failed
+        is_error: bool,
+    },
+    /// Summary Claude Code wrote when the conversation was compacted
+    Summary {
+        /// Summary text
+        text: String,
+    },
+}
+
+impl Message {
+    /// Time of the message (milliseconds), if recorded
+    pub fn timestamp(&self) -> Option<i64> {
+        match self {
+            Message::User { timestamp, .. }
+            | Message::AssistantText { timestamp, .. }
+            | Message::ToolUse { timestamp, .. }
+            | Message::ToolResult { timestamp, .. } => *timestamp,
+            Message::Summary { .. } => None,
+        }
+    }
+}
+
+/// The conversation of one session
+#[derive(Debug, Clone, PartialEq)]
+pub struct Transcript {
+    /// Unique session identifier (UUID)
+    pub session_id: String,
+    /// Messages in file order
+    pub messages: Vec<Message>,
+}
+
+impl Transcript {
+    /// Name of the tool whose call has the given ID
+    pub fn tool_name(&self, tool_use_id: &str) -> Option<&str> {
+        self.messages.iter().find_map(|m| match m {
+            Message::ToolUse { id, name, .. } if id == tool_use_id => Some(name.as_str()),
+            _ => None,
+        })
+    }
+
+    /// Summaries of compacted parts of the conversation
+    pub fn summaries(&self) -> impl Iterator<Item = &str> {
+        self.messages.iter().filter_map(|m| match m {
+            Message::Summary { text } => Some(text.as_str()),
+            _ => None,
+        })
+    }
+
+    /// Messages sent from `start` (inclusive) until `end` (exclusive, or
+    /// the end of the conversation), in milliseconds
+    ///
+    /// Messages without a timestamp are left out.
+    pub fn between(&self, start: i64, end: Option<i64>) -> impl Iterator<Item = &Message> {
+        self.messages.iter().filter(move |m| {
+            m.timestamp()
+                .is_some_and(|t| t >= start && end.is_none_or(|end| t < end))
+        })
+    }
+}
+
+/// Keys of tool arguments that best describe a call, in order of preference
+const SUMMARY_KEYS: &[&str] = &[
+    "command",
+    "file_path",
+    "notebook_path",
+    "path",
+    "pattern",
+    "url",
+    "query",
+    "description",
+    "prompt",
+];
+
+/// The main argument of a tool call, e.g. the command run by `Bash` or the
+/// file read by `Read`; other calls are summarized by their JSON input
+pub fn tool_input_summary(input: &Value) -> String {
+    SUMMARY_KEYS
+        .iter()
+        .find_map(|key| input.get(key).and_then(Value::as_str))
+        .map(str::to_string)
+        .unwrap_or_else(|| input.to_string())
+}
+
+/// Parse one transcript line into its messages
+///
+/// Lines of other event types yield no messages.
+pub fn parse_transcript_line(line: &str) -> Result<Vec<Message>, crate::error::Error> {
+    let line = line.trim();
+    if line.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let event: Value = serde_json::from_str(line)?;
+    let timestamp = event
+        .get("timestamp")
+        .and_then(Value::as_str)
+        .and_then(|t| DateTime::parse_from_rfc3339(t).ok())
+        .map(|t| t.timestamp_millis());
+    let content = event.pointer("/message/content");
+
+    let mut messages = Vec::new();
+    match event.get("type").and_then(Value::as_str) {
+        Some("summary") => {
+            if let Some(text) = event.get("summary").and_then(Value::as_str) {
+                messages.push(Message::Summary {
+                    text: text.to_string(),
+                });
+            }
+        }
+        // Meta messages are injected by Claude Code, not typed by the user
+        Some("user") if event.get("isMeta").and_then(Value::as_bool) != Some(true) => {
+            for block in &content_blocks(content) {
+                match block.get("type").and_then(Value::as_str) {
+                    Some("text") => messages.push(Message::User {
+                        timestamp,
+                        text: text_of(block),
+                    }),
+                    Some("tool_result") => messages.push(Message::ToolResult {
+                        timestamp,
+                        tool_use_id: string_field(block, "tool_use_id"),
+                        content: result_text(block.get("content")),
+                        is_error: block
+                            .get("is_error")
+                            .and_then(Value::as_bool)
+                            .unwrap_or(false),
+                    }),
+                    _ => {}
+                }
+            }
+        }
+        Some("assistant") => {
+            for block in &content_blocks(content) {
+                match block.get("type").and_then(Value::as_str) {
+                    Some("text") => messages.push(Message::AssistantText {
+                        timestamp,
+                        text: text_of(block),
+                    }),
+                    Some("tool_use") => messages.push(Message::ToolUse {
+                        timestamp,
+                        id: string_field(block, "id"),
+                        name: string_field(block, "name"),
+                        input: block.get("input").cloned().unwrap_or(Value::Null),
+                    }),
+                    _ => {}
+                }
+            }
+        }
+        _ => {}
+    }
+    messages.retain(|m| match m {
+        Message::User { text, .. } | Message::AssistantText { text, .. } => !text.trim().is_empty(),
+        _ => true,
+    });
+    Ok(messages)
+}
+
+/// Content blocks of a message; plain string content is one text block
+fn content_blocks(content: Option<&Value>) -> Vec<Value> {
+    match content {
+        Some(Value::String(text)) => vec![serde_json::json!({ "type": "text", "text": text })],
+        Some(Value::Array(blocks)) => blocks.clone(),
+        _ => Vec::new(),
+    }
+}
+
+/// Text of a text block
+fn text_of(block: &Value) -> String {
+    string_field(block, "text")
+}
+
+/// A string field of a block, empty when missing
+fn string_field(block: &Value, field: &str) -> String {
+    block
+        .get(field)
+        .and_then(Value::as_str)
+        .unwrap_or_default()
+        .to_string()
+}
+
+/// Text of a tool result, which is a string or a list of blocks
+fn result_text(content: Option<&Value>) -> String {
+    match content {
+        Some(Value::String(text)) => text.clone(),
+        Some(Value::Array(blocks)) => blocks
+            .iter()
+            .map(|block| match block.get("type").and_then(Value::as_str) {
+                Some("text") => text_of(block),
+                Some(kind) => format!("[{kind}]"),
+                None => String::new(),
+            })
+            .collect::<Vec<_>>()
+            .join("\n"),
+        _ => String::new(),
+    }
+}
+
+/// Parse a transcript file
+///
+/// The session ID is taken from the file name. Lines that cannot be parsed
+/// are skipped.
+pub fn parse_transcript_file(path: &Path) -> Result<Transcript, crate::error::Error> {
+    let file = std::fs::File::open(path)
+        .map_err(|_| crate::error::Error::NotFound(path.to_string_lossy().to_string()))?;
+    let reader = std::io::BufReader::new(file);
+
+    let mut messages = Vec::new();
+    for line in reader.lines() {
+        // Skip invalid lines instead of failing (graceful handling)
+        if let Ok(parsed) = parse_transcript_line(&line?) {
+            messages.extend(parsed);
+        }
+    }
+
+    Ok(Transcript {
+        session_id: session_id_of(path),
+        messages,
+    })
+}
+
+/// Session ID of a transcript file
+fn session_id_of(path: &Path) -> String {
+    path.file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+/// Finds the transcript files in the Claude Code projects directory
+#[derive(Debug, Clone)]
+pub struct TranscriptStore {
+    /// Directory holding one subdirectory per project
+    projects_dir: PathBuf,
+}
+
+impl TranscriptStore {
+    /// Create a store reading transcripts from a projects directory
+    pub fn new(projects_dir: PathBuf) -> Self {
+        Self { projects_dir }
+    }
+
+    /// Paths of all transcripts, as (session ID, path)
+    ///
+    /// A missing projects directory has no transcripts.
+    pub fn list(&self) -> Result<Vec<(String, PathBuf)>, crate::error::Error> {
+        let mut transcripts = Vec::new();
+        let Ok(projects) = std::fs::read_dir(&self.projects_dir) else {
+            return Ok(transcripts);
+        };
+        for project in projects {
+            let project = project?.path();
+            if !project.is_dir() {
+                continue;
+            }
+            for entry in std::fs::read_dir(&project)? {
+                let path = entry?.path();
+                if path.is_file() && path.extension().is_some_and(|e| e == "jsonl") {
+                    transcripts.push((session_id_of(&path), path));
+                }
+            }
+        }
+        transcripts.sort();
+        Ok(transcripts)
+    }
+
+    /// Path of the transcript of a session, if there is one
+    pub fn find(&self, session_id: &str) -> Option<PathBuf> {
+        let file_name = format!("{session_id}.jsonl");
+        std::fs::read_dir(&self.projects_dir)
+            .ok()?
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path().join(&file_name))
+            .find(|path| path.is_file())
+    }
+
+    /// Load the transcript of a session, if there is one
+    pub fn load(&self, session_id: &str) -> Result<Option<Transcript>, crate::error::Error> {
+        self.find(session_id)
+            .map(|path| parse_transcript_file(&path))
+            .transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    const TRANSCRIPT: &str = r#"{"type":"summary","summary":"Fix the parser","leafUuid":"u0"}
+{"type":"user","message":{"role":"user","content":"fix the parser"},"timestamp":"2025-12-24T09:13:36.000Z","sessionId":"abc123","newField":{"nested":true}}
+{"type":"assistant","message":{"role":"assistant","content":[{"type":"thinking","thinking":"hmm"},{"type":"text","text":"Let me look."},{"type":"tool_use","id":"toolu_1","name":"Bash","input":{"command":"cargo test"}}]},"timestamp":"2025-12-24T09:13:40.000Z"}
+{"type":"user","message":{"role":"user","content":[{"type":"tool_result","tool_use_id":"toolu_1","content":[{"type":"text","text":"test result: FAILED"}],"is_error":true}]},"timestamp":"2025-12-24T09:13:50.000Z"}
+{"type":"user","isMeta":true,"message":{"role":"user","content":"<local-command-stdout>ok</local-command-stdout>"},"timestamp":"2025-12-24T09:13:51.000Z"}
+{"type":"attachment","attachment":{"type":"file"},"timestamp":"2025-12-24T09:13:52.000Z"}
+not json
+"#;
+
+    #[test]
+    fn test_parse_transcript_messages() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("abc123.jsonl");
+        std::fs::write(&path, TRANSCRIPT).unwrap();
+
+        let transcript = parse_transcript_file(&path).unwrap();
+        assert_eq!(transcript.session_id, "abc123");
+        assert_eq!(
+            transcript.messages,
+            vec![
+                Message::Summary {
+                    text: "Fix the parser".to_string()
+                },
+                Message::User {
+                    timestamp: Some(1766567616000),
+                    text: "fix the parser".to_string()
+                },
+                Message::AssistantText {
+                    timestamp: Some(1766567620000),
+                    text: "Let me look.".to_string()
+                },
+                Message::ToolUse {
+                    timestamp: Some(1766567620000),
+                    id: "toolu_1".to_string(),
+                    name: "Bash".to_string(),
+                    input: serde_json::json!({ "command": "cargo test" }),
+                },
+                Message::ToolResult {
+                    timestamp: Some(1766567630000),
+                    tool_use_id: "toolu_1".to_string(),
+                    content: "test result: FAILED".to_string(),
+                    is_error: true,
+                },
+            ]
+        );
+        assert_eq!(transcript.tool_name("toolu_1"), Some("Bash"));
+        assert_eq!(
+            transcript.summaries().collect::<Vec<_>>(),
+            vec!["Fix the parser"]
+        );
+        assert_eq!(
+            transcript
+                .between(1766567620000, Some(1766567630000))
+                .count(),
+            2
+        );
+    }
+
+    #[test]
+    fn test_message_serializes_with_type_tag() {
+        let message = Message::ToolUse {
+            timestamp: None,
+            id: "toolu_1".to_string(),
+            name: "Read".to_string(),
+            input: serde_json::json!({ "file_path": "src/main.rs" }),
+        };
+        assert_eq!(
+            serde_json::to_value(&message).unwrap(),
+            serde_json::json!({
+                "type": "tool_use",
+                "timestamp": null,
+                "id": "toolu_1",
+                "name": "Read",
+                "input": { "file_path": "src/main.rs" }
+            })
+        );
+    }
+
+    #[test]
+    fn test_tool_input_summary() {
+        let input = serde_json::json!({ "description": "Run tests", "command": "cargo test" });
+        assert_eq!(tool_input_summary(&input), "cargo test");
+        let input = serde_json::json!({ "todos": [] });
+        assert_eq!(tool_input_summary(&input), r#"{"todos":[]}"#);
+    }
+
+    #[test]
+    fn test_store_finds_transcripts() {
+        let temp_dir = TempDir::new().unwrap();
+        let project = temp_dir.path().join("-Users-elliotxx-app");
+        std::fs::create_dir_all(project.join("abc123")).unwrap();
+        std::fs::write(project.join("abc123.jsonl"), TRANSCRIPT).unwrap();
+        std::fs::write(project.join("notes.txt"), "").unwrap();
+
+        let store = TranscriptStore::new(temp_dir.path().to_path_buf());
+        assert_eq!(store.find("abc123"), Some(project.join("abc123.jsonl")));
+        assert!(store.find("missing").is_none());
+        assert_eq!(store.list().unwrap().len(), 1);
+        assert_eq!(store.load("abc123").unwrap().unwrap().messages.len(), 5);
+
+        let missing = TranscriptStore::new(temp_dir.path().join("missing"));
+        assert!(missing.list().unwrap().is_empty());
+    }
+}